@@ -0,0 +1,19 @@
+//! The package header and index, then every resource they point at.
+#![no_main]
+
+use std::io::{Cursor, Read};
+
+use libfuzzer_sys::fuzz_target;
+use sims3_rs::dbpf::DBPFReader;
+
+fuzz_target!(|data: &[u8]| {
+    generativity::make_guard!(guard);
+    let Ok((mut reader, package)) = DBPFReader::parse(Cursor::new(data), guard) else {
+        return;
+    };
+    for entry in &package.entries {
+        if let Ok(mut chunk) = entry.get_reader(&mut reader) {
+            let _ = chunk.read_to_end(&mut Vec::new());
+        }
+    }
+});
@@ -0,0 +1,14 @@
+//! NMAP (name map) resources.
+#![no_main]
+
+use std::io::Cursor;
+
+use binrw::BinRead;
+use libfuzzer_sys::fuzz_target;
+use sims3_rs::dbpf::filetypes::nmap::NMAP;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(nmap) = NMAP::read_le(&mut Cursor::new(data)) {
+        for _ in nmap.names() {}
+    }
+});
@@ -0,0 +1,19 @@
+//! RCOL containers, and the GEOM inside them if there is one.
+#![no_main]
+
+use std::io::Cursor;
+
+use binrw::BinRead;
+use libfuzzer_sys::fuzz_target;
+use sims3_rs::dbpf::filetypes::geom::Geometry;
+use sims3_rs::dbpf::filetypes::rcol::RCOL;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(rcol) = RCOL::read_le(&mut Cursor::new(data)) else {
+        return;
+    };
+    if let Ok(geom) = Geometry::from_rcol(&rcol) {
+        let _ = geom.triangle_count();
+        let _ = geom.to_bytes();
+    }
+});
@@ -0,0 +1,38 @@
+//! Compressed resources. The input is the index's uncompressed size (4 bytes), then the compressed data,
+//! which gets wrapped in a package with a single entry.
+#![no_main]
+
+use std::io::{Cursor, Read};
+
+use libfuzzer_sys::fuzz_target;
+use sims3_rs::dbpf::DBPFReader;
+
+const INDEX_SIZE: u32 = 4 + 8 * 4;
+
+fn package(memsize: u32, compressed: &[u8]) -> Vec<u8> {
+    let words = |out: &mut Vec<u8>, words: &[u32]| out.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+    let mut out = b"DBPF".to_vec();
+    // version 2.0, user version, flags, timestamps, index version 7, one entry, old index position,
+    // index size, an empty hole index, index minor version 3, index position
+    words(&mut out, &[2, 0, 0, 0, 0, 0, 0, 7, 1, 0, INDEX_SIZE, 0, 0, 0, 3, 96]);
+    out.resize(96, 0);
+    // no common fields, then type, group, instance, offset, filesize, memsize, and compressed/unk2
+    words(&mut out, &[0, 0x0333406C, 0, 0, 1, 96 + INDEX_SIZE, compressed.len() as u32, memsize, 0x0001FFFF]);
+    out.extend_from_slice(compressed);
+    out
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((memsize, compressed)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    if compressed.len() >= 1 << 31 {
+        return;
+    }
+    let data = package(u32::from_le_bytes(*memsize), compressed);
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(Cursor::new(data), guard).expect("test package is valid");
+    if let Ok(mut chunk) = package.entries[0].get_reader(&mut reader) {
+        let _ = chunk.read_to_end(&mut Vec::new());
+    }
+});
@@ -0,0 +1,5 @@
+//! Same as `sims3 pack`.
+
+fn main() -> std::process::ExitCode {
+    sims3_rs::cli::run_alias("pack")
+}
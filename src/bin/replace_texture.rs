@@ -0,0 +1,5 @@
+//! Same as `sims3 replace-texture`.
+
+fn main() -> std::process::ExitCode {
+    sims3_rs::cli::run_alias("replace-texture")
+}
@@ -0,0 +1,3 @@
+fn main() -> std::process::ExitCode {
+    sims3_rs::cli::main()
+}
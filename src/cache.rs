@@ -0,0 +1,386 @@
+//! A cache of what's in each package of a CC folder, so tools that scan thousands of packages
+//! only have to read the ones that changed since the last run.
+//!
+//! Packages are keyed by their absolute path, and a cached entry is used as long as the file's size
+//! and modification time are the same. The cache is one file, see [`ScanCache::default_path`].
+//!
+//! Scanning a package only reads its index. Hashes of the resources' data are worked out the first
+//! time they're asked for, with [`ScanCache::hashed`], and kept in the cache from then on.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use binrw::{binrw, BinRead, BinWrite};
+use rayon::prelude::*;
+
+use crate::dbpf::filetypes::tgi::{TGIOrder, TGI};
+use crate::dbpf::index::{self, IndexKey};
+use crate::error::Error;
+use crate::util::LengthString;
+
+/// Bumped whenever the file format (or what's stored in it) changes. Older caches are thrown away.
+const VERSION: u32 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CachedResource {
+    pub key: IndexKey,
+    /// FNV-1a hash of the resource's data as it's stored in the file (so, compressed if it is).
+    /// `None` until [`CachedPackage::hash_resources`] has been run on the package.
+    pub hash: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedPackage {
+    pub size: u64,
+    pub modified: SystemTime,
+    /// In index order.
+    pub resources: Vec<CachedResource>,
+}
+
+impl CachedPackage {
+    /// Read the index of the package at `path`. The resources' data isn't read, so they have no hashes yet.
+    pub fn scan(path: &Path) -> crate::error::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let resources = index::read_file(path)?
+            .into_iter()
+            .map(|key| CachedResource { key, hash: None })
+            .collect();
+        Ok(CachedPackage {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            resources,
+        })
+    }
+
+    /// Hash the data of every resource that doesn't have a hash yet, reading only that data from
+    /// `path`, which must be the package this was scanned from.
+    pub fn hash_resources(&mut self, path: &Path) -> crate::error::Result<()> {
+        if self.resources.iter().all(|r| r.hash.is_some()) {
+            return Ok(());
+        }
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+        let mut stored = Vec::new();
+        for resource in self.resources.iter_mut().filter(|r| r.hash.is_none()) {
+            let key = resource.key;
+            file.seek(io::SeekFrom::Start(key.offset as u64))?;
+            stored.clear();
+            (&mut file).take(key.filesize as u64).read_to_end(&mut stored)?;
+            if stored.len() != key.filesize as usize {
+                return Err(Error::ChunkOutOfBounds {
+                    tgi: Some(key.tgi),
+                    offset: key.offset as u64,
+                    size: key.filesize as u64,
+                    file_size: self.size,
+                });
+            }
+            resource.hash = Some(crate::hash::fnv1a_64_bytes(&stored));
+        }
+        Ok(())
+    }
+
+    pub fn tgis(&self) -> impl Iterator<Item = TGI> + Clone + '_ {
+        self.resources.iter().map(|r| r.key.tgi)
+    }
+
+    fn is_current(&self, metadata: &fs::Metadata) -> bool {
+        metadata.len() == self.size && metadata.modified().is_ok_and(|m| m == self.modified)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanCache {
+    packages: HashMap<PathBuf, CachedPackage>,
+}
+
+impl ScanCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `sims3_rs/scan.cache` in the user's cache directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("sims3_rs").join("scan.cache"))
+    }
+
+    /// Load the cache at `path`. A missing, damaged or outdated cache file gives an empty cache,
+    /// since it will just be rebuilt.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e),
+        };
+        let file = match CacheFile::read(&mut io::Cursor::new(data)) {
+            Ok(file) => file,
+            Err(_) => return Ok(Self::new()),
+        };
+        let packages = file
+            .packages
+            .into_iter()
+            .filter_map(|p| {
+                let path = PathBuf::from(String::from_utf8(p.path.inner).ok()?);
+                // Duration::new panics if the nanoseconds carry over into seconds that don't fit
+                let nanos = Duration::from_nanos(p.modified_nanos.into());
+                let package = CachedPackage {
+                    size: p.size,
+                    modified: UNIX_EPOCH.checked_add(Duration::from_secs(p.modified_secs).checked_add(nanos)?)?,
+                    resources: p.resources.into_iter().map(CachedResource::from).collect(),
+                };
+                Some((path, package))
+            })
+            .collect();
+        Ok(ScanCache { packages })
+    }
+
+    /// Write the cache to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let packages = self
+            .packages
+            .iter()
+            .filter_map(|(path, package)| {
+                // paths that aren't Unicode are left out, and rescanned every time
+                let path = path.to_str()?.as_bytes().to_vec();
+                let modified = package.modified.duration_since(UNIX_EPOCH).ok()?;
+                Some(CacheEntry {
+                    path: LengthString { inner: path },
+                    size: package.size,
+                    modified_secs: modified.as_secs(),
+                    modified_nanos: modified.subsec_nanos(),
+                    resources: package.resources.iter().map(RawResource::from).collect(),
+                })
+            })
+            .collect();
+        let mut out = io::Cursor::new(Vec::new());
+        CacheFile { version: VERSION, packages }
+            .write(&mut out)
+            .map_err(io::Error::other)?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // so that a tool that gets interrupted doesn't leave half a cache behind
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, out.into_inner())?;
+        fs::rename(temp, path)
+    }
+
+    /// The cached contents of `path`, without checking whether the package has changed since.
+    pub fn get(&self, path: &Path) -> Option<&CachedPackage> {
+        self.packages.get(&cache_key(path))
+    }
+
+    /// The contents of `path`, reading the package if the cached entry is missing or out of date.
+    pub fn package(&mut self, path: &Path) -> crate::error::Result<&CachedPackage> {
+        self.current(path).map(|package| &*package)
+    }
+
+    /// Like [`package`](Self::package), but with the hash of every resource filled in.
+    pub fn hashed(&mut self, path: &Path) -> crate::error::Result<&CachedPackage> {
+        let package = self.current(path)?;
+        package.hash_resources(path)?;
+        Ok(package)
+    }
+
+    fn current(&mut self, path: &Path) -> crate::error::Result<&mut CachedPackage> {
+        let metadata = fs::metadata(path)?;
+        Ok(match self.packages.entry(cache_key(path)) {
+            Entry::Occupied(e) if e.get().is_current(&metadata) => e.into_mut(),
+            Entry::Occupied(mut e) => {
+                e.insert(CachedPackage::scan(path)?);
+                e.into_mut()
+            }
+            Entry::Vacant(e) => e.insert(CachedPackage::scan(path)?),
+        })
+    }
+
+    /// Bring the cache up to date for `paths`, reading (in parallel) the packages that are new or
+    /// have changed. Packages that no longer exist are dropped from the cache.
+    ///
+    /// Returns the packages that couldn't be read.
+    pub fn refresh(&mut self, paths: &[PathBuf]) -> Vec<(PathBuf, Error)> {
+        self.packages.retain(|path, _| path.exists());
+        let stale: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| {
+                let current = self.packages.get(&cache_key(path));
+                !fs::metadata(path).is_ok_and(|m| current.is_some_and(|p| p.is_current(&m)))
+            })
+            .collect();
+        let scanned: Vec<_> = stale
+            .into_par_iter()
+            .map(|path| (path, CachedPackage::scan(path)))
+            .collect();
+
+        let mut failed = Vec::new();
+        for (path, result) in scanned {
+            match result {
+                Ok(package) => {
+                    self.packages.insert(cache_key(path), package);
+                }
+                Err(e) => {
+                    self.packages.remove(&cache_key(path));
+                    failed.push((path.clone(), e));
+                }
+            }
+        }
+        failed
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &CachedPackage)> {
+        self.packages.iter().map(|(path, package)| (path.as_path(), package))
+    }
+}
+
+fn cache_key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[binrw]
+#[brw(little, magic = b"S3SC")]
+struct CacheFile {
+    #[br(assert(version == VERSION))]
+    version: u32,
+    #[br(temp)]
+    #[bw(try_calc = packages.len().try_into())]
+    count: u32,
+    #[br(count = count)]
+    packages: Vec<CacheEntry>,
+}
+
+#[binrw]
+struct CacheEntry {
+    path: LengthString,
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    #[br(temp)]
+    #[bw(try_calc = resources.len().try_into())]
+    count: u32,
+    #[br(count = count)]
+    resources: Vec<RawResource>,
+}
+
+#[binrw]
+struct RawResource {
+    #[brw(args_raw = TGIOrder::TGI)]
+    tgi: TGI,
+    offset: u32,
+    filesize: u32,
+    memsize: u32,
+    #[br(map = |b: u8| b != 0)]
+    #[bw(map = |b: &bool| *b as u8)]
+    compressed: bool,
+    #[br(map = |(hashed, hash): (u8, u64)| (hashed != 0).then_some(hash))]
+    #[bw(map = |h: &Option<u64>| (h.is_some() as u8, h.unwrap_or(0)))]
+    hash: Option<u64>,
+}
+
+impl From<RawResource> for CachedResource {
+    fn from(r: RawResource) -> Self {
+        CachedResource {
+            key: IndexKey {
+                tgi: r.tgi,
+                offset: r.offset,
+                filesize: r.filesize,
+                memsize: r.memsize,
+                compressed: r.compressed,
+            },
+            hash: r.hash,
+        }
+    }
+}
+
+impl From<&CachedResource> for RawResource {
+    fn from(r: &CachedResource) -> Self {
+        RawResource {
+            tgi: r.key.tgi,
+            offset: r.key.offset,
+            filesize: r.key.filesize,
+            memsize: r.key.memsize,
+            compressed: r.key.compressed,
+            hash: r.hash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbpf::{ChunkHandle, DBPFIndexEntry, DBPF};
+
+    #[test]
+    fn hashes_only_when_asked() {
+        let dir = std::env::temp_dir().join(format!("sims3_rs-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.package");
+        let mut package = DBPF::new();
+        package.entries.push(DBPFIndexEntry {
+            resource_type: 0x0333406C,
+            resource_group: 0,
+            instance: 1,
+            unk1: false,
+            unk2: 1,
+            chunk: ChunkHandle::Dirty {
+                decompressed: b"data".to_vec(),
+                should_compress: false,
+            },
+        });
+        let mut out = io::Cursor::new(Vec::new());
+        package.write_to(&mut out, ()).unwrap();
+        fs::write(&path, out.into_inner()).unwrap();
+
+        let mut cache = ScanCache::new();
+        let scanned = cache.package(&path).unwrap();
+        assert_eq!(scanned.tgis().collect::<Vec<_>>(), [TGI::new(0x0333406C, 0, 1)]);
+        assert_eq!(scanned.resources[0].hash, None);
+        let hashed = cache.hashed(&path).unwrap();
+        assert_eq!(hashed.resources[0].hash, Some(crate::hash::fnv1a_64_bytes(b"data")));
+
+        let cache_path = dir.join("scan.cache");
+        cache.save(&cache_path).unwrap();
+        let loaded = ScanCache::load(&cache_path).unwrap();
+        assert_eq!(loaded.get(&path), cache.get(&path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn out_of_range_times_are_dropped() {
+        let dir = std::env::temp_dir().join(format!("sims3_rs-cache-time-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entry = |name: &str, modified_secs, modified_nanos| CacheEntry {
+            path: LengthString {
+                inner: dir.join(name).to_str().unwrap().as_bytes().to_vec(),
+            },
+            size: 0,
+            modified_secs,
+            modified_nanos,
+            resources: Vec::new(),
+        };
+        let file = CacheFile {
+            version: VERSION,
+            packages: vec![entry("ok.package", 1, 999_999_999), entry("bad.package", u64::MAX, u32::MAX)],
+        };
+        let mut out = io::Cursor::new(Vec::new());
+        file.write(&mut out).unwrap();
+        let cache_path = dir.join("scan.cache");
+        fs::write(&cache_path, out.into_inner()).unwrap();
+
+        let loaded = ScanCache::load(&cache_path).unwrap();
+        assert_eq!(loaded.packages.len(), 1);
+        assert!(loaded.get(&dir.join("ok.package")).is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+//! The `sims3` command line tool.
+//!
+//! Every subcommand lives in its own module with an `Args` struct and a `run` function.
+//! The older single-purpose binaries (`extract`, `geom_tri_count`, ...) are kept as aliases
+//! that call [`run_alias`] with the name of their subcommand.
+
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use walkdir::WalkDir;
+
+use crate::cache::ScanCache;
+use crate::dbpf::filetypes::ResourceType;
+
+pub mod dump_meshes;
+pub mod dump_pngs;
+pub mod extract;
+pub mod find_merged_cc;
+pub mod geom_tri_count;
+pub mod import_mesh;
+pub mod inspect;
+pub mod pack;
+pub mod package_names;
+pub mod repair;
+pub mod replace_texture;
+pub mod sims3pack;
+pub mod unpack;
+pub mod validate;
+
+/// Options shared by every subcommand.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct GlobalOpts {
+    /// Only look at the top level of directories, instead of searching them recursively
+    #[arg(long, global = true)]
+    pub no_recurse: bool,
+
+    /// Print results as JSON, for commands that report something
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Number of threads to use for commands that read many packages. Defaults to one per core
+    #[arg(short = 'j', long, global = true, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// Only print results and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print more about what's going on
+    #[arg(long, global = true)]
+    pub verbose: bool,
+
+    /// Read every package again, instead of using the cache of what's in them
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+
+    /// Where to keep the cache of what's in each package. Defaults to the user's cache directory
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "no_cache")]
+    pub cache: Option<PathBuf>,
+}
+
+impl GlobalOpts {
+    /// Every package in `paths`. Directories are searched, and files are taken as they are.
+    pub fn find_packages(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        let depth = if self.no_recurse { 1 } else { usize::MAX };
+        paths
+            .iter()
+            .flat_map(|path| WalkDir::new(path).max_depth(depth))
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| e.depth() == 0 || e.path().extension() == Some(OsStr::new("package")))
+            .map(|e| e.into_path())
+            .collect()
+    }
+
+    /// Progress messages. These go to stderr, so they don't get mixed into results.
+    pub fn status(&self, message: impl Display) {
+        if !self.quiet {
+            eprintln!("{}", message);
+        }
+    }
+
+    pub fn debug(&self, message: impl Display) {
+        if self.verbose {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// A problem with one input that doesn't stop the command.
+    pub fn warn(&self, message: impl Display) {
+        eprintln!("warning: {}", message);
+    }
+
+    fn cache_path(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+        self.cache.clone().or_else(ScanCache::default_path)
+    }
+
+    /// The scan cache, unless `--no-cache` was given. If it can't be loaded, this warns and starts a new one.
+    pub fn load_cache(&self) -> Option<ScanCache> {
+        let path = self.cache_path()?;
+        self.debug(format_args!("Using cache {}", path.display()));
+        Some(ScanCache::load(&path).unwrap_or_else(|e| {
+            self.warn(format_args!("couldn't load cache {}: {}", path.display(), e));
+            ScanCache::new()
+        }))
+    }
+
+    pub fn save_cache(&self, cache: &ScanCache) {
+        if let Some(path) = self.cache_path() {
+            if let Err(e) = cache.save(&path) {
+                self.warn(format_args!("couldn't save cache {}: {}", path.display(), e));
+            }
+        }
+    }
+}
+
+/// How a command failed. Each kind has its own exit code, see [`CliError::exit_code`].
+#[derive(Debug)]
+pub enum CliError {
+    /// Some of the inputs couldn't be processed, but the rest were.
+    Partial { failed: usize },
+    /// `validate` found packages with issues at or above `--fail-on`.
+    Invalid { packages: usize },
+    Other(Box<dyn Error>),
+}
+
+pub type CliResult = Result<(), CliError>;
+
+impl CliError {
+    /// 1 for errors, 3 when only some inputs failed, 4 when packages failed validation.
+    /// (clap exits with 2 for bad arguments.)
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Other(_) => 1,
+            CliError::Partial { .. } => 3,
+            CliError::Invalid { .. } => 4,
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Partial { failed } => write!(f, "{} input(s) could not be processed", failed),
+            CliError::Invalid { packages } => write!(f, "{} package(s) failed validation", packages),
+            CliError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+// CliError deliberately doesn't implement Error, so that this doesn't overlap with `From<T> for T`.
+impl<E: Into<Box<dyn Error>>> From<E> for CliError {
+    fn from(e: E) -> Self {
+        CliError::Other(e.into())
+    }
+}
+
+/// `Ok` if nothing failed, otherwise [`CliError::Partial`].
+pub fn partial(failed: usize) -> CliResult {
+    match failed {
+        0 => Ok(()),
+        failed => Err(CliError::Partial { failed }),
+    }
+}
+
+/// Name for a resource type, like `CASP`, or its hex value if it doesn't have one.
+pub fn type_name(resource_type: u32) -> String {
+    num_traits::FromPrimitive::from_u32(resource_type)
+        .map(|t: ResourceType| format!("{:?}", t))
+        .unwrap_or_else(|| format!("{:08X}", resource_type))
+}
+
+/// Keep names usable as filenames on every platform.
+pub fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// Parses hex numbers, with or without `0x`.
+pub fn parse_hex(s: &str) -> Result<u32, String> {
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex number", s))
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "sims3", version, about = "Tools for Sims 3 packages")]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalOpts,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Show a package's header and a summary of what's in it
+    Info(inspect::InfoArgs),
+    /// List the resources in a package
+    Ls(inspect::LsArgs),
+    /// Copy one resource out of a package
+    Extract(extract::Args),
+    /// Write every resource in a package to a directory
+    Unpack(unpack::Args),
+    /// Build a package from a directory made by unpack (or S3PE's export)
+    Pack(pack::Args),
+    /// Find the packages that the CC in a merged package came from
+    #[command(alias = "find_merged_cc")]
+    FindMergedCc(find_merged_cc::Args),
+    /// Count the polygons in every CAS part
+    #[command(alias = "geom_tri_count")]
+    GeomTriCount(geom_tri_count::Args),
+    /// Export the images in packages as PNGs
+    #[command(alias = "dump_package_pngs")]
+    DumpPngs(dump_pngs::Args),
+    /// Export every GEOM and MODL in a package to OBJ/glTF
+    #[command(alias = "dump_package_meshes")]
+    DumpMeshes(dump_meshes::Args),
+    /// Replace the mesh of a GEOM with an OBJ or glTF file
+    #[command(alias = "import_mesh")]
+    ImportMesh(import_mesh::Args),
+    /// Replace _IMG textures in a package with PNG images
+    #[command(alias = "replace_texture")]
+    ReplaceTexture(replace_texture::Args),
+    /// Rename packages after the name of the CC inside them
+    #[command(alias = "package_names")]
+    PackageNames(package_names::Args),
+    /// Check packages for damage and broken format rules
+    Validate(validate::Args),
+    /// Save what can be saved from a damaged or half-downloaded package
+    Repair(repair::Args),
+    /// Look inside Sims3Packs, turn them into packages, or build new ones
+    Sims3pack(sims3pack::Args),
+}
+
+impl Cli {
+    pub fn run(&self) -> CliResult {
+        if let Some(threads) = self.global.threads {
+            rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+        }
+        let global = &self.global;
+        match &self.command {
+            Command::Info(args) => inspect::info(args, global),
+            Command::Ls(args) => inspect::ls(args, global),
+            Command::Extract(args) => extract::run(args, global),
+            Command::Unpack(args) => unpack::run(args, global),
+            Command::Pack(args) => pack::run(args, global),
+            Command::FindMergedCc(args) => find_merged_cc::run(args, global),
+            Command::GeomTriCount(args) => geom_tri_count::run(args, global),
+            Command::DumpPngs(args) => dump_pngs::run(args, global),
+            Command::DumpMeshes(args) => dump_meshes::run(args, global),
+            Command::ImportMesh(args) => import_mesh::run(args, global),
+            Command::ReplaceTexture(args) => replace_texture::run(args, global),
+            Command::PackageNames(args) => package_names::run(args, global),
+            Command::Validate(args) => validate::run(args, global),
+            Command::Repair(args) => repair::run(args, global),
+            Command::Sims3pack(args) => sims3pack::run(args, global),
+        }
+    }
+}
+
+fn exit(result: CliResult) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Entry point of the `sims3` binary.
+pub fn main() -> ExitCode {
+    exit(Cli::parse().run())
+}
+
+/// Entry point of the old binaries: runs `sims3 <subcommand>` with the rest of the arguments.
+pub fn run_alias(subcommand: &str) -> ExitCode {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    args.insert(1.min(args.len()), subcommand.into());
+    exit(Cli::parse_from(args).run())
+}
@@ -0,0 +1,226 @@
+//! `dump_package_meshes`: export every mesh in a package to OBJ and/or glTF.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use super::{partial, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::casp::CASP;
+use crate::dbpf::filetypes::geom::Geometry;
+use crate::dbpf::filetypes::model::Modl;
+use crate::dbpf::filetypes::rcol::{ChunkTag, Resolved, RCOL};
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::filetypes::vpxy::VPXY;
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::{DBPFIndexEntry, DBPFReader, FileCtx};
+use crate::mesh::{gltf, obj, Lod, Mesh};
+
+use binrw::{error::ContextExt, BinRead};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Obj,
+    Glb,
+    Both,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Package to export meshes from
+    pub package: PathBuf,
+
+    /// Directory to write meshes to
+    #[arg(default_value = ".")]
+    pub output: PathBuf,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Both)]
+    pub format: Format,
+}
+
+fn read_rcol<'brand, Ctx: FileCtx<'brand>>(
+    ctx: &mut Ctx,
+    entry: &DBPFIndexEntry<'brand>,
+) -> Result<RCOL, binrw::Error> {
+    RCOL::read_le(&mut entry.get_reader(ctx)?).with_context(entry.tgi())
+}
+
+fn export(opt: &Args, name: &str, lods: &[Lod], names: &crate::hash::HashDictionary) -> std::io::Result<()> {
+    let path = |ext: &str| opt.output.join(Path::new(name).with_extension(ext));
+    if opt.format != Format::Glb {
+        obj::write_obj(&mut BufWriter::new(File::create(path("obj"))?), lods)?;
+    }
+    if opt.format != Format::Obj {
+        gltf::write_glb(&mut BufWriter::new(File::create(path("glb"))?), lods, names)?;
+    }
+    Ok(())
+}
+
+fn tgi_name(tgi: &TGI) -> String {
+    format!("{:08X}_{:08X}_{:016X}", tgi.resource_type, tgi.resource_group, tgi.instance)
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let file = File::open(&opt.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(std::io::BufReader::new(file), guard)?;
+    let names = package.hash_dictionary(&mut reader).unwrap_or_else(|e| {
+        global.warn(format_args!("{}: {}", opt.package.display(), e));
+        crate::hash::KNOWN_NAMES.clone()
+    });
+    std::fs::create_dir_all(&opt.output)?;
+
+    let mut failed = 0;
+    let save = |name: &str, lods: &[Lod], failed: &mut usize| match export(opt, name, lods, &names) {
+        Ok(()) => global.debug(format_args!("Exported {}", name)),
+        Err(e) => {
+            global.warn(format_args!("failed to export {}: {}", name, e));
+            *failed += 1;
+        }
+    };
+    let by_tgi: HashMap<TGI, &DBPFIndexEntry> = package.entries.iter().map(|e| (e.tgi(), e)).collect();
+
+    // CAS parts keep each LOD in its own GEOM
+    let mut geoms = BTreeMap::new();
+    for entry in package
+        .entries
+        .iter()
+        .filter(|e| e.resource_type == ResourceType::GEOM as u32 && e.chunk.memsize() != 0)
+    {
+        let geom =
+            read_rcol(&mut reader, entry).and_then(|rcol| Geometry::from_rcol(&rcol).with_context(entry.tgi()));
+        match geom {
+            Ok(geom) => {
+                geoms.insert(entry.tgi(), geom);
+            }
+            Err(e) => {
+                global.warn(format_args!("{}: {}", tgi_name(&entry.tgi()), e));
+                failed += 1;
+            }
+        }
+    }
+
+    // which GEOMs make up each LOD is in the VPXYs of the CASP, so export a file per CAS part
+    let mut referenced = HashSet::new();
+    for entry in package.entries.iter().filter(|e| e.resource_type == ResourceType::CASP as u32) {
+        let name = tgi_name(&entry.tgi());
+        let casp = entry
+            .get_reader(&mut reader)
+            .map_err(binrw::Error::from)
+            .and_then(|mut r| CASP::read_le(&mut r))
+            .with_context(entry.tgi());
+        let casp = match casp {
+            Ok(casp) => casp,
+            Err(e) => {
+                global.warn(format_args!("{}: {}", name, e));
+                failed += 1;
+                continue;
+            }
+        };
+
+        let mut lods: BTreeMap<u8, Vec<Mesh>> = BTreeMap::new();
+        for vpxy_tgi in casp.vpxys() {
+            // recolours of game items point at the game's VPXY
+            let Some(vpxy_entry) = by_tgi.get(vpxy_tgi) else {
+                continue;
+            };
+            let vpxy = match read_rcol(&mut reader, vpxy_entry)
+                .and_then(|rcol| VPXY::from_rcol(&rcol).with_context(*vpxy_tgi))
+            {
+                Ok(vpxy) => vpxy,
+                Err(e) => {
+                    global.warn(format_args!("{}: {}", name, e));
+                    failed += 1;
+                    continue;
+                }
+            };
+            for id in vpxy.lod_ids() {
+                let meshes = lods.entry(id).or_default();
+                for tgi in vpxy.lod(id) {
+                    if let Some(geom) = geoms.get(tgi) {
+                        referenced.insert(*tgi);
+                        meshes.push(Mesh::from_geometry(&tgi_name(tgi), geom));
+                    }
+                }
+            }
+        }
+        let lods: Vec<Lod> = lods
+            .into_iter()
+            .filter(|(_, meshes)| !meshes.is_empty())
+            .map(|(id, meshes)| Lod {
+                name: format!("LOD{}", id),
+                meshes,
+            })
+            .collect();
+        if !lods.is_empty() {
+            save(&name, &lods, &mut failed);
+        }
+    }
+
+    // GEOMs no CAS part uses get a file each
+    for (tgi, geom) in geoms.iter().filter(|(tgi, _)| !referenced.contains(*tgi)) {
+        let name = tgi_name(tgi);
+        save(
+            &name,
+            &[Lod {
+                name: name.clone(),
+                meshes: vec![Mesh::from_geometry(&name, geom)],
+            }],
+            &mut failed,
+        );
+    }
+
+    for entry in package
+        .entries
+        .iter()
+        .filter(|e| e.resource_type == ResourceType::MODL as u32 && e.chunk.memsize() != 0)
+    {
+        let name = tgi_name(&entry.tgi());
+        let rcol = match read_rcol(&mut reader, entry) {
+            Ok(rcol) => rcol,
+            Err(e) => {
+                global.warn(format_args!("{}: {}", name, e));
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(chunk) = rcol.find(ChunkTag::MODL) else {
+            continue;
+        };
+        let modl = match Modl::read_le(&mut chunk.reader()).with_context(entry.tgi()) {
+            Ok(modl) => modl,
+            Err(e) => {
+                global.warn(format_args!("{}: {}", name, e));
+                failed += 1;
+                continue;
+            }
+        };
+        let mut lods = Vec::new();
+        for (i, lod) in modl.lods.iter().enumerate() {
+            let meshes = match rcol.resolve(lod.model_lod) {
+                Some(Resolved::Chunk(mlod)) => Mesh::from_mlod_chunk(&rcol, mlod),
+                Some(Resolved::External(tgi)) => match package.entries.iter().find(|e| e.tgi() == *tgi) {
+                    Some(mlod) => read_rcol(&mut reader, mlod).and_then(|rcol| Mesh::from_mlod(&rcol)),
+                    None => {
+                        global.warn(format_args!("{}: LOD {} is in another package ({})", name, i, tgi));
+                        continue;
+                    }
+                },
+                None => continue,
+            };
+            match meshes {
+                Ok(meshes) => lods.push(Lod {
+                    name: format!("LOD{}", i),
+                    meshes,
+                }),
+                Err(e) => {
+                    global.warn(format_args!("{}: LOD {}: {}", name, i, e));
+                    failed += 1;
+                }
+            }
+        }
+        save(&name, &lods, &mut failed);
+    }
+
+    partial(failed)
+}
@@ -0,0 +1,167 @@
+//! `dump_package_pngs`: export the images in packages as PNGs.
+
+use super::{parse_hex, partial, sanitize_filename, type_name, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::{dds::{Format, DDS}, resource_is_png, ResourceType};
+use crate::dbpf::DBPFReader;
+
+use binrw::{error::ContextExt, BinRead};
+use serde_json::{json, Value};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Packages, or directories to search for packages
+    #[arg(required = true, num_args = 1..)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Directory to write images to. With several packages, each gets a subdirectory
+    #[arg(short, long, default_value = ".")]
+    pub output: PathBuf,
+
+    /// Append the resource's name from the package's NMAP to the filename, if it has one
+    #[arg(short, long)]
+    pub names: bool,
+
+    /// Only export these resource types, in hex (like `00B2D882` for _IMG). Can be repeated
+    #[arg(short = 't', long = "type", value_name = "TYPE", value_parser = parse_hex)]
+    pub types: Vec<u32>,
+
+    /// Don't write manifest.json
+    #[arg(long)]
+    pub no_manifest: bool,
+}
+
+fn dump_package(opt: &Args, global: &GlobalOpts, path: &Path, output: &Path) -> Result<Vec<Value>, binrw::Error> {
+    let file = File::open(path)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(std::io::BufReader::new(file), guard)?;
+    let names = if opt.names {
+        package.gather_names(&mut reader).unwrap_or_else(|e| {
+            global.warn(format_args!("{}: {}", path.display(), e));
+            Default::default()
+        })
+    } else {
+        Default::default()
+    };
+    std::fs::create_dir_all(output)?;
+
+    let mut manifest = Vec::new();
+    for entry in package
+        .entries
+        .iter()
+        .filter(|e| e.chunk.memsize() != 0)
+        .filter(|e| opt.types.is_empty() || opt.types.contains(&e.resource_type))
+    {
+        let is_png = resource_is_png(entry.resource_type);
+        if !is_png && entry.resource_type != ResourceType::IMG as u32 {
+            continue;
+        }
+
+        let mut filename = format!("{:08X}_{:08X}_{:016X}", entry.resource_type, entry.resource_group, entry.instance);
+        let name = names.get(&entry.instance);
+        if let Some(name) = name {
+            filename = format!("{}_{}", filename, sanitize_filename(name));
+        }
+        filename.push_str(".png");
+        let file_path = output.join(&filename);
+
+        let (format, width, height) = if is_png {
+            let mut f = File::create(&file_path)?;
+            std::io::copy(&mut entry.get_reader(&mut reader)?, &mut f)?;
+            ("PNG".to_string(), None, None)
+        } else {
+            let dds = DDS::read_le(&mut entry.get_reader(&mut reader)?).with_context(entry.tgi());
+            let decoded = dds.and_then(|dds| Ok((dds.decode().with_context(entry.tgi())?, dds)));
+            match decoded {
+                Ok((image, dds)) => {
+                    image.write_png(BufWriter::new(File::create(&file_path)?))?;
+                    let pf = &dds.header.pixel_format;
+                    let format = match dds.format() {
+                        Some(Format::Uncompressed) => format!("{}-bit", pf.rgb_bit_count),
+                        _ => String::from_utf8_lossy(&pf.four_cc).into_owned(),
+                    };
+                    (format, Some(image.width), Some(image.height))
+                }
+                Err(e) => {
+                    global.warn(format_args!("{}: skipping texture: {}", path.display(), e));
+                    continue;
+                }
+            }
+        };
+
+        manifest.push(json!({
+            "package": path.to_string_lossy(),
+            "tgi": entry.tgi().to_string(),
+            "type": type_name(entry.resource_type),
+            "name": name,
+            "file": file_path.to_string_lossy(),
+            "format": format,
+            "width": width,
+            "height": height,
+        }));
+    }
+    Ok(manifest)
+}
+
+/// The subdirectory for each package in a batch: its path below the directory it was found in,
+/// minus the extension, or just its name if it was given directly. Anything that still clashes,
+/// like `a.package` in two different inputs, gets a number on the end.
+fn batch_dirs(inputs: &[PathBuf], packages: &[PathBuf]) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    packages
+        .iter()
+        .map(|path| {
+            let relative = inputs
+                .iter()
+                .filter(|input| input.is_dir())
+                .find_map(|input| path.strip_prefix(input).ok())
+                .unwrap_or_else(|| Path::new(path.file_name().unwrap_or_default()));
+            let base = relative.with_extension("");
+            let mut dir = base.clone();
+            for n in 2.. {
+                if taken.insert(dir.clone()) {
+                    break;
+                }
+                dir = PathBuf::from(format!("{}_{}", base.display(), n));
+            }
+            dir
+        })
+        .collect()
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let packages = global.find_packages(&opt.inputs);
+    let batch = packages.len() > 1 || opt.inputs.iter().any(|p| p.is_dir());
+
+    let dirs = batch_dirs(&opt.inputs, &packages);
+
+    let mut manifest = Vec::new();
+    let mut failed = 0;
+    for (path, dir) in packages.iter().zip(&dirs) {
+        let output = if batch { opt.output.join(dir) } else { opt.output.clone() };
+        match dump_package(opt, global, path, &output) {
+            Ok(entries) => {
+                global.status(format_args!("{}: exported {} images", path.display(), entries.len()));
+                manifest.extend(entries);
+            }
+            Err(e) => {
+                global.warn(format_args!("error while parsing {}: {}", path.display(), e));
+                failed += 1;
+            }
+        }
+    }
+
+    if !opt.no_manifest {
+        std::fs::create_dir_all(&opt.output)?;
+        let f = BufWriter::new(File::create(opt.output.join("manifest.json"))?);
+        serde_json::to_writer_pretty(f, &manifest)?;
+    }
+    if global.json {
+        println!("{}", serde_json::to_string_pretty(&manifest)?);
+    }
+    partial(failed)
+}
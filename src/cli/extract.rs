@@ -0,0 +1,37 @@
+//! `extract`: copy one resource out of a package, for looking at in a hex editor.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use super::{CliResult, GlobalOpts};
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::DBPFReader;
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    pub package: PathBuf,
+
+    /// The resource to extract, as TTTTTTTT:GGGGGGGG:IIIIIIIIIIIIIIII
+    pub tgi: TGI,
+
+    pub output: PathBuf,
+}
+
+pub fn run(args: &Args, global: &GlobalOpts) -> CliResult {
+    let file = File::open(&args.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(BufReader::new(file), guard)?;
+
+    let entry = package
+        .entries
+        .iter()
+        .find(|entry| entry.tgi() == args.tgi)
+        .ok_or_else(|| format!("{} is not in the package", args.tgi))?;
+
+    let mut chunk_reader = entry.get_reader(&mut reader)?;
+    let mut output_file = File::create(&args.output)?;
+    let size = std::io::copy(&mut chunk_reader, &mut output_file)?;
+    global.status(format_args!("{}: wrote {} bytes to {}", args.tgi, size, args.output.display()));
+    Ok(())
+}
@@ -0,0 +1,603 @@
+//! `find_merged_cc`: find the packages that the CC in a merged package came from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::{Cursor, Read, Seek};
+use std::iter::{FromIterator, Iterator};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use binrw::BinRead;
+use rayon::prelude::*;
+use serde_json::json;
+
+use super::{partial, type_name, CliError, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::casp::CASP;
+use crate::dbpf::filetypes::pattern::Pattern;
+use crate::dbpf::filetypes::simo::SIMO;
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::{index, DBPFIndexEntry, DBPFReader, FileCtx};
+use crate::resource_cfg::{Resolver, ResourceCfg};
+use crate::sims3pack::Sims3Pack;
+
+// TODO: is packageid under dependencyList ever not going to be a 128 bit number?
+// What about other languages?
+// static ADDON_DEPS: phf::Map<u128, &'static str> = phf_map! {
+//     0x062d99d500000000062d99d500000000u128 => "World Adventures Expansion",
+//     0x062d99de00000000062d99de00000000u128 => "Ambitions Expansion",
+//     0x062d99ed00000000062d99ed00000000u128 => "Late Night Expansion",
+//     0x062d9a0200000000062d9a0200000000u128 => "Generations Expansion",
+//     0x062d9a0300000000062d9a0300000000u128 => "Pets Expansion",
+//     0x062d9a0400000000062d9a0400000000u128 => "Showtime Expansion",
+//     0x062d9a0500000000062d9a0500000000u128 => "Supernatural Expansion",
+//     0x062d9a0600000000062d9a0600000000u128 => "Seasons Expansion",
+//     0x062d9a0600000000062d9a0600000000u128 => "University Expansion",
+//     0x062d9a0800000000062d9a0800000000u128 => "Island Paradise Expansion",
+//     0x062d9a0900000000062d9a0900000000u128 => "Into The Future Expansion",
+//     0x062d9a0900000000062d9a0900000000u128 => "High-End Loft Stuff Pack",
+//     0x062d9b1000000001062d9b1000000001u128 => "Fast Lane Stuff Pack",
+//     0x062d9b1100000001062d9b1100000001u128 => "Outdoor Living Stuff Pack",
+//     0x062d9b1200000001062d9b1200000001u128 => "Town Life Stuff Pack",
+//     0x062d9b1300000001062d9b1300000001u128 => "Master Suite Stuff Pack",
+//     0x062d9b1400000001062d9b1400000001u128 => "Katy Perry's Sweet Treats Stuff Pack",
+//     0x062d9b1500000001062d9b1500000001u128 => "Diesel Stuff Pack",
+//     0x062d9b1600000001062d9b1600000001u128 => "70's, 80's and 90's Stuff Pack",
+//     0x062d9b1700000001062d9b1700000001u128 => "Movie Stuff Pack"
+// };
+
+/// Whether `tgi` is a kind of resource that CC is made of.
+fn is_cc_key(tgi: &TGI) -> bool {
+    // Clothing, hair, etc.
+    tgi.resource_type == ResourceType::CASP as u32
+    // Sliders
+ || tgi.resource_type == ResourceType::FACE as u32
+    // Skins
+ || tgi.resource_type == ResourceType::SkinTone as u32
+    // Objects, if someone uses this for that.
+ || tgi.resource_type == ResourceType::OBJD as u32
+}
+
+fn filter_tgi_into_map(tgis: impl Iterator<Item = TGI> + Clone, merged: bool) -> HashSet<TGI> {
+    let tgi_set = HashSet::from_iter(
+        tgis.clone()
+            .filter(|tgi| {
+                is_cc_key(tgi)
+                // Patterns -- but only if this is a merged package that I'm searching.
+                // Clothing has duplicates, so unless this is the package I'm searching I only
+                // want to see it if there's nothing else.
+             || (merged && tgi.resource_type == ResourceType::XMLResource as u32)
+            }),
+    );
+
+    if !tgi_set.is_empty() {
+        tgi_set
+    } else {
+        // If there's nothing else of interest, now pattern XMLs are interesting.
+        HashSet::from_iter(tgis.filter(|tgi| tgi.resource_type == ResourceType::XMLResource as u32))
+    }
+}
+
+fn package_keys(path: &Path) -> crate::error::Result<HashSet<TGI>> {
+    Ok(filter_tgi_into_map(index::read_file(path)?.iter().map(|key| key.tgi), false))
+}
+
+/// Something to find the CC of: a package, or one of the packages in a Sims3Pack or save.
+struct Source {
+    name: String,
+    /// The keys of the CC in it, see [`filter_tgi_into_map`].
+    find: HashSet<TGI>,
+    sims: Vec<Sim>,
+    /// Names of the CC in it, from its NMAPs or the CASPs themselves.
+    names: HashMap<TGI, String>,
+}
+
+/// A sim in a source, and its outfits.
+struct Sim {
+    /// From the NMAP names of its outfits, see [`outfit_owner`]. `None` gathers the outfits that
+    /// aren't named.
+    name: Option<String>,
+    outfits: Vec<Outfit>,
+}
+
+impl Sim {
+    /// The CC keys used by any of the sim's outfits.
+    fn parts(&self) -> HashSet<TGI> {
+        self.outfits.iter().flat_map(|o| o.parts.iter().copied()).collect()
+    }
+}
+
+/// One outfit of a sim (a SIMO), and the CC keys it uses.
+struct Outfit {
+    key: TGI,
+    parts: HashSet<TGI>,
+}
+
+/// Outfit categories, as they end the names of a sim's outfits.
+const OUTFIT_CATEGORIES: [&str; 8] = [
+    "Naked",
+    "Everyday",
+    "Formalwear",
+    "Sleepwear",
+    "Swimwear",
+    "Athletic",
+    "Singed",
+    "Career",
+];
+
+/// The sim an outfit belongs to, from the outfit's name: the name without the outfit category
+/// and number it ends with (`Bella Goth_Everyday_1` is Bella Goth's). A name without a category
+/// is taken to be the sim's name as it is.
+fn outfit_owner(name: &str) -> &str {
+    let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_' || c == ' ');
+    OUTFIT_CATEGORIES
+        .iter()
+        .find_map(|category| {
+            let start = trimmed.len().checked_sub(category.len())?;
+            let owner = trimmed.get(..start)?;
+            trimmed[start..].eq_ignore_ascii_case(category).then_some(owner)
+        })
+        .map(|owner| owner.trim_end_matches(['_', ' ']))
+        .filter(|owner| !owner.is_empty())
+        .unwrap_or(name)
+}
+
+/// Group `outfits` by the sim they belong to, going by their names in `nmap`.
+fn group_outfits(outfits: Vec<Outfit>, nmap: &BTreeMap<u64, String>) -> Vec<Sim> {
+    let mut sims: Vec<Sim> = Vec::new();
+    for outfit in outfits {
+        let name = nmap.get(&outfit.key.instance).map(|name| outfit_owner(name).to_string());
+        match sims.iter_mut().find(|sim| sim.name == name) {
+            Some(sim) => sim.outfits.push(outfit),
+            None => sims.push(Sim {
+                name,
+                outfits: vec![outfit],
+            }),
+        }
+    }
+    // the unnamed outfits go last
+    sims.sort_by_key(|sim| sim.name.is_none());
+    sims
+}
+
+/// Files in a directory that can be searched: packages, Sims3Packs, and the neighborhoods in a save.
+const INPUT_EXTENSIONS: [&str; 3] = ["package", "sims3pack", "nhd"];
+
+fn read_resource<'brand, T>(ctx: &mut impl FileCtx<'brand>, entry: &DBPFIndexEntry<'brand>) -> crate::error::Result<T>
+where
+    T: for<'a> BinRead<Args<'a> = ()>,
+{
+    let mut reader = entry.get_reader(ctx)?;
+    T::read_le(&mut reader).map_err(|e| crate::error::Error::from(e).with_tgi(entry.tgi()))
+}
+
+fn read_source<R: Read + Seek>(name: String, reader: R, global: &GlobalOpts) -> crate::error::Result<Source> {
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(reader, guard)?;
+    let nmap = package.gather_names(&mut reader).unwrap_or_else(|e| {
+        global.warn(format_args!("{}: {}", name, e));
+        Default::default()
+    });
+    let mut names = HashMap::new();
+    let mut outfits = Vec::new();
+    // patterns that come with the clothing that uses them, and the pattern XMLs themselves
+    let mut embedded = HashSet::new();
+    let mut patterns = HashSet::new();
+    for entry in &package.entries {
+        let warn = |e: crate::error::Error| global.warn(format_args!("{}: {}", name, e));
+        match num_traits::FromPrimitive::from_u32(entry.resource_type) {
+            Some(ResourceType::SIMO) => match read_resource::<SIMO>(&mut reader, entry) {
+                Ok(simo) => outfits.push(Outfit {
+                    key: entry.tgi(),
+                    parts: simo.tgis.into_iter().filter(is_cc_key).collect(),
+                }),
+                Err(e) => warn(e),
+            },
+            Some(ResourceType::CASP) => match read_resource::<CASP>(&mut reader, entry) {
+                Ok(casp) => {
+                    embedded.extend(casp.presets.iter().flat_map(|preset| preset.patterns()));
+                    names.insert(entry.tgi(), casp.name);
+                }
+                Err(e) => warn(e),
+            },
+            Some(ResourceType::XMLResource) => {
+                let mut xml = Vec::new();
+                match entry.get_reader(&mut reader).and_then(|mut r| Ok(r.read_to_end(&mut xml)?)) {
+                    Ok(_) if Pattern::parse(&String::from_utf8_lossy(&xml)).is_some() => {
+                        patterns.insert(entry.tgi());
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn(e),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut find = filter_tgi_into_map(package.entries.iter().map(|e| e.tgi()), true);
+    // A pattern that one of the clothes here comes with is found through the clothing. Looking for
+    // it too would also find every pattern package that has it, even though it isn't needed.
+    find.retain(|tgi| !(patterns.contains(tgi) && embedded.contains(tgi)));
+    for tgi in &find {
+        if let Some(nmap_name) = nmap.get(&tgi.instance) {
+            names.insert(*tgi, nmap_name.clone());
+        }
+    }
+    names.retain(|tgi, _| find.contains(tgi));
+    Ok(Source {
+        name,
+        find,
+        sims: group_outfits(outfits, &nmap),
+        names,
+    })
+}
+
+/// A package, or every package in a Sims3Pack.
+fn read_file(path: &Path, global: &GlobalOpts) -> crate::error::Result<Vec<Source>> {
+    let data = std::fs::read(path)?;
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    };
+    if !Sims3Pack::is_sims3pack(&data) {
+        return Ok(vec![read_source(name, Cursor::new(&data[..]), global)?]);
+    }
+    let pack = Sims3Pack::read(&mut &data[..])?;
+    pack.packages()
+        .map(|file| read_source(format!("{}/{}", name, file.name), Cursor::new(pack.file_data(file)?), global))
+        .collect()
+}
+
+/// The sources in the input, and how many files in it couldn't be read.
+fn read_input(path: &Path, global: &GlobalOpts) -> Result<(Vec<Source>, usize), CliError> {
+    if !path.is_dir() {
+        return Ok((read_file(path, global)?, 0));
+    }
+    // a save (Name.sims3), which has a .nhd for each world that's been visited, or exported households
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let extension = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+            INPUT_EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e))
+        })
+        .collect();
+    paths.sort();
+    let mut sources = Vec::new();
+    let mut failed = 0;
+    for path in paths {
+        match read_file(&path, global) {
+            Ok(found) => sources.extend(found),
+            Err(e) => {
+                global.warn(format_args!("{}: {}", path.display(), e));
+                failed += 1;
+            }
+        }
+    }
+    if sources.is_empty() {
+        return Err(format!("{} has no packages, saves or Sims3Packs in it", path.display()).into());
+    }
+    Ok((sources, failed))
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Print full paths instead of just the package filenames
+    #[arg(short = 'v', long = "full")]
+    pub full_path: bool,
+
+    /// List the CC used by each sim and each of its outfits, and by each package in the input
+    #[arg(short = 's', long)]
+    pub per_sim: bool,
+
+    /// Report which resources each package matched, which CC isn't installed, and which is
+    /// installed more than once
+    #[arg(short = 'r', long, conflicts_with = "per_sim")]
+    pub report: bool,
+
+    /// Merged package, Sims3Pack, save (the .sims3 folder) or folder of exported households
+    #[arg(name = "PACKAGE")]
+    pub input_file: PathBuf,
+
+    /// Directories to search for custom content in
+    #[arg(name = "DIR", required = true, num_args = 1..)]
+    pub search_dirs: Vec<PathBuf>,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let (sources, input_failed) = read_input(&opt.input_file, global)?;
+    let mut find: HashSet<TGI> = sources.iter().flat_map(|s| s.find.iter().copied()).collect();
+    find.extend(sources.iter().flat_map(|s| &s.sims).flat_map(|sim| sim.parts()));
+
+    // A directory with a Resource.cfg (like Mods) has just the packages the game loads from it.
+    let mut packages = Vec::new();
+    let mut loaded = Vec::new();
+    for dir in &opt.search_dirs {
+        let cfg_path = dir.join("Resource.cfg");
+        if cfg_path.is_file() {
+            match ResourceCfg::read_file(&cfg_path) {
+                Ok(cfg) => {
+                    let dir_packages = cfg.packages(dir);
+                    global.debug(format_args!(
+                        "{} loads {} packages",
+                        cfg_path.display(),
+                        dir_packages.len()
+                    ));
+                    loaded.extend(dir_packages);
+                    continue;
+                }
+                Err(e) => global.warn(format_args!("{}: {}, searching all of it", cfg_path.display(), e)),
+            }
+        }
+        packages.extend(global.find_packages(std::slice::from_ref(dir)));
+    }
+    packages.extend(loaded.iter().map(|p| p.path.clone()));
+    let mut seen = HashSet::new();
+    packages.retain(|path| seen.insert(path.clone()));
+
+    let matched = |path: PathBuf, keys: HashSet<TGI>| {
+        let matched: HashSet<TGI> = keys.intersection(&find).copied().collect();
+        (!matched.is_empty()).then_some((path, matched))
+    };
+    // each package that has some of the CC, with the keys it has
+    let (mut found, failed): (Vec<(PathBuf, HashSet<TGI>)>, usize) = match global.load_cache() {
+        Some(mut cache) => {
+            let failures = cache.refresh(&packages);
+            for (path, e) in &failures {
+                global.warn(format_args!("{}: {}", path.display(), e));
+            }
+            global.save_cache(&cache);
+            let found = packages
+                .into_iter()
+                .filter_map(|path| {
+                    global.debug(format_args!("Testing {}", path.display()));
+                    let keys = filter_tgi_into_map(cache.get(&path)?.tgis(), false);
+                    matched(path, keys)
+                })
+                .collect();
+            (found, failures.len())
+        }
+        None => {
+            let failed = AtomicUsize::new(0);
+            let found = packages
+                .into_par_iter()
+                .filter_map(|path| {
+                    global.debug(format_args!("Testing {}", path.display()));
+                    match package_keys(&path) {
+                        Ok(keys) => matched(path, keys),
+                        Err(e) => {
+                            global.warn(format_args!("{}: {}", path.display(), e));
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            None
+                        }
+                    }
+                })
+                .collect();
+            (found, failed.into_inner())
+        }
+    };
+    let resolver = (!loaded.is_empty()).then(|| {
+        let keys: HashMap<&Path, &HashSet<TGI>> = found.iter().map(|(p, k)| (p.as_path(), k)).collect();
+        Resolver::new(loaded.iter().map(|package| {
+            let keys: Vec<TGI> = keys
+                .get(package.path.as_path())
+                .map_or_else(Vec::new, |k| k.iter().copied().collect());
+            (package.clone(), keys)
+        }))
+    });
+    // Of the packages a Resource.cfg loads, only the one the game uses a resource from has it. The
+    // report shows every package that has it instead, so that conflicts between them show up.
+    if let Some(resolver) = resolver.as_ref().filter(|_| !opt.report) {
+        let loaded: HashSet<&Path> = loaded.iter().map(|p| p.path.as_path()).collect();
+        for (path, keys) in &mut found {
+            if loaded.contains(path.as_path()) {
+                keys.retain(|tgi| resolver.winner(tgi).map_or(true, |w| w.path == *path));
+            }
+        }
+        found.retain(|(_, keys)| !keys.is_empty());
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // TODO: print relative path
+    let name = |path: &Path| match path.file_name() {
+        Some(name) if !opt.full_path => name.to_string_lossy().into_owned(),
+        _ => path.to_string_lossy().into_owned(),
+    };
+    let using = |keys: &HashSet<TGI>| -> Vec<String> {
+        found
+            .iter()
+            .filter(|(_, matched)| !matched.is_disjoint(keys))
+            .map(|(path, _)| name(path))
+            .collect()
+    };
+
+    if opt.report {
+        report(&sources, &found, &name, resolver.as_ref(), global)?;
+    } else if opt.per_sim {
+        let sim_name = |sim: &Sim| sim.name.clone().unwrap_or_else(|| "(unnamed outfits)".to_string());
+        if global.json {
+            let out: Vec<_> = sources
+                .iter()
+                .map(|source| {
+                    let sims: Vec<_> = source
+                        .sims
+                        .iter()
+                        .map(|sim| {
+                            let outfits: Vec<_> = sim
+                                .outfits
+                                .iter()
+                                .map(|o| json!({ "outfit": o.key.to_string(), "packages": using(&o.parts) }))
+                                .collect();
+                            json!({ "sim": sim.name, "packages": using(&sim.parts()), "outfits": outfits })
+                        })
+                        .collect();
+                    json!({ "source": source.name, "packages": using(&source.find), "sims": sims })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        } else {
+            for source in &sources {
+                println!("{}", source.name);
+                for package in using(&source.find) {
+                    println!("  {}", package);
+                }
+                for sim in &source.sims {
+                    println!("  sim {}", sim_name(sim));
+                    for package in using(&sim.parts()) {
+                        println!("    {}", package);
+                    }
+                    for outfit in &sim.outfits {
+                        println!("    outfit {}", outfit.key);
+                        for package in using(&outfit.parts) {
+                            println!("      {}", package);
+                        }
+                    }
+                }
+            }
+        }
+    } else {
+        let names: Vec<String> = found.iter().map(|(path, _)| name(path)).collect();
+        if global.json {
+            println!("{}", serde_json::to_string_pretty(&names)?);
+        } else {
+            for name in names {
+                println!("{}", name);
+            }
+        }
+    }
+
+    partial(failed + input_failed)
+}
+
+/// Print the `--report`: what each package matched, what matched nothing, and what matched more than once.
+/// With a `resolver` (from a Resource.cfg), resources in more than one package say which one the game uses.
+fn report(
+    sources: &[Source],
+    found: &[(PathBuf, HashSet<TGI>)],
+    name: &dyn Fn(&Path) -> String,
+    resolver: Option<&Resolver>,
+    global: &GlobalOpts,
+) -> CliResult {
+    // every key in the input, with the first name it has and the source it's from
+    let mut keys: BTreeMap<TGI, (Option<&str>, &str)> = BTreeMap::new();
+    for source in sources {
+        for tgi in &source.find {
+            let entry = keys.entry(*tgi).or_insert((None, source.name.as_str()));
+            entry.0 = entry.0.or(source.names.get(tgi).map(String::as_str));
+        }
+    }
+    let describe = |tgi: &TGI| {
+        json!({
+            "tgi": tgi.to_string(),
+            "type": type_name(tgi.resource_type),
+            "name": keys.get(tgi).and_then(|(name, _)| *name),
+        })
+    };
+
+    // every key that was found, with the packages it was found in
+    let mut providers: BTreeMap<TGI, Vec<String>> = BTreeMap::new();
+    let matched: Vec<_> = found
+        .iter()
+        .map(|(path, matched)| {
+            let mut tgis: Vec<&TGI> = matched.iter().collect();
+            tgis.sort();
+            for tgi in &tgis {
+                providers.entry(**tgi).or_default().push(name(path));
+            }
+            (name(path), tgis)
+        })
+        .collect();
+    // outfits also refer to the game's own CAS parts, so only what's in the input itself counts as missing
+    let missing: Vec<(&TGI, &str)> = keys
+        .iter()
+        .filter(|(tgi, _)| !providers.contains_key(*tgi))
+        .map(|(tgi, (_, source))| (tgi, *source))
+        .collect();
+    let ambiguous: Vec<(&TGI, &Vec<String>)> =
+        providers.iter().filter(|(_, packages)| packages.len() > 1).collect();
+    let winner = |tgi: &TGI| resolver.and_then(|r| r.winner(tgi)).map(|w| name(&w.path));
+
+    if global.json {
+        let out = json!({
+            "matched": matched.iter().map(|(package, tgis)| json!({
+                "package": package,
+                "resources": tgis.iter().map(|tgi| describe(*tgi)).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "missing": missing.iter().map(|(tgi, source)| {
+                let mut resource = describe(*tgi);
+                resource["source"] = json!(source);
+                resource
+            }).collect::<Vec<_>>(),
+            "ambiguous": ambiguous.iter().map(|(tgi, packages)| {
+                let mut resource = describe(*tgi);
+                resource["packages"] = json!(packages);
+                resource["winner"] = json!(winner(tgi));
+                resource
+            }).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    let line = |tgi: &TGI| {
+        let name = keys.get(tgi).and_then(|(name, _)| *name).unwrap_or_default();
+        format!("{:<11} {}  {}", type_name(tgi.resource_type), tgi, name)
+    };
+    println!("Matched ({} packages):", matched.len());
+    for (package, tgis) in &matched {
+        println!("  {}", package);
+        for tgi in tgis {
+            println!("    {}", line(*tgi));
+        }
+    }
+    println!("Not installed ({} resources):", missing.len());
+    for (tgi, source) in &missing {
+        println!("  {}  (in {})", line(*tgi), source);
+    }
+    println!("In more than one package ({} resources):", ambiguous.len());
+    for (tgi, packages) in &ambiguous {
+        println!("  {}", line(*tgi));
+        let winner = winner(tgi);
+        for package in packages.iter() {
+            if winner.as_ref() == Some(package) {
+                println!("    {}  (used by the game)", package);
+            } else {
+                println!("    {}", package);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outfit_owners() {
+        assert_eq!(outfit_owner("Bella Goth_Everyday_1"), "Bella Goth");
+        assert_eq!(outfit_owner("Bella Goth formalwear 2"), "Bella Goth");
+        assert_eq!(outfit_owner("Bella Goth"), "Bella Goth");
+        assert_eq!(outfit_owner("Sim 2"), "Sim 2");
+        assert_eq!(outfit_owner("Everyday_0"), "Everyday_0");
+    }
+
+    #[test]
+    fn outfits_are_grouped_by_sim() {
+        let outfit = |instance| Outfit {
+            key: TGI::new(ResourceType::SIMO as u32, 0, instance),
+            parts: HashSet::from([TGI::new(ResourceType::CASP as u32, 0, instance)]),
+        };
+        let nmap = BTreeMap::from([
+            (1, "Bella Goth_Everyday_0".to_string()),
+            (2, "Mortimer Goth_Everyday_0".to_string()),
+            (3, "Bella Goth_Sleepwear_0".to_string()),
+        ]);
+        let sims = group_outfits((0..4).map(outfit).collect(), &nmap);
+        let names: Vec<_> = sims.iter().map(|sim| sim.name.as_deref()).collect();
+        assert_eq!(names, [Some("Bella Goth"), Some("Mortimer Goth"), None]);
+        assert_eq!(sims[0].outfits.iter().map(|o| o.key.instance).collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(sims[0].parts().len(), 2);
+        assert_eq!(sims[2].outfits[0].key.instance, 0);
+    }
+}
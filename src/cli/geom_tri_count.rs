@@ -0,0 +1,334 @@
+//! `geom_tri_count`: count the polygons of every CAS part, LOD by LOD.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsStr,
+    fs::File,
+    io::{BufWriter, Write},
+    panic::catch_unwind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rayon::prelude::*;
+use serde_json::json;
+
+use super::{partial, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::{casp::CASP, geom::Geometry, rcol::RCOL, tgi::TGI, vpxy::VPXY};
+use crate::dbpf::{filetypes::ResourceType, DBPFIndexEntry, DBPFReader, FileCtx};
+
+use binrw::{error::ContextExt, BinRead};
+
+#[derive(Clone, Debug, Default)]
+struct LodCount {
+    /// `None` for GEOMs that no VPXY refers to.
+    lod: Option<u8>,
+    geoms: usize,
+    vertices: usize,
+    triangles: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    package: String,
+    name: String,
+    casp: Option<TGI>,
+    age_gender: String,
+    lods: Vec<LodCount>,
+    warnings: Vec<String>,
+}
+
+fn read_rcol<'brand>(ctx: &mut impl FileCtx<'brand>, entry: &DBPFIndexEntry<'brand>) -> Result<RCOL, binrw::Error> {
+    RCOL::read_le(&mut entry.get_reader(ctx)?)
+        .with_message("parsing RCOL")
+        .with_context(entry.tgi())
+}
+
+fn geom_information(path: &Path, global: &GlobalOpts) -> Result<Vec<Item>, binrw::Error> {
+    let file = File::open(path)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(std::io::BufReader::new(file), guard)?;
+    let names = package.gather_names(&mut reader).unwrap_or_else(|e| {
+        global.warn(format_args!("{}: {}", path.display(), e));
+        Default::default()
+    });
+    let filename = path.file_name().unwrap().to_string_lossy().into_owned();
+
+    let by_tgi: HashMap<TGI, &DBPFIndexEntry> = package.entries.iter().map(|e| (e.tgi(), e)).collect();
+
+    // (vertices, triangles) of every GEOM in the package that could be read
+    let mut geoms = BTreeMap::new();
+    for entry in package
+        .entries
+        .iter()
+        .filter(|entry| entry.resource_type == ResourceType::GEOM as u32)
+        // Workaround: I've found *one* file that has LODs "present" but a size of 0, therefore it immediately fails to read anything.
+        //  This prevents it from printing an error message for this case.
+        .filter(|entry| entry.chunk.memsize() != 0)
+    {
+        let geom = read_rcol(&mut reader, entry).and_then(|rcol| {
+            Geometry::from_rcol(&rcol)
+                .with_message("parsing GEOM")
+                .with_context(entry.tgi())
+        });
+        match geom {
+            Ok(geom) => {
+                geoms.insert(entry.tgi(), (geom.vertex_count as usize, geom.triangle_count()));
+            }
+            Err(e) => global.warn(e.with_message(path.to_string_lossy().into_owned())),
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut referenced = HashSet::new();
+    for entry in package.entries.iter().filter(|e| e.resource_type == ResourceType::CASP as u32) {
+        let casp = entry
+            .get_reader(&mut reader)
+            .map_err(binrw::Error::from)
+            .and_then(|mut r| CASP::read_le(&mut r))
+            .with_message("parsing CASP")
+            .with_context(entry.tgi());
+        let casp = match casp {
+            Ok(casp) => casp,
+            Err(e) => {
+                global.warn(e.with_message(path.to_string_lossy().into_owned()));
+                continue;
+            }
+        };
+
+        let mut item = Item {
+            package: filename.clone(),
+            name: names.get(&entry.instance).cloned().unwrap_or_else(|| casp.name.clone()),
+            casp: Some(entry.tgi()),
+            age_gender: casp.age_gender.to_string(),
+            lods: Vec::new(),
+            warnings: Vec::new(),
+        };
+        for vpxy_tgi in casp.vpxys() {
+            let Some(vpxy_entry) = by_tgi.get(vpxy_tgi) else {
+                // recolours of game items point at the game's VPXY
+                item.warnings.push(format!("VPXY {} is not in this package", vpxy_tgi));
+                continue;
+            };
+            let vpxy = match read_rcol(&mut reader, vpxy_entry)
+                .and_then(|rcol| VPXY::from_rcol(&rcol).with_message("parsing VPXY").with_context(*vpxy_tgi))
+            {
+                Ok(vpxy) => vpxy,
+                Err(e) => {
+                    item.warnings.push(format!("unreadable VPXY: {}", e));
+                    continue;
+                }
+            };
+            for id in vpxy.lod_ids() {
+                let mut count = LodCount {
+                    lod: Some(id),
+                    ..Default::default()
+                };
+                for tgi in vpxy.lod(id) {
+                    referenced.insert(*tgi);
+                    if let Some((vertices, triangles)) = geoms.get(tgi) {
+                        count.geoms += 1;
+                        count.vertices += vertices;
+                        count.triangles += triangles;
+                    }
+                }
+                match item.lods.iter_mut().find(|l| l.lod == Some(id)) {
+                    Some(l) => {
+                        l.geoms += count.geoms;
+                        l.vertices += count.vertices;
+                        l.triangles += count.triangles;
+                    }
+                    None => item.lods.push(count),
+                }
+            }
+        }
+        item.lods.sort_by_key(|l| l.lod);
+        items.push(item);
+    }
+
+    let unreferenced: Vec<_> = geoms
+        .iter()
+        .filter(|(tgi, _)| !referenced.contains(tgi))
+        .map(|(_, &(vertices, triangles))| LodCount {
+            lod: None,
+            geoms: 1,
+            vertices,
+            triangles,
+        })
+        .collect();
+    if !unreferenced.is_empty() {
+        items.push(Item {
+            package: filename,
+            name: "(GEOMs without a CASP)".to_string(),
+            casp: None,
+            age_gender: String::new(),
+            lods: unreferenced,
+            warnings: Vec::new(),
+        });
+    }
+
+    Ok(items)
+}
+
+fn parse_threshold(s: &str) -> Result<(u8, usize), String> {
+    let (lod, triangles) = s.split_once('=').ok_or("expected LOD=TRIANGLES")?;
+    let lod = lod.strip_prefix("LOD").or_else(|| lod.strip_prefix("lod")).unwrap_or(lod);
+    Ok((
+        lod.parse().map_err(|_| format!("bad LOD '{}'", lod))?,
+        triangles.parse().map_err(|_| format!("bad triangle count '{}'", triangles))?,
+    ))
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Write a report of every item to this file
+    #[arg(short, long, visible_alias = "summary", short_alias = 's')]
+    pub output: Option<PathBuf>,
+
+    /// Report format, guessed from the output's extension if not given
+    #[arg(short, long, value_enum)]
+    pub format: Option<Format>,
+
+    /// Warn about items with more than TRIANGLES triangles in a LOD, like `0=12000`. Can be repeated
+    #[arg(short, long = "warn", value_name = "LOD=TRIANGLES", value_parser = parse_threshold)]
+    pub warn: Vec<(u8, usize)>,
+
+    /// Packages, or directories to search for packages
+    #[arg(num_args = 1..)]
+    pub paths: Vec<PathBuf>,
+}
+
+fn write_csv(output: &mut impl Write, items: &[Item]) -> std::io::Result<()> {
+    writeln!(output, "Package, Item, CASP, Age/Gender, LOD, GEOMs, Vertices, Triangles, Warnings")?;
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    for item in items {
+        // items whose meshes aren't in the package still get a row
+        let empty = [LodCount::default()];
+        let lods = if item.lods.is_empty() { &empty[..] } else { &item.lods };
+        for lod in lods {
+            writeln!(
+                output,
+                "{}, {}, {}, {}, {}, {}, {}, {}, {}",
+                quote(&item.package),
+                quote(&item.name),
+                item.casp.map(|t| t.to_string()).unwrap_or_default(),
+                item.age_gender,
+                lod.lod.map(|l| l.to_string()).unwrap_or_default(),
+                lod.geoms,
+                lod.vertices,
+                lod.triangles,
+                quote(&item.warnings.join("; ")),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json(output: &mut impl Write, items: &[Item], by_age_gender: &BTreeMap<String, (usize, usize)>) -> std::io::Result<()> {
+    let items: Vec<_> = items
+        .iter()
+        .map(|item| {
+            json!({
+                "package": item.package,
+                "name": item.name,
+                "casp": item.casp.map(|t| t.to_string()),
+                "age_gender": item.age_gender,
+                "lods": item.lods.iter().map(|l| json!({
+                    "lod": l.lod,
+                    "geoms": l.geoms,
+                    "vertices": l.vertices,
+                    "triangles": l.triangles,
+                })).collect::<Vec<_>>(),
+                "warnings": item.warnings,
+            })
+        })
+        .collect();
+    let by_age_gender: serde_json::Map<_, _> = by_age_gender
+        .iter()
+        .map(|(k, &(count, max))| (k.clone(), json!({ "items": count, "max_lod0_triangles": max })))
+        .collect();
+    serde_json::to_writer_pretty(&mut *output, &json!({ "items": items, "by_age_gender": by_age_gender }))?;
+    writeln!(output)
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let failed = AtomicUsize::new(0);
+    let mut items: Vec<Item> = global
+        .find_packages(&opt.paths)
+        .into_par_iter()
+        .flat_map_iter(|path| {
+            let res = catch_unwind(|| geom_information(&path, global));
+            let error = match res {
+                Ok(Ok(items)) => return items,
+                Err(unwind) => format!("caught panic while parsing {}: {:?}", path.display(), unwind),
+                Ok(Err(err)) => format!("error while parsing {}: {}", path.display(), err),
+            };
+            global.warn(error);
+            failed.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        })
+        .collect();
+    items.sort_by(|a, b| (&a.package, &a.name).cmp(&(&b.package, &b.name)));
+
+    for item in &mut items {
+        for &(lod, limit) in &opt.warn {
+            if let Some(l) = item.lods.iter().find(|l| l.lod == Some(lod) && l.triangles > limit) {
+                item.warnings.push(format!("LOD{} has {} triangles (limit {})", lod, l.triangles, limit));
+            }
+        }
+    }
+
+    // (items, highest LOD0 triangle count) per age/gender
+    let mut by_age_gender: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for item in items.iter().filter(|i| i.casp.is_some()) {
+        let entry = by_age_gender.entry(item.age_gender.clone()).or_default();
+        entry.0 += 1;
+        let lod0 = item.lods.iter().find(|l| l.lod == Some(0)).map_or(0, |l| l.triangles);
+        entry.1 = entry.1.max(lod0);
+    }
+
+    if global.json {
+        write_json(&mut std::io::stdout().lock(), &items, &by_age_gender)?;
+    } else {
+        for item in &items {
+            let lods: Vec<_> = item
+                .lods
+                .iter()
+                .map(|l| match l.lod {
+                    Some(lod) => format!("LOD{}: {} ({} vertices)", lod, l.triangles, l.vertices),
+                    None => format!("{} ({} vertices)", l.triangles, l.vertices),
+                })
+                .collect();
+            println!("{} -- {} [{}] -- Polys: {}", item.package, item.name, item.age_gender, lods.join(", "));
+            for warning in &item.warnings {
+                println!("  WARNING: {}", warning);
+            }
+        }
+        if !by_age_gender.is_empty() {
+            println!();
+            for (age_gender, (count, max)) in &by_age_gender {
+                println!("{}: {} items, at most {} LOD0 polys", age_gender, count, max);
+            }
+        }
+    }
+
+    if let Some(path) = &opt.output {
+        let format = opt.format.unwrap_or(match path.extension().and_then(OsStr::to_str) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Csv,
+        });
+        let mut output = BufWriter::new(File::create(path)?);
+        match format {
+            Format::Csv => write_csv(&mut output, &items),
+            Format::Json => write_json(&mut output, &items, &by_age_gender),
+        }?;
+    }
+
+    partial(failed.into_inner())
+}
@@ -0,0 +1,78 @@
+//! `import_mesh`: replace the mesh of a GEOM with an OBJ or glTF file.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use super::{CliResult, GlobalOpts};
+use crate::dbpf::filetypes::geom::Geometry;
+use crate::dbpf::filetypes::rcol::RCOL;
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::DBPFReader;
+use crate::mesh::{gltf, obj, replace_geometry, Mesh};
+
+use binrw::{error::ContextExt, BinRead};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Package containing the GEOM
+    pub package: PathBuf,
+
+    /// The GEOM to replace, as TTTTTTTT:GGGGGGGG:IIIIIIIIIIIIIIII
+    pub tgi: TGI,
+
+    /// Mesh to import (.obj or .glb). All of its meshes are merged into one
+    pub mesh: PathBuf,
+
+    /// Where to write the modified package
+    pub output: PathBuf,
+
+    /// Which LOD of the mesh file to import, if it has several
+    #[arg(short, long, default_value_t = 0)]
+    pub lod: usize,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    if opt.tgi.resource_type != ResourceType::GEOM as u32 {
+        return Err(format!("{} is not a GEOM", opt.tgi).into());
+    }
+
+    let lods = match opt.mesh.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("obj") => obj::read_obj(BufReader::new(File::open(&opt.mesh)?))?,
+        Some(ext) if ext.eq_ignore_ascii_case("glb") => gltf::read_glb(&std::fs::read(&opt.mesh)?)?,
+        _ => return Err("mesh must be an .obj or .glb file".into()),
+    };
+    let lod = lods
+        .get(opt.lod)
+        .ok_or_else(|| format!("{} has no LOD {}", opt.mesh.display(), opt.lod))?;
+    let mesh = Mesh::merge(&lod.name, &lod.meshes)?;
+
+    let file = File::open(&opt.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, mut package) = DBPFReader::parse(BufReader::new(file), guard)?;
+
+    let entry = package
+        .entries
+        .iter_mut()
+        .find(|e| e.tgi() == opt.tgi)
+        .ok_or_else(|| format!("{} is not in the package", opt.tgi))?;
+    let mut rcol = RCOL::read_le(&mut entry.get_reader(&mut reader)?).with_context(entry.tgi())?;
+    let mut geom = Geometry::from_rcol(&rcol).with_context(entry.tgi())?;
+
+    replace_geometry(&mut geom, &mesh)?;
+    geom.store_into(&mut rcol)?;
+    entry.chunk = rcol.to_chunk_handle()?;
+    global.status(format_args!(
+        "{}: {} vertices, {} triangles",
+        opt.tgi,
+        geom.vertex_count,
+        geom.triangle_count()
+    ));
+
+    // written to memory first, so the output can be the package itself
+    let mut out = std::io::Cursor::new(Vec::new());
+    package.write_to(&mut out, reader)?;
+    std::fs::write(&opt.output, out.into_inner())?;
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! `info` and `ls`: what's in a package.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use super::{parse_hex, type_name, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::DBPFReader;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// Order in the package's index
+    Index,
+    Tgi,
+    Type,
+    Size,
+    Ratio,
+    Name,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct InfoArgs {
+    pub package: PathBuf,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LsArgs {
+    pub package: PathBuf,
+
+    /// Only list resources of this type, by name (like `CASP`) or in hex. Can be repeated
+    #[arg(short = 't', long = "type", value_name = "TYPE")]
+    pub types: Vec<String>,
+
+    /// Only list resources in this group, in hex
+    #[arg(short, long, value_parser = parse_hex)]
+    pub group: Option<u32>,
+
+    /// Only list resources whose NMAP name contains this text (ignoring case)
+    #[arg(short, long)]
+    pub name: Option<String>,
+
+    #[arg(short, long, value_enum, default_value_t = SortKey::Index)]
+    pub sort: SortKey,
+
+    /// Reverse the sort order
+    #[arg(short, long)]
+    pub reverse: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+struct Row {
+    index: usize,
+    tgi: TGI,
+    type_name: String,
+    filesize: u32,
+    memsize: u32,
+    compressed: bool,
+    unk1: bool,
+    unk2: u16,
+    name: Option<String>,
+}
+
+impl Row {
+    /// Compressed size as a fraction of the uncompressed size.
+    fn ratio(&self) -> f64 {
+        if self.memsize == 0 {
+            1.0
+        } else {
+            self.filesize as f64 / self.memsize as f64
+        }
+    }
+}
+
+pub fn info(args: &InfoArgs, global: &GlobalOpts) -> CliResult {
+    let format = if global.json { Format::Json } else { args.format };
+    let package_path = &args.package;
+    let file = File::open(package_path)?;
+    let file_size = file.metadata()?.len();
+    generativity::make_guard!(guard);
+    let (_, package) = DBPFReader::parse(BufReader::new(file), guard)?;
+
+    // type name -> (resources, total uncompressed size)
+    let mut types: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for entry in &package.entries {
+        let t = types.entry(type_name(entry.resource_type)).or_default();
+        t.0 += 1;
+        t.1 += entry.chunk.memsize() as u64;
+    }
+    let compressed = package.entries.iter().filter(|e| e.chunk.is_compressed()).count();
+    let filesize: u64 = package.entries.iter().map(|e| e.chunk.filesize() as u64).sum();
+    let memsize: u64 = package.entries.iter().map(|e| e.chunk.memsize() as u64).sum();
+
+    match format {
+        Format::Text => {
+            println!("{}", package_path.display());
+            println!("  File size:          {}", file_size);
+            println!("  Flags:              {:08X}", package.maybe_flags);
+            println!("  Created:            {}", package.created_timestamp);
+            println!("  Modified:           {}", package.modified_timestamp);
+            println!("  Index version:      {}", package.index_version);
+            println!("  Resources:          {} ({} compressed)", package.entries.len(), compressed);
+            println!("  Stored size:        {}", filesize);
+            println!("  Uncompressed size:  {}", memsize);
+            println!();
+            for (name, (count, size)) in &types {
+                println!("  {:<16} {:>6} {:>12}", name, count, size);
+            }
+        }
+        Format::Json => {
+            let types: serde_json::Map<_, _> = types
+                .iter()
+                .map(|(name, (count, size))| (name.clone(), json!({ "count": count, "size": size })))
+                .collect();
+            let info = json!({
+                "path": package_path.to_string_lossy(),
+                "file_size": file_size,
+                "maybe_flags": package.maybe_flags,
+                "created_timestamp": package.created_timestamp,
+                "modified_timestamp": package.modified_timestamp,
+                "index_version": package.index_version,
+                "resources": package.entries.len(),
+                "compressed": compressed,
+                "stored_size": filesize,
+                "uncompressed_size": memsize,
+                "types": types,
+            });
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        }
+        Format::Csv => {
+            println!("Type, Resources, Uncompressed Size");
+            for (name, (count, size)) in &types {
+                println!("{}, {}, {}", name, count, size);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn ls(args: &LsArgs, global: &GlobalOpts) -> CliResult {
+    let format = if global.json { Format::Json } else { args.format };
+    let file = File::open(&args.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(BufReader::new(file), guard)?;
+    let names = package.gather_names(&mut reader).unwrap_or_else(|e| {
+        global.warn(format_args!("{}: {}", args.package.display(), e));
+        Default::default()
+    });
+
+    let name_filter = args.name.as_ref().map(|n| n.to_lowercase());
+    let mut rows: Vec<Row> = package
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, e)| Row {
+            index,
+            tgi: e.tgi(),
+            type_name: type_name(e.resource_type),
+            filesize: e.chunk.filesize(),
+            memsize: e.chunk.memsize(),
+            compressed: e.chunk.is_compressed(),
+            unk1: e.unk1,
+            unk2: e.unk2,
+            name: names.get(&e.instance).cloned(),
+        })
+        .filter(|r| {
+            args.types.is_empty()
+                || args.types.iter().any(|t| {
+                    t.eq_ignore_ascii_case(&r.type_name)
+                        || u32::from_str_radix(t.trim_start_matches("0x"), 16) == Ok(r.tgi.resource_type)
+                })
+        })
+        .filter(|r| args.group.is_none_or(|g| g == r.tgi.resource_group))
+        .filter(|r| match &name_filter {
+            Some(filter) => r.name.as_ref().is_some_and(|n| n.to_lowercase().contains(filter)),
+            None => true,
+        })
+        .collect();
+
+    match args.sort {
+        SortKey::Index => {}
+        SortKey::Tgi => rows.sort_by_key(|r| r.tgi),
+        SortKey::Type => rows.sort_by(|a, b| (&a.type_name, a.tgi).cmp(&(&b.type_name, b.tgi))),
+        SortKey::Size => rows.sort_by_key(|r| r.memsize),
+        SortKey::Ratio => rows.sort_by(|a, b| a.ratio().total_cmp(&b.ratio())),
+        SortKey::Name => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+    if args.reverse {
+        rows.reverse();
+    }
+
+    let mut out = std::io::stdout().lock();
+    match format {
+        Format::Text => {
+            writeln!(
+                out,
+                "{:<14} {:<8} {:<16} {:>10} {:>10} {:>6} {} {:>4} Name",
+                "Type", "Group", "Instance", "Stored", "Size", "Ratio", "U", "Unk2"
+            )?;
+            for r in &rows {
+                writeln!(
+                    out,
+                    "{:<14} {:08X} {:016X} {:>10} {:>10} {:>5.1}% {} {:>4X} {}",
+                    r.type_name,
+                    r.tgi.resource_group,
+                    r.tgi.instance,
+                    r.filesize,
+                    r.memsize,
+                    r.ratio() * 100.0,
+                    if r.unk1 { "U" } else { "-" },
+                    r.unk2,
+                    r.name.as_deref().unwrap_or(""),
+                )?;
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|r| {
+                    json!({
+                        "index": r.index,
+                        "tgi": r.tgi.to_string(),
+                        "type": r.type_name,
+                        "compressed": r.compressed,
+                        "stored_size": r.filesize,
+                        "uncompressed_size": r.memsize,
+                        "ratio": r.ratio(),
+                        "unk1": r.unk1,
+                        "unk2": r.unk2,
+                        "name": r.name,
+                    })
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?;
+        }
+        Format::Csv => {
+            let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+            writeln!(out, "Index, Type, TGI, Compressed, Stored Size, Uncompressed Size, Ratio, Unk1, Unk2, Name")?;
+            for r in &rows {
+                writeln!(
+                    out,
+                    "{}, {}, {}, {}, {}, {}, {:.3}, {}, {}, {}",
+                    r.index,
+                    r.type_name,
+                    r.tgi,
+                    r.compressed,
+                    r.filesize,
+                    r.memsize,
+                    r.ratio(),
+                    r.unk1,
+                    r.unk2,
+                    quote(r.name.as_deref().unwrap_or("")),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
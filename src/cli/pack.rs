@@ -0,0 +1,108 @@
+//! `pack`: build a package from a directory made by `unpack` or S3PE.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use super::{CliResult, GlobalOpts};
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::{ChunkHandle, DBPFIndexEntry, DBPF};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Directory of S3_T_G_I files
+    pub input: PathBuf,
+
+    /// Where to write the package
+    pub output: PathBuf,
+
+    /// Compress resources that aren't listed in package.json
+    #[arg(short, long)]
+    pub compress: bool,
+}
+
+/// How to store a resource, from package.json.
+struct EntryInfo {
+    compressed: bool,
+    unk1: bool,
+    unk2: u16,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    // filename -> key, in filename order
+    let mut files = BTreeMap::new();
+    for dir_entry in std::fs::read_dir(&opt.input)? {
+        let filename = dir_entry?.file_name().to_string_lossy().into_owned();
+        match TGI::from_s3pe_filename(&filename) {
+            Some((tgi, _)) => {
+                files.insert(filename, tgi);
+            }
+            None if filename == "package.json" => {}
+            None => global.warn(format_args!("skipping {}, which isn't named S3_T_G_I", filename)),
+        }
+    }
+
+    let mut package = DBPF::new();
+    let mut order = Vec::new();
+    let mut info = BTreeMap::new();
+    let metadata_path = opt.input.join("package.json");
+    if metadata_path.exists() {
+        let metadata: Value = serde_json::from_reader(BufReader::new(File::open(&metadata_path)?))?;
+        let field = |v: &Value, name: &str| v.get(name).and_then(Value::as_u64).unwrap_or(0);
+        package.maybe_flags = field(&metadata, "maybe_flags") as u32;
+        package.created_timestamp = field(&metadata, "created_timestamp") as u32;
+        package.modified_timestamp = field(&metadata, "modified_timestamp") as u32;
+
+        for entry in metadata["entries"].as_array().into_iter().flatten() {
+            let Some(file) = entry["file"].as_str() else {
+                continue;
+            };
+            if !files.contains_key(file) {
+                global.warn(format_args!("{} is listed in package.json but missing, leaving it out", file));
+                continue;
+            }
+            order.push(file.to_owned());
+            info.insert(
+                file.to_owned(),
+                EntryInfo {
+                    compressed: entry["compressed"].as_bool().unwrap_or(opt.compress),
+                    unk1: entry["unk1"].as_bool().unwrap_or(false),
+                    unk2: field(entry, "unk2") as u16,
+                },
+            );
+        }
+    }
+    // new files go after the ones package.json knows about
+    let new_files: Vec<_> = files.keys().filter(|f| !info.contains_key(*f)).cloned().collect();
+    order.extend(new_files);
+
+    for filename in &order {
+        let tgi = files[filename];
+        let info = info.remove(filename).unwrap_or(EntryInfo {
+            compressed: opt.compress,
+            unk1: false,
+            unk2: 1,
+        });
+        package.entries.push(DBPFIndexEntry {
+            resource_type: tgi.resource_type,
+            resource_group: tgi.resource_group,
+            instance: tgi.instance,
+            unk1: info.unk1,
+            unk2: info.unk2,
+            chunk: ChunkHandle::Dirty {
+                decompressed: std::fs::read(opt.input.join(filename))?,
+                should_compress: info.compressed,
+            },
+        });
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    package.write_to(&mut out, ())?;
+    std::fs::write(&opt.output, out.into_inner())?;
+
+    global.status(format_args!("Packed {} resources into {}", package.entries.len(), opt.output.display()));
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! `package_names`: rename packages after the name of the CC inside them.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use super::{partial, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::DBPFReader;
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Packages, or directories to search for packages
+    #[arg(required = true, num_args = 1..)]
+    pub packages: Vec<PathBuf>,
+
+    /// Only print what would be renamed
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+fn find_name(package_path: &Path) -> Result<Option<String>, binrw::Error> {
+    let file = File::open(package_path)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(std::io::BufReader::new(file), guard)?;
+
+    let name_map = package.gather_names(&mut reader)?;
+    Ok(package
+        .entries
+        .iter()
+        .filter(|e| {
+            e.resource_type == ResourceType::CASP as u32
+                || e.resource_type == ResourceType::OBJD as u32
+                || e.resource_type == ResourceType::NMAP as u32
+                || e.resource_type == 0xB52F5055 // FBLN
+        })
+        .find_map(|e| name_map.get(&e.instance))
+        .cloned())
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for package_path in global.find_packages(&opt.packages) {
+        let name = match find_name(&package_path) {
+            Ok(Some(name)) => name,
+            Ok(None) => {
+                global.warn(format_args!("unable to find a name for '{}'", package_path.display()));
+                continue;
+            }
+            Err(e) => {
+                global.warn(format_args!("error while parsing {}: {}", package_path.display(), e));
+                failed += 1;
+                continue;
+            }
+        };
+
+        let new_path = package_path.with_file_name(format!("{}.package", name));
+        let renamed = if new_path.exists() {
+            global.warn(format_args!(
+                "'{}' -> '{}' but destination already exists! Ignoring.",
+                package_path.display(),
+                new_path.display()
+            ));
+            false
+        } else {
+            if !global.json {
+                println!("'{}' -> '{}'", package_path.display(), new_path.display());
+            }
+            if !opt.dry_run {
+                std::fs::rename(&package_path, &new_path)?;
+            }
+            !opt.dry_run
+        };
+        results.push(json!({
+            "from": package_path.to_string_lossy(),
+            "to": new_path.to_string_lossy(),
+            "renamed": renamed,
+        }));
+    }
+
+    if global.json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    }
+    partial(failed)
+}
@@ -0,0 +1,98 @@
+//! `repair`: save what can be saved from a damaged or half-downloaded package.
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use super::{type_name, CliResult, GlobalOpts};
+use crate::dbpf::salvage::{salvage, IndexState, Recovery};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The damaged package
+    pub input: PathBuf,
+
+    /// Where to write the repaired package
+    pub output: PathBuf,
+
+    /// Only report what could be recovered, without writing anything
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let data = std::fs::read(&opt.input)?;
+    let (package, report) = salvage(&data);
+
+    let index = match &report.index {
+        IndexState::Intact => "intact".to_string(),
+        IndexState::Partial { read, expected } => format!("partial: {} of {} entries", read, expected),
+        IndexState::Missing(e) => format!("missing ({}), searched the file instead", e),
+    };
+    let recovery = |r: Recovery| match r {
+        Recovery::Intact => "intact".to_string(),
+        Recovery::Truncated { expected } => format!("truncated, should be {} bytes", expected),
+        Recovery::Resized { expected } => format!("wrong size, should be {} bytes", expected),
+        Recovery::Scanned => "found by searching".to_string(),
+    };
+
+    if global.json {
+        let recovered: Vec<_> = report
+            .recovered
+            .iter()
+            .zip(&package.entries)
+            .map(|(r, entry)| {
+                json!({
+                    "tgi": r.tgi.to_string(),
+                    "type": type_name(r.tgi.resource_type),
+                    "offset": r.offset,
+                    "size": entry.chunk.memsize(),
+                    "recovery": recovery(r.recovery),
+                })
+            })
+            .collect();
+        let dropped: Vec<_> = report
+            .dropped
+            .iter()
+            .map(|e| {
+                json!({
+                    "tgi": e.tgi().map(|tgi| tgi.to_string()),
+                    "offset": e.offset(),
+                    "error": e.to_string(),
+                })
+            })
+            .collect();
+        let out = json!({
+            "input": opt.input.to_string_lossy(),
+            "index": index,
+            "recovered": recovered,
+            "dropped": dropped,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("Index: {}", index);
+        for r in report.recovered.iter().filter(|r| r.recovery != Recovery::Intact) {
+            println!("  {} {}: {}", r.tgi, type_name(r.tgi.resource_type), recovery(r.recovery));
+        }
+        for e in &report.dropped {
+            println!("  dropped {}", e);
+        }
+        println!(
+            "Recovered {} resource(s), dropped {}",
+            report.recovered.len(),
+            report.dropped.len()
+        );
+    }
+
+    if package.entries.is_empty() {
+        return Err("nothing could be recovered".into());
+    }
+    if !opt.dry_run {
+        let mut out = Cursor::new(Vec::new());
+        package.write_to(&mut out, ())?;
+        std::fs::write(&opt.output, out.into_inner())?;
+        global.status(format_args!("Wrote {}", opt.output.display()));
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! `replace_texture`: replace _IMG textures with PNG images.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use super::{CliResult, GlobalOpts};
+use crate::dbpf::filetypes::dds::{Image, DDS};
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::{ChunkHandle, DBPFReader};
+
+use binrw::{error::ContextExt, BinRead};
+
+fn parse_replacement(s: &str) -> Result<(TGI, PathBuf), String> {
+    let (tgi, path) = s.split_once('=').ok_or("expected TGI=IMAGE")?;
+    Ok((tgi.parse().map_err(|e| format!("{}", e))?, PathBuf::from(path)))
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Package containing the textures
+    pub package: PathBuf,
+
+    /// Where to write the modified package
+    pub output: PathBuf,
+
+    /// Textures to replace, as TTTTTTTT:GGGGGGGG:IIIIIIIIIIIIIIII=image.png
+    #[arg(required = true, num_args = 1.., value_name = "TGI=IMAGE", value_parser = parse_replacement)]
+    pub replacements: Vec<(TGI, PathBuf)>,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let file = File::open(&opt.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, mut package) = DBPFReader::parse(BufReader::new(file), guard)?;
+
+    for (tgi, path) in &opt.replacements {
+        if tgi.resource_type != ResourceType::IMG as u32 {
+            return Err(format!("{} is not an _IMG texture", tgi).into());
+        }
+        let entry = package
+            .entries
+            .iter_mut()
+            .find(|e| e.tgi() == *tgi)
+            .ok_or_else(|| format!("{} is not in the package", tgi))?;
+        let original = DDS::read_le(&mut entry.get_reader(&mut reader)?).with_context(*tgi)?;
+
+        let image = Image::read_png(BufReader::new(File::open(path)?))?;
+        if (image.width, image.height) != (original.width(), original.height()) {
+            global.status(format_args!(
+                "{}: resizing {}x{} to {}x{}",
+                path.display(),
+                image.width,
+                image.height,
+                original.width(),
+                original.height()
+            ));
+        }
+        // same size, mip count and (roughly) format, so that everything that uses the texture keeps working
+        let dds = original.encode_like(&image).with_context(*tgi)?;
+        if original.format() != dds.format() {
+            let from = original.format().map_or("an unknown format".to_string(), |f| f.to_string());
+            global.status(format_args!(
+                "{}: {} can't be encoded, writing {} instead",
+                tgi,
+                from,
+                dds.format().unwrap()
+            ));
+        }
+        entry.chunk = ChunkHandle::Dirty {
+            decompressed: dds.to_bytes()?,
+            should_compress: true,
+        };
+        global.status(format_args!("{}: replaced with {}", tgi, path.display()));
+    }
+
+    // written to memory first, so the output can be the package itself
+    let mut out = std::io::Cursor::new(Vec::new());
+    package.write_to(&mut out, reader)?;
+    std::fs::write(&opt.output, out.into_inner())?;
+    Ok(())
+}
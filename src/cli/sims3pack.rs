@@ -0,0 +1,227 @@
+//! `sims3pack`: look inside Sims3Packs, turn them into packages, and build new ones.
+
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use serde_json::json;
+
+use super::{sanitize_filename, CliResult, GlobalOpts};
+use crate::dbpf::{ChunkHandle, DBPFIndexEntry, DBPF};
+use crate::sims3pack::{FileKind, Manifest, Sims3Pack};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Show the manifest of a Sims3Pack and the files in it
+    Info { input: PathBuf },
+    /// Write the files in a Sims3Pack (packages and thumbnails) to a directory
+    Extract {
+        input: PathBuf,
+
+        /// Directory to write the files to. It is created if it doesn't exist
+        output: PathBuf,
+    },
+    /// Merge the packages in a Sims3Pack into one package
+    Convert {
+        input: PathBuf,
+
+        /// Where to write the package
+        output: PathBuf,
+    },
+    /// Build a Sims3Pack out of packages and thumbnails
+    Create {
+        /// Where to write the Sims3Pack
+        output: PathBuf,
+
+        /// Packages and thumbnails to put in it
+        #[arg(required = true, num_args = 1..)]
+        files: Vec<PathBuf>,
+
+        /// What it holds, like household, lot, casPart or object
+        #[arg(short = 't', long = "type")]
+        content_type: String,
+
+        /// Name shown in the launcher. Defaults to the name of the output file
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Description shown in the launcher
+        #[arg(short, long, default_value = "")]
+        description: String,
+    },
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    match &opt.action {
+        Action::Info { input } => info(&Sims3Pack::read_file(input)?, global),
+        Action::Extract { input, output } => {
+            let pack = Sims3Pack::read_file(input)?;
+            std::fs::create_dir_all(output)?;
+            for file in &pack.manifest.files {
+                let path = output.join(sanitize_filename(&file.name));
+                std::fs::write(&path, pack.file_data(file)?)?;
+                global.debug(format_args!("Wrote {}", path.display()));
+            }
+            global.status(format_args!(
+                "Extracted {} files to {}",
+                pack.manifest.files.len(),
+                output.display()
+            ));
+            Ok(())
+        }
+        Action::Convert { input, output } => {
+            let package = merge_packages(&Sims3Pack::read_file(input)?, global)?;
+            let mut out = Cursor::new(Vec::new());
+            package.write_to(&mut out, ())?;
+            std::fs::write(output, out.into_inner())?;
+            global.status(format_args!(
+                "Wrote {} resources to {}",
+                package.entries.len(),
+                output.display()
+            ));
+            Ok(())
+        }
+        Action::Create {
+            output,
+            files,
+            content_type,
+            name,
+            description,
+        } => {
+            let name = name.clone().unwrap_or_else(|| {
+                output
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let mut manifest = Manifest::new(content_type, name);
+            manifest.description = description.clone();
+            let mut pack = Sims3Pack::new(manifest);
+            for path in files {
+                let file_name = path
+                    .file_name()
+                    .ok_or_else(|| format!("{} is not a file", path.display()))?
+                    .to_string_lossy();
+                pack.add_file(file_name, content_type.as_str(), &std::fs::read(path)?);
+            }
+            if pack.packages().next().is_none() {
+                global.warn("none of the files are .package files");
+            }
+            let guids: String = pack.manifest.files.iter().map(|f| f.guid.as_str()).collect();
+            pack.manifest.package_id = format!(
+                "0x{:016x}{:016x}",
+                crate::hash::fnv1a64(&pack.manifest.display_name),
+                crate::hash::fnv1a64(&guids)
+            );
+
+            let mut out = Cursor::new(Vec::new());
+            pack.write_to(&mut out)?;
+            std::fs::write(output, out.into_inner())?;
+            global.status(format_args!(
+                "Wrote {} files to {}",
+                pack.manifest.files.len(),
+                output.display()
+            ));
+            Ok(())
+        }
+    }
+}
+
+fn info(pack: &Sims3Pack, global: &GlobalOpts) -> CliResult {
+    let manifest = &pack.manifest;
+    let kind = |kind| match kind {
+        FileKind::Package => "package",
+        FileKind::Thumbnail => "thumbnail",
+        FileKind::Other => "other",
+    };
+    if global.json {
+        let files: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|f| {
+                json!({
+                    "name": f.name,
+                    "kind": kind(f.kind()),
+                    "content_type": f.content_type,
+                    "length": f.length,
+                    "offset": f.offset,
+                    "guid": f.guid,
+                    "ep_flags": f.ep_flags,
+                })
+            })
+            .collect();
+        let out = json!({
+            "type": manifest.content_type,
+            "name": manifest.display_name,
+            "description": manifest.description,
+            "package_id": manifest.package_id,
+            "date": manifest.date,
+            "game_version": manifest.game_version,
+            "min_req_version": manifest.min_req_version,
+            "dependencies": manifest.dependencies,
+            "files": files,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("Name:            {}", manifest.display_name);
+        println!("Type:            {}", manifest.content_type);
+        println!("Package id:      {}", manifest.package_id);
+        println!("Date:            {}", manifest.date);
+        println!("Game version:    {}", manifest.game_version);
+        println!("Min req version: {}", manifest.min_req_version);
+        if !manifest.description.is_empty() {
+            println!("Description:     {}", manifest.description);
+        }
+        println!("Dependencies:");
+        for id in &manifest.dependencies {
+            println!("  {}", id);
+        }
+        println!("Files:");
+        for f in &manifest.files {
+            println!("  {:<9} {:>10}  {}", kind(f.kind()), f.length, f.name);
+        }
+    }
+    Ok(())
+}
+
+/// Every resource of every package in `pack`, in memory. If several packages have the same
+/// resource, the first one is kept.
+pub fn merge_packages(pack: &Sims3Pack, global: &GlobalOpts) -> crate::error::Result<DBPF<'static, ()>> {
+    let mut merged = DBPF::new();
+    let mut seen = HashSet::new();
+    for file in pack.packages() {
+        generativity::make_guard!(guard);
+        let (mut reader, package) = pack.open_package(file, guard)?;
+        for entry in &package.entries {
+            if !seen.insert(entry.tgi()) {
+                global.warn(format_args!(
+                    "{}: {} is in more than one package, keeping the first",
+                    file.name,
+                    entry.tgi()
+                ));
+                continue;
+            }
+            let mut data = Vec::new();
+            entry.get_reader(&mut reader)?.read_to_end(&mut data)?;
+            merged.entries.push(DBPFIndexEntry {
+                resource_type: entry.resource_type,
+                resource_group: entry.resource_group,
+                instance: entry.instance,
+                unk1: entry.unk1,
+                unk2: entry.unk2,
+                chunk: ChunkHandle::Dirty {
+                    decompressed: data,
+                    should_compress: entry.chunk.is_compressed(),
+                },
+            });
+        }
+    }
+    Ok(merged)
+}
@@ -0,0 +1,79 @@
+//! `unpack`: write every resource in a package to a directory, named like S3PE names them.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use super::{sanitize_filename, CliResult, GlobalOpts};
+use crate::dbpf::filetypes::ResourceType;
+use crate::dbpf::DBPFReader;
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Package to unpack
+    pub package: PathBuf,
+
+    /// Directory to write the resources to. It is created if it doesn't exist
+    pub output: PathBuf,
+
+    /// Don't add NMAP names to the filenames
+    #[arg(long)]
+    pub no_names: bool,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let file = File::open(&opt.package)?;
+    generativity::make_guard!(guard);
+    let (mut reader, package) = DBPFReader::parse(BufReader::new(file), guard)?;
+    let names = if opt.no_names {
+        Default::default()
+    } else {
+        package.gather_names(&mut reader).unwrap_or_else(|e| {
+            global.warn(format_args!("{}: {}", opt.package.display(), e));
+            Default::default()
+        })
+    };
+    std::fs::create_dir_all(&opt.output)?;
+
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    for entry in &package.entries {
+        let tgi = entry.tgi();
+        if !seen.insert(tgi) {
+            global.warn(format_args!("skipping duplicate resource {}", tgi));
+            continue;
+        }
+        let name = names
+            .get(&entry.instance)
+            .filter(|_| entry.resource_type != ResourceType::NMAP as u32)
+            .map(|n| sanitize_filename(n));
+        let filename = tgi.s3pe_filename(name.as_deref());
+
+        let mut output = File::create(opt.output.join(&filename))?;
+        std::io::copy(&mut entry.get_reader(&mut reader)?, &mut output)?;
+
+        entries.push(json!({
+            "file": filename,
+            "tgi": tgi.to_string(),
+            "compressed": entry.chunk.is_compressed(),
+            "unk1": entry.unk1,
+            "unk2": entry.unk2,
+        }));
+    }
+
+    // everything the filenames can't hold, so `pack` can rebuild the same package
+    let metadata = json!({
+        "maybe_flags": package.maybe_flags,
+        "created_timestamp": package.created_timestamp,
+        "modified_timestamp": package.modified_timestamp,
+        "entries": entries,
+    });
+    let f = BufWriter::new(File::create(opt.output.join("package.json"))?);
+    serde_json::to_writer_pretty(f, &metadata)?;
+
+    global.status(format_args!("Unpacked {} resources to {}", seen.len(), opt.output.display()));
+    Ok(())
+}
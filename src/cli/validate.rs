@@ -0,0 +1,133 @@
+//! `validate`: check packages for damage and for things that break the DBPF format's rules.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde_json::json;
+
+use super::{CliError, CliResult, GlobalOpts};
+use crate::dbpf::validate::{validate, Report, Severity};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FailOn {
+    Error,
+    Warning,
+    Info,
+    /// Always exit successfully if the packages could be read
+    Never,
+}
+
+impl FailOn {
+    fn fails(self, report: &Report) -> bool {
+        let threshold = match self {
+            FailOn::Error => Severity::Error,
+            FailOn::Warning => Severity::Warning,
+            FailOn::Info => Severity::Info,
+            FailOn::Never => return false,
+        };
+        report.worst().is_some_and(|worst| worst >= threshold)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Packages, or directories to search for packages
+    #[arg(required = true, num_args = 1..)]
+    pub paths: Vec<PathBuf>,
+
+    /// Exit with code 4 if any package has an issue this severe or worse
+    #[arg(long, value_enum, default_value_t = FailOn::Error)]
+    pub fail_on: FailOn,
+
+    /// Leave out issues with this code, like `unknown-type`. Can be repeated
+    #[arg(long, value_name = "CODE")]
+    pub ignore: Vec<String>,
+}
+
+pub fn run(opt: &Args, global: &GlobalOpts) -> CliResult {
+    let packages = global.find_packages(&opt.paths);
+    let results: Vec<(PathBuf, std::io::Result<Report>)> = packages
+        .into_par_iter()
+        .map(|path| {
+            global.debug(format_args!("Checking {}", path.display()));
+            let report = File::open(&path).and_then(|file| validate(&mut BufReader::new(file)));
+            (path, report)
+        })
+        .collect();
+
+    let mut failed = 0;
+    let mut invalid = 0;
+    let mut reports = Vec::new();
+    for (path, report) in results {
+        match report {
+            Ok(mut report) => {
+                report.issues.retain(|i| !opt.ignore.iter().any(|code| code == i.code));
+                if opt.fail_on.fails(&report) {
+                    invalid += 1;
+                }
+                reports.push((path, report));
+            }
+            Err(e) => {
+                global.warn(format_args!("{}: {}", path.display(), e));
+                failed += 1;
+            }
+        }
+    }
+
+    if global.json {
+        let reports: Vec<_> = reports
+            .iter()
+            .map(|(path, report)| {
+                let issues: Vec<_> = report
+                    .issues
+                    .iter()
+                    .map(|i| {
+                        json!({
+                            "severity": i.severity.as_str(),
+                            "code": i.code,
+                            "tgi": i.tgi.map(|tgi| tgi.to_string()),
+                            "offset": i.offset,
+                            "message": i.message,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "path": path.to_string_lossy(),
+                    "file_size": report.file_size,
+                    "resources": report.entries,
+                    "passed": !opt.fail_on.fails(report),
+                    "errors": report.count(Severity::Error),
+                    "warnings": report.count(Severity::Warning),
+                    "infos": report.count(Severity::Info),
+                    "issues": issues,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for (path, report) in &reports {
+            if report.issues.is_empty() {
+                global.status(format_args!("{}: ok", path.display()));
+            }
+            for issue in &report.issues {
+                println!("{}: {}", path.display(), issue);
+            }
+        }
+        global.status(format_args!(
+            "{} package(s) checked, {} failed validation",
+            reports.len(),
+            invalid
+        ));
+    }
+
+    if invalid > 0 {
+        Err(CliError::Invalid { packages: invalid })
+    } else if failed > 0 {
+        Err(CliError::Partial { failed })
+    } else {
+        Ok(())
+    }
+}
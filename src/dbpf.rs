@@ -1,4 +1,8 @@
+mod compression;
 pub mod filetypes;
+pub mod index;
+pub mod salvage;
+pub mod validate;
 
 use binrw::io::TakeSeekExt;
 use binrw::{binrw, io, BinRead, BinResult};
@@ -6,6 +10,8 @@ use std::collections::BTreeMap;
 use std::io::Read;
 use std::marker::PhantomData;
 
+use crate::error::Error;
+
 use bilge::prelude::*;
 
 macro_rules! dbpf_index_entry {
@@ -74,7 +80,7 @@ macro_rules! dbpf_index_entry {
 
                     for entry in iter {
                         $(
-                            if common.$field_name.map(|f| f == entry.$field_name).unwrap_or(false) {
+                            if common.$field_name.map(|f| f != entry.$field_name).unwrap_or(false) {
                                 common.$field_name = None;
                             }
                         )*
@@ -174,6 +180,18 @@ pub enum ChunkHandle<'brand> {
 }
 
 impl ChunkHandle<'_> {
+    /// Size of the chunk in the file. Dirty chunks haven't been compressed yet, so this is their full size.
+    pub fn filesize(&self) -> u32 {
+        match self {
+            ChunkHandle::Compressed { filesize, .. } | ChunkHandle::Uncompressed { filesize, .. } => (*filesize).into(),
+            ChunkHandle::Dirty { decompressed, .. } => decompressed.len() as u32,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, ChunkHandle::Compressed { .. })
+    }
+
     pub fn memsize(&self) -> u32 {
         match self {
             ChunkHandle::Compressed { memsize, .. } => *memsize,
@@ -217,7 +235,7 @@ impl<'brand> ChunkHandle<'brand> {
     pub fn get_reader<'a, Ctx: FileCtx<'brand>>(
         &'a self,
         ctx: &'a mut Ctx,
-    ) -> io::Result<impl ReadSeek + 'a> {
+    ) -> crate::error::Result<impl ReadSeek + 'a> {
         match self {
             ChunkHandle::Uncompressed {
                 offset,
@@ -231,7 +249,7 @@ impl<'brand> ChunkHandle<'brand> {
             ChunkHandle::Compressed {
                 offset,
                 filesize,
-                memsize: _,      // TODO: check against decompression output
+                memsize,
                 decompressed: _, // TODO: caching
                 brand,
             } => {
@@ -239,10 +257,27 @@ impl<'brand> ChunkHandle<'brand> {
                     ctx.get_chunk_reader(*offset as u64, (*filesize).into(), &brand)?;
                 let mut compressed = Vec::new();
                 reader.read_to_end(&mut compressed)?;
+                let size_mismatch = |found: usize| Error::SizeMismatch {
+                    tgi: None,
+                    offset: *offset as u64,
+                    expected: *memsize as u64,
+                    found: found as u64,
+                };
+                // checked before decompressing, so the index can't make us allocate more than it says
+                if let Some(header) = compression::header(&compressed) {
+                    if header.size != *memsize as usize {
+                        return Err(size_mismatch(header.size));
+                    }
+                }
                 // TODO: cache this
-                let decompressed =
-                    refpack::easy_decompress::<refpack::format::SimEA>(&compressed)
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let decompressed = compression::decompress(&compressed).map_err(|message| Error::Decompression {
+                    tgi: None,
+                    offset: *offset as u64,
+                    message,
+                })?;
+                if decompressed.len() != *memsize as usize {
+                    return Err(size_mismatch(decompressed.len()));
+                }
                 Ok(ChunkReader::CursorOwned(io::Cursor::new(decompressed)))
             }
             ChunkHandle::Dirty { decompressed, .. } => {
@@ -278,6 +313,18 @@ pub struct DBPFIndexEntry<'brand> {
 }
 
 impl<'brand> DBPFIndexEntry<'brand> {
+    pub fn tgi(&self) -> filetypes::tgi::TGI {
+        filetypes::tgi::TGI::new(self.resource_type, self.resource_group, self.instance)
+    }
+
+    /// Same as [`ChunkHandle::get_reader`], with this entry's TGI attached to errors.
+    pub fn get_reader<'a, Ctx: FileCtx<'brand>>(
+        &'a self,
+        ctx: &'a mut Ctx,
+    ) -> crate::error::Result<impl ReadSeek + 'a> {
+        self.chunk.get_reader(ctx).map_err(|e| e.with_tgi(self.tgi()))
+    }
+
     fn from_raw(value: IndexEntry, brand: generativity::Id<'brand>) -> Self {
         DBPFIndexEntry {
             resource_type: value.resource_type,
@@ -305,7 +352,17 @@ impl<'brand> DBPFIndexEntry<'brand> {
 }
 
 impl IndexEntry {
-    fn from_nice<'brand>(value: &DBPFIndexEntry<'brand>, current_offset: &mut u32) -> Self {
+    /// `compressed` is the compressed form of a dirty chunk, if it is to be written compressed.
+    fn from_nice<'brand>(
+        value: &DBPFIndexEntry<'brand>,
+        compressed_data: Option<&[u8]>,
+        current_offset: &mut u32,
+    ) -> crate::error::Result<Self> {
+        let overflow = |what, size: usize| Error::Overflow {
+            tgi: Some(value.tgi()),
+            what,
+            size: size as u64,
+        };
         let chunk_offset = *current_offset;
         let chunk_filesize: u31;
         let chunk_memsize: u32;
@@ -327,13 +384,22 @@ impl IndexEntry {
                 ref decompressed,
                 should_compress: _,
             } => {
-                compressed = false; // TODO
-                chunk_memsize = decompressed.len().try_into().unwrap();
-                chunk_filesize = u31::try_new(decompressed.len().try_into().unwrap()).unwrap();
+                let stored = compressed_data.unwrap_or(decompressed);
+                compressed = compressed_data.is_some();
+                chunk_memsize = decompressed
+                    .len()
+                    .try_into()
+                    .map_err(|_| overflow("resource", decompressed.len()))?;
+                chunk_filesize = u32::try_from(stored.len())
+                    .ok()
+                    .and_then(|size| u31::try_new(size).ok())
+                    .ok_or_else(|| overflow("resource", stored.len()))?;
             }
         }
-        *current_offset += u32::from(chunk_filesize);
-        IndexEntry {
+        *current_offset = current_offset
+            .checked_add(u32::from(chunk_filesize))
+            .ok_or_else(|| overflow("package", *current_offset as usize + u32::from(chunk_filesize) as usize))?;
+        Ok(IndexEntry {
             resource_type: value.resource_type,
             resource_group: value.resource_group,
             instance_hi: (value.instance >> 32) as u32,
@@ -342,7 +408,7 @@ impl IndexEntry {
             filesize_unk1: IndexFilesize::new(chunk_filesize, value.unk1),
             memsize: chunk_memsize,
             compressed_unk2: (if compressed { 0xFFFF } else { 0 }, value.unk2),
-        }
+        })
     }
 }
 
@@ -378,7 +444,7 @@ pub trait FileCtx<'brand>: FileReader {
         pos: u64,
         size: u64,
         _brand: &generativity::Id<'brand>,
-    ) -> io::Result<Self::ChunkReader<'a>>;
+    ) -> crate::error::Result<Self::ChunkReader<'a>>;
 }
 impl FileReader for () {
     type ChunkReader<'r> = NeverReader where Self: 'r;
@@ -389,11 +455,12 @@ impl FileCtx<'static> for () {
         _pos: u64,
         _size: u64,
         _brand: &generativity::Id<'static>,
-    ) -> io::Result<NeverReader> {
+    ) -> crate::error::Result<NeverReader> {
         Err(io::Error::new(
             io::ErrorKind::Other,
             "Unable to get chunk reader from unwritten file!",
-        ))
+        )
+        .into())
     }
 }
 impl<'brand, Read: io::Read + io::Seek> FileReader for DBPFReader<'brand, Read> {
@@ -405,14 +472,23 @@ impl<'brand, Read: io::Read + io::Seek> FileCtx<'brand> for DBPFReader<'brand, R
         pos: u64,
         size: u64,
         _brand: &generativity::Id<'brand>,
-    ) -> io::Result<io::TakeSeek<&'a mut Read>> {
+    ) -> crate::error::Result<io::TakeSeek<&'a mut Read>> {
+        if pos.checked_add(size).is_none_or(|end| end > self.2) {
+            return Err(Error::ChunkOutOfBounds {
+                tgi: None,
+                offset: pos,
+                size,
+                file_size: self.2,
+            });
+        }
         self.0.seek(io::SeekFrom::Start(pos))?;
         // TODO: this is buggy, since seeks in the resulting reader are relative to the original file!
         Ok((&mut self.0).take_seek(size))
     }
 }
 
-pub struct DBPFReader<'brand, Read>(Read, generativity::Id<'brand>);
+/// The file a [`DBPF`] was read from, and its size.
+pub struct DBPFReader<'brand, Read>(Read, generativity::Id<'brand>, u64);
 
 impl<'brand, Read> DBPFReader<'brand, Read>
 where
@@ -421,10 +497,12 @@ where
     pub fn parse(
         mut reader: Read,
         guard: generativity::Guard<'brand>,
-    ) -> BinResult<(Self, DBPF<'brand, Self>)> {
+    ) -> crate::error::Result<(Self, DBPF<'brand, Self>)> {
         let id: generativity::Id = guard.into();
-        let dbpf = DBPF::<'brand>::read(&mut reader, id.clone())?;
-        Ok((DBPFReader(reader, id), dbpf))
+        let file_size = reader.seek(io::SeekFrom::End(0))?;
+        reader.seek(io::SeekFrom::Start(0))?;
+        let dbpf = DBPF::<'brand>::read(&mut reader, file_size, id.clone())?;
+        Ok((DBPFReader(reader, id, file_size), dbpf))
     }
 }
 
@@ -433,6 +511,7 @@ pub struct DBPF<'brand, Ctx> {
     pub maybe_flags: u32,
     pub created_timestamp: u32,  // usually 0
     pub modified_timestamp: u32, // usually 0
+    pub index_version: u32,      // usually 7
     pub entries: Vec<DBPFIndexEntry<'brand>>,
     phantom: PhantomData<Ctx>,
 }
@@ -443,29 +522,77 @@ impl DBPF<'static, ()> {
             maybe_flags: 0,
             created_timestamp: 0,
             modified_timestamp: 0,
+            index_version: 7,
             entries: Vec::new(),
             phantom: PhantomData,
         }
     }
 }
 
+/// Read and check the header, then read the whole index at once.
+///
+/// That's two reads, so this doesn't need a buffered reader to be fast.
+fn read_index<R: io::Read + io::Seek>(
+    reader: &mut R,
+    file_size: u64,
+) -> crate::error::Result<(DBPFHeader, Vec<IndexEntry>)> {
+    let mut header = [0; 96];
+    reader.seek(io::SeekFrom::Start(0))?;
+    reader.read_exact(&mut header)?;
+    let header: DBPFHeader = BinRead::read_le(&mut io::Cursor::new(&header))?;
+    if header.major != 2 || header.minor != 0 {
+        let (offset, version) = if header.major != 2 { (4, header.major) } else { (8, header.minor) };
+        return Err(Error::UnsupportedVersion {
+            offset,
+            tgi: None,
+            version,
+        });
+    }
+
+    // index
+    let truncated = || Error::TruncatedIndex {
+        offset: header.index_position as u64,
+        entries: header.index_entries,
+        file_size,
+    };
+    if header.index_position as u64 + header.index_size as u64 > file_size {
+        return Err(truncated());
+    }
+    let mut index = vec![0; header.index_size as usize];
+    reader.seek(io::SeekFrom::Start(header.index_position as u64))?;
+    reader.read_exact(&mut index).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(),
+        _ => e.into(),
+    })?;
+
+    let reader = &mut io::Cursor::new(index);
+    let eof_is_truncated = |e: binrw::Error| if e.is_eof() { truncated() } else { e.into() };
+    let mask: IndexEntryMask = BinRead::read_le(reader).map_err(eof_is_truncated)?;
+    let common: PartialIndexEntry = BinRead::read_le_args(reader, (mask,)).map_err(eof_is_truncated)?;
+    // the entry count decides how much gets allocated, so check it against the index size first.
+    // if every field is common, entries take no space at all, so allow at most one per byte of index.
+    let needed = (1 + mask.count_present() as u64
+        + (8 - mask.count_present() as u64) * header.index_entries as u64)
+        * 4;
+    if needed > header.index_size as u64 || header.index_entries > header.index_size {
+        return Err(truncated());
+    }
+    let entries_args = binrw::VecArgs {
+        count: header.index_entries as usize,
+        inner: (common,),
+    };
+    let entries = BinRead::read_le_args(reader, entries_args).map_err(eof_is_truncated)?;
+    Ok((header, entries))
+}
+
 // This is *not* a BinRead impl, so that it can be private and I can make it take an Id<'_> instead of a Guard Id<'_>
 impl<'brand, Ctx: FileCtx<'brand>> DBPF<'brand, Ctx> {
     fn read<R: io::Read + io::Seek>(
         reader: &mut R,
+        file_size: u64,
         brand: generativity::Id<'brand>,
-    ) -> BinResult<Self> {
-        let header: DBPFHeader = BinRead::read_le(reader)?;
-
-        // index
-        reader.seek(io::SeekFrom::Start(header.index_position as u64))?;
-        let mask: IndexEntryMask = BinRead::read_le(reader)?;
-        let common: PartialIndexEntry = BinRead::read_le_args(reader, (mask,))?;
-        let entries_args = binrw::VecArgs {
-            count: header.index_entries as usize,
-            inner: (common,),
-        };
-        let entries: Vec<IndexEntry> = BinRead::read_le_args(reader, entries_args)?;
+    ) -> crate::error::Result<Self> {
+        let (header, entries) = read_index(reader, file_size)?;
         let entries = entries
             .into_iter()
             .map(|e| DBPFIndexEntry::from_raw(e, brand.clone()))
@@ -475,6 +602,7 @@ impl<'brand, Ctx: FileCtx<'brand>> DBPF<'brand, Ctx> {
             maybe_flags: header.maybe_flags,
             created_timestamp: header.created_timestamp,
             modified_timestamp: header.modified_timestamp,
+            index_version: header._index_major,
             entries,
             phantom: PhantomData,
         })
@@ -494,22 +622,50 @@ impl<'brand, Ctx: FileCtx<'brand>> binrw::BinWrite for DBPF<'brand, Ctx> {
         // we don't know how large the index is going to be yet,
         // so we'll start after the header and add the size of the index later.
         let mut current_offset = 96u32;
-        let mut entries: Vec<_> = self
+        // dirty chunks that asked for compression. if compressing fails, they're stored as-is.
+        let compressed: Vec<Option<Vec<u8>>> = self
             .entries
             .iter()
-            .map(|e| IndexEntry::from_nice(e, &mut current_offset))
+            .map(|e| match &e.chunk {
+                ChunkHandle::Dirty {
+                    decompressed,
+                    should_compress: true,
+                } => refpack::easy_compress::<refpack::format::SimEA>(
+                    decompressed,
+                    refpack::data::compression::CompressionOptions::Optimal,
+                )
+                .ok()
+                .filter(|c| c.len() < decompressed.len()),
+                _ => None,
+            })
             .collect();
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .zip(&compressed)
+            .map(|(e, c)| IndexEntry::from_nice(e, c.as_deref(), &mut current_offset))
+            .collect::<crate::error::Result<_>>()?;
         let common = PartialIndexEntry::calc_common_entries(entries.iter());
         let mask = common.calc_mask();
 
         // (mask + common_fields + different_fields * num_entries) * 4
         let index_size =
             (1 + mask.count_present() + (8 - mask.count_present()) * (entries.len() as u32)) * 4;
+        if current_offset.checked_add(index_size).is_none() {
+            return Err(Error::Overflow {
+                tgi: None,
+                what: "package",
+                size: current_offset as u64 + index_size as u64,
+            }
+            .into());
+        }
         entries
             .iter_mut()
             .for_each(|e| e.chunk_offset += index_size);
 
         let header = DBPFHeader {
+            major: 2,
+            minor: 0,
             maybe_flags: self.maybe_flags,
             created_timestamp: self.created_timestamp,
             modified_timestamp: self.modified_timestamp,
@@ -520,18 +676,33 @@ impl<'brand, Ctx: FileCtx<'brand>> binrw::BinWrite for DBPF<'brand, Ctx> {
             hole_index_position: 0,
             hole_index_size: 0,
             index_position: 96,
-            _index_major: 7,
+            _index_major: self.index_version,
         };
 
         header.write_le(writer)?;
-        mask.write_le(writer)?;
-        common.write_le(writer)?;
-        entries.write_le_args(writer, (mask,))?;
         binrw::BinWrite::write_options(&mask, writer, endian, ())?;
         binrw::BinWrite::write_options(&common, writer, endian, ())?;
         binrw::BinWrite::write_options(&entries, writer, endian, (mask,))?;
-        for chunk in self.entries.iter().map(|e| &e.chunk) {
-            std::io::copy(&mut chunk.get_reader(&mut args)?, writer)?;
+        for (chunk, compressed) in self.entries.iter().map(|e| &e.chunk).zip(&compressed) {
+            match (chunk, compressed) {
+                (_, Some(compressed)) => writer.write_all(compressed)?,
+                // the index says these are compressed, so copy them as they are
+                (
+                    ChunkHandle::Compressed {
+                        offset,
+                        filesize,
+                        brand,
+                        ..
+                    },
+                    None,
+                ) => {
+                    let mut reader = args.get_chunk_reader(*offset as u64, (*filesize).into(), brand)?;
+                    std::io::copy(&mut reader, writer)?;
+                }
+                _ => {
+                    std::io::copy(&mut chunk.get_reader(&mut args)?, writer)?;
+                }
+            }
         }
         Ok(())
     }
@@ -544,12 +715,8 @@ impl<'brand, Ctx: FileCtx<'brand>> binrw::BinWrite for DBPF<'brand, Ctx> {
 struct DBPFHeader {
     // Would it be nice to expand to more versions of DBPF eventually?
     // Sure. But not right now.
-    #[br(temp)]
-    #[brw(magic = 2u32, calc = ())]
-    _major: (), // 2
-    #[br(temp)]
-    #[brw(magic = 0u32, calc = ())]
-    _minor: (), // 0
+    major: u32, // 2
+    minor: u32, // 0
     #[br(temp)]
     #[brw(magic = 0u32, calc = ())]
     _major_user: (), // 0
@@ -582,8 +749,14 @@ struct DBPFHeader {
 }
 
 impl<'brand, Ctx: FileCtx<'brand>> DBPF<'brand, Ctx> {
+    /// Write the package, reading unchanged resources from `ctx`.
+    /// Same as the [`BinWrite`](binrw::BinWrite) impl, with this crate's error type.
+    pub fn write_to<W: io::Write + io::Seek>(&self, writer: &mut W, ctx: Ctx) -> crate::error::Result<()> {
+        binrw::BinWrite::write_le_args(self, writer, ctx).map_err(Error::from)
+    }
+
     // instance -> name
-    pub fn gather_names(&self, ctx: &mut Ctx) -> Result<BTreeMap<u64, String>, binrw::Error> {
+    pub fn gather_names(&self, ctx: &mut Ctx) -> crate::error::Result<BTreeMap<u64, String>> {
         let mut map = BTreeMap::new();
         self.entries
             .iter()
@@ -592,4 +765,111 @@ impl<'brand, Ctx: FileCtx<'brand>> DBPF<'brand, Ctx> {
             .collect::<Result<_, binrw::Error>>()?;
         Ok(map)
     }
+
+    // string key hash -> text, across all languages
+    pub fn gather_strings(&self, ctx: &mut Ctx) -> crate::error::Result<BTreeMap<u64, String>> {
+        let mut map = BTreeMap::new();
+        self.entries
+            .iter()
+            .filter(|e| e.resource_type == filetypes::ResourceType::STBL as u32)
+            .map(|e| filetypes::stbl::gather_strings_into(ctx, e, &mut map))
+            .collect::<Result<_, binrw::Error>>()?;
+        Ok(map)
+    }
+
+    /// Reverse hash lookup seeded with [`crate::hash::KNOWN_NAMES`], plus this package's NMAPs and STBLs.
+    pub fn hash_dictionary(&self, ctx: &mut Ctx) -> crate::error::Result<crate::hash::HashDictionary> {
+        let mut dict = crate::hash::KNOWN_NAMES.clone();
+        dict.extend_from_nmap(&self.gather_names(ctx)?);
+        dict.extend_from_stbl(&self.gather_strings(ctx)?);
+        Ok(dict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinWrite;
+    use std::io::{Cursor, Read};
+
+    fn dirty(resource_type: u32, instance: u64, data: &[u8]) -> DBPFIndexEntry<'static> {
+        DBPFIndexEntry {
+            resource_type,
+            resource_group: 0,
+            instance,
+            unk1: false,
+            unk2: 1,
+            chunk: ChunkHandle::Dirty {
+                decompressed: data.to_vec(),
+                should_compress: false,
+            },
+        }
+    }
+
+    fn write<'brand, Ctx: FileCtx<'brand>>(package: &DBPF<'brand, Ctx>, ctx: Ctx) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        package.write_le_args(&mut out, ctx).unwrap();
+        out.into_inner()
+    }
+
+    fn data<'brand, Ctx: FileCtx<'brand>>(entry: &DBPFIndexEntry<'brand>, ctx: &mut Ctx) -> Vec<u8> {
+        let mut data = Vec::new();
+        entry.chunk.get_reader(ctx).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let mut package = DBPF::new();
+        package.entries.push(dirty(0x0333406C, 0x1_0000_0001, b"first"));
+        package.entries.push(dirty(0x034AEECB, 0x1_0000_0002, b"second"));
+        let bytes = write(&package, ());
+
+        // group, instance_hi and compressed_unk2 are common, the other 5 fields aren't
+        let index_size = (1 + 3 + 5 * 2) * 4;
+        assert_eq!(bytes.len(), 96 + index_size + 11, "the index is written once");
+
+        generativity::make_guard!(guard);
+        let (mut reader, read) = DBPFReader::parse(Cursor::new(&bytes[..]), guard).unwrap();
+        assert_eq!(read.entries.len(), 2);
+        for (written, read) in package.entries.iter().zip(&read.entries) {
+            assert_eq!(written.tgi(), read.tgi());
+            assert_eq!(written.unk2, read.unk2);
+            assert_eq!(data(written, &mut ()), data(read, &mut reader));
+        }
+    }
+
+    #[test]
+    fn compressed_chunks_are_copied_as_is() {
+        let original = b"abcabcabcabcabcabcabcabcabcabcabcabc".repeat(8);
+        let packed = refpack::easy_compress::<refpack::format::SimEA>(
+            &original,
+            refpack::data::compression::CompressionOptions::Optimal,
+        )
+        .unwrap();
+        let mut package = DBPF::new();
+        package.entries.push(dirty(0x0333406C, 1, &packed));
+        let bytes = write(&package, ());
+
+        // point the entry at the same bytes again, but as a compressed chunk
+        generativity::make_guard!(guard);
+        let (reader, mut read) = DBPFReader::parse(Cursor::new(&bytes[..]), guard).unwrap();
+        read.entries[0].chunk = match &read.entries[0].chunk {
+            ChunkHandle::Uncompressed { offset, filesize, brand } => ChunkHandle::Compressed {
+                offset: *offset,
+                filesize: *filesize,
+                memsize: original.len() as u32,
+                decompressed: None,
+                brand: brand.clone(),
+            },
+            other => panic!("expected an uncompressed chunk, got {:?}", other),
+        };
+        let bytes = write(&read, reader);
+
+        generativity::make_guard!(guard);
+        let (mut reader, read) = DBPFReader::parse(Cursor::new(&bytes[..]), guard).unwrap();
+        assert!(matches!(read.entries[0].chunk, ChunkHandle::Compressed { .. }));
+        assert_eq!(bytes.len(), 96 + (1 + 8) * 4 + packed.len());
+        assert_eq!(data(&read.entries[0], &mut reader), original);
+    }
 }
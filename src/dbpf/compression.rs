@@ -0,0 +1,66 @@
+//! RefPack, the compression used for resources.
+//!
+//! The actual decompression is done by the `refpack` crate, which allocates whatever a stream's header
+//! asks for. Headers come from untrusted files, so they're checked against the stream's length first.
+
+/// A RefPack stream can't grow by more than this: its longest copy command is 4 bytes, for 1028 bytes of output.
+const MAX_RATIO: usize = 257;
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Header {
+    /// Length of the header itself.
+    pub len: usize,
+    /// Size of the decompressed data.
+    pub size: usize,
+}
+
+/// The header of the RefPack stream at the start of `data`, if it looks like one.
+pub(crate) fn header(data: &[u8]) -> Option<Header> {
+    let (&flags, rest) = data.split_first()?;
+    if flags & 0x3E != 0x10 || rest.first() != Some(&0xFB) {
+        return None;
+    }
+    let size_len = if flags & 0x80 != 0 { 4 } else { 3 };
+    // compressed size (if flagged) then decompressed size, big endian
+    let pos = 2 + if flags & 0x01 != 0 { size_len } else { 0 };
+    let size = data.get(pos..pos + size_len)?.iter().fold(0, |size, &b| (size << 8) | b as usize);
+    Some(Header {
+        len: pos + size_len,
+        size,
+    })
+}
+
+/// Length of the RefPack stream at the start of `data`, found by walking its commands.
+pub(crate) fn stream_len(data: &[u8]) -> Option<usize> {
+    let mut pos = header(data)?.len;
+    loop {
+        let command = *data.get(pos)?;
+        let (len, literal, stop) = match command {
+            0x00..=0x7F => (2, command as usize & 3, false),
+            0x80..=0xBF => (3, (*data.get(pos + 1)? as usize >> 6) & 3, false),
+            0xC0..=0xDF => (4, command as usize & 3, false),
+            0xE0..=0xFB => (1, ((command as usize & 0x1F) + 1) * 4, false),
+            0xFC..=0xFF => (1, command as usize & 3, true),
+        };
+        pos += len + literal;
+        if pos > data.len() {
+            return None;
+        }
+        if stop {
+            return Some(pos);
+        }
+    }
+}
+
+/// Decompress a RefPack stream, refusing ones whose header claims more data than they could hold.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let header = header(data).ok_or_else(|| "not RefPack compressed data".to_string())?;
+    if header.size > data.len().saturating_mul(MAX_RATIO) {
+        return Err(format!(
+            "header claims {} bytes, which {} bytes of compressed data can't hold",
+            header.size,
+            data.len()
+        ));
+    }
+    refpack::easy_decompress::<refpack::format::SimEA>(data).map_err(|e| e.to_string())
+}
@@ -1,6 +1,18 @@
 // TODO: What should I do with these? I'm just making this public for now.
+pub mod casp;
+pub mod dds;
+pub mod geom;
+pub mod model;
+pub mod mtnf;
 pub mod nmap;
-//pub mod casp;
+pub mod objd;
+pub mod objk;
+pub mod pattern;
+pub mod rcol;
+pub mod simo;
+pub mod stbl;
+pub mod tgi;
+pub mod vpxy;
 
 //use num_traits::ToPrimitive;
 
@@ -89,6 +101,8 @@ pub enum ResourceType {
     // ...
     TWNI = 0x0668F635, // png
     // ...
+    STBL = 0x220557DA,
+    // ...
     OBJIconSmall = 0x2E75C764,  // png
     OBJIconMedium = 0x2E75C765, // png
     OBJIconLarge = 0x2E75C766,  // png
@@ -104,8 +118,11 @@ pub enum ResourceType {
     FamilySNAPMedium = 0x6B6D837E, // png
     FamilySNAPLarge = 0x6B6D837F,  // png
     // ...
+    VPXY = 0x736884F1,
     XMLManifest = 0x73E93EEB,
     // ...
+    RSLT = 0xD3044521,
+    FTPT = 0xD382BF57,
     PTRN = 0xD4D9FBE5,
     // ...
     LotIconSmall = 0xD84E7FC5,  // png
@@ -171,3 +188,41 @@ lazy_static! {
 pub fn resource_is_png(resource: u32) -> bool {
     PNG_RESOURCES.iter().any(|&x| x == resource)
 }
+
+/// The tag and file extension S3PE uses for a resource type, for exporting resources to files.
+pub fn tag_and_extension(resource: u32) -> (&'static str, &'static str) {
+    use num_traits::FromPrimitive;
+    if resource_is_png(resource) {
+        return ("THUM", ".png");
+    }
+    match ResourceType::from_u32(resource) {
+        Some(ResourceType::BONE) => ("BONE", ".bone"),
+        Some(ResourceType::IMG) => ("_IMG", ".dds"),
+        Some(ResourceType::SPT) => ("_SPT", ".speedtree"),
+        Some(ResourceType::GEOM) => ("GEOM", ".simgeom"),
+        Some(ResourceType::NMAP) => ("NMAP", ".nmap"),
+        Some(ResourceType::MODL) => ("MODL", ".model"),
+        Some(ResourceType::MLOD) => ("MLOD", ".mlod"),
+        Some(ResourceType::MTST) => ("MTST", ".mtst"),
+        Some(ResourceType::SIMO) => ("SIMO", ".simoutfit"),
+        Some(ResourceType::JAZZ) => ("JAZZ", ".jazz"),
+        Some(ResourceType::OBJK) => ("OBJK", ".objkey"),
+        Some(ResourceType::XMLResource) => ("_XML", ".xml"),
+        Some(ResourceType::TXTC) => ("TXTC", ".txtc"),
+        Some(ResourceType::TXTF) => ("TXTF", ".txtf"),
+        Some(ResourceType::CASP) => ("CASP", ".caspart"),
+        Some(ResourceType::SkinTone) => ("TONE", ".skintone"),
+        Some(ResourceType::HairTone) => ("HTON", ".hairtone"),
+        Some(ResourceType::BoneDelta) => ("BOND", ".bonedelta"),
+        Some(ResourceType::FACE) => ("FACE", ".faceblend"),
+        Some(ResourceType::UPST) => ("UPST", ".upst"),
+        Some(ResourceType::STBL) => ("STBL", ".stbl"),
+        Some(ResourceType::OBJD) => ("OBJD", ".objd"),
+        Some(ResourceType::VPXY) => ("VPXY", ".vpxy"),
+        Some(ResourceType::XMLManifest) => ("MANI", ".xml"),
+        Some(ResourceType::RSLT) => ("RSLT", ".rslt"),
+        Some(ResourceType::FTPT) => ("FTPT", ".footprint"),
+        Some(ResourceType::PTRN) => ("PTRN", ".patternlist"),
+        _ => ("UNKN", ".dat"),
+    }
+}
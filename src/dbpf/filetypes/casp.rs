@@ -0,0 +1,581 @@
+//! CASP, the CAS part resource. Describes a piece of clothing, hair, makeup, etc.:
+//! who can wear it, which presets (patterns and colours) it comes in, and where its meshes are.
+
+use super::tgi::{TGIOrder, TGI};
+use super::ResourceType;
+use crate::util::{parse_7bit_utf16be, write_7bit_utf16be};
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A pattern/colour preset, as the XML that CAS reads it from.
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    #[br(temp)]
+    #[bw(try_calc = xml.encode_utf16().count().try_into())]
+    len: u32,
+    // UTF-16LE, length in characters
+    #[br(count = len, map = |s: Vec<u16>| String::from_utf16_lossy(&s))]
+    #[bw(map = |s: &String| s.encode_utf16().collect::<Vec<u16>>())]
+    pub xml: String,
+    pub unknown: u32,
+}
+
+impl Preset {
+    /// The patterns this preset uses, see [`preset_patterns`](super::pattern::preset_patterns).
+    pub fn patterns(&self) -> Vec<TGI> {
+        super::pattern::preset_patterns(&self.xml)
+    }
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodAsset {
+    pub sorting: u32,
+    pub spec_level: u32,
+    pub cast_shadow: u32,
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodInfo {
+    pub level: u8,
+    pub unused: u32,
+    #[br(temp)]
+    #[bw(try_calc = assets.len().try_into())]
+    count: u8,
+    #[br(count = count)]
+    pub assets: Vec<LodAsset>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum Species {
+    Human = 1,
+    Horse = 2,
+    Cat = 3,
+    Dog = 4,
+    LittleDog = 5,
+    Deer = 6,
+    Raccoon = 7,
+}
+
+/// Age, gender, species and handedness flags.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct AgeGender(pub u32);
+
+impl AgeGender {
+    pub const BABY: u32 = 0x01;
+    pub const TODDLER: u32 = 0x02;
+    pub const CHILD: u32 = 0x04;
+    pub const TEEN: u32 = 0x08;
+    pub const YOUNG_ADULT: u32 = 0x10;
+    pub const ADULT: u32 = 0x20;
+    pub const ELDER: u32 = 0x40;
+    pub const AGE_MASK: u32 = 0x7F;
+
+    pub const MALE: u32 = 0x1000;
+    pub const FEMALE: u32 = 0x2000;
+    pub const GENDER_MASK: u32 = 0x3000;
+
+    const SPECIES_MASK: u32 = 0xF00;
+    const SPECIES_SHIFT: u32 = 8;
+
+    pub const LEFT_HANDED: u32 = 0x10_0000;
+    pub const RIGHT_HANDED: u32 = 0x20_0000;
+
+    const AGES: [(u32, &'static str); 7] = [
+        (Self::BABY, "B"),
+        (Self::TODDLER, "P"),
+        (Self::CHILD, "C"),
+        (Self::TEEN, "T"),
+        (Self::YOUNG_ADULT, "Y"),
+        (Self::ADULT, "A"),
+        (Self::ELDER, "E"),
+    ];
+    const GENDERS: [(u32, &'static str); 2] = [(Self::MALE, "M"), (Self::FEMALE, "F")];
+
+    pub fn contains(&self, flags: u32) -> bool {
+        self.0 & flags == flags
+    }
+
+    pub fn set(&mut self, flags: u32, value: bool) {
+        if value {
+            self.0 |= flags;
+        } else {
+            self.0 &= !flags;
+        }
+    }
+
+    /// Short names of the ages: Baby, Toddler (P), Child, Teen, Young adult, Adult, Elder.
+    pub fn ages(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Self::AGES.iter().filter(|(bit, _)| self.0 & bit != 0).map(|&(_, name)| name)
+    }
+
+    /// Short names of the genders: Male, Female.
+    pub fn genders(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Self::GENDERS.iter().filter(|(bit, _)| self.0 & bit != 0).map(|&(_, name)| name)
+    }
+
+    /// Parts made before Pets have no species set, which means human.
+    pub fn species(&self) -> Option<Species> {
+        match (self.0 & Self::SPECIES_MASK) >> Self::SPECIES_SHIFT {
+            0 => Some(Species::Human),
+            n => num_traits::FromPrimitive::from_u32(n),
+        }
+    }
+
+    pub fn set_species(&mut self, species: Species) {
+        self.0 = (self.0 & !Self::SPECIES_MASK) | ((species as u32) << Self::SPECIES_SHIFT);
+    }
+}
+
+/// Formats like `YAE-F`, the way CAS part names are usually prefixed.
+impl fmt::Display for AgeGender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ages().collect::<String>(), self.genders().collect::<String>())
+    }
+}
+
+/// The body slot a part goes in. Only one part per slot can be worn at a time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ClothingType {
+    None = 0,
+    Hair = 1,
+    Scalp = 2,
+    FaceOverlay = 3,
+    Body = 4,
+    Top = 5,
+    Bottom = 6,
+    Shoes = 7,
+    Accessories = 8,
+    Earrings = 9,
+    Glasses = 10,
+    Necklace = 11,
+    Gloves = 12,
+    BraceletLeft = 13,
+    BraceletRight = 14,
+    LipRingLeft = 15,
+    LipRingRight = 16,
+    NoseRingLeft = 17,
+    NoseRingRight = 18,
+    BrowRingLeft = 19,
+    BrowRingRight = 20,
+    RingIndexLeft = 21,
+    RingIndexRight = 22,
+    RingThirdLeft = 23,
+    RingThirdRight = 24,
+    RingMidLeft = 25,
+    RingMidRight = 26,
+    FacialHair = 27,
+    Lipstick = 28,
+    Eyeshadow = 29,
+    Eyeliner = 30,
+    Blush = 31,
+    Makeup = 32,
+    Eyebrow = 33,
+    Glove = 34,
+    Socks = 35,
+    Mascara = 36,
+}
+
+/// Flags for what kind of part this is, as opposed to [`ClothingType`], which is where it goes.
+pub mod data_type {
+    pub const HAIR: u32 = 0x01;
+    pub const SCALP: u32 = 0x02;
+    pub const FACE_OVERLAY: u32 = 0x04;
+    pub const BODY: u32 = 0x08;
+    pub const ACCESSORY: u32 = 0x10;
+}
+
+/// Flags for the outfit categories a part is available in.
+pub mod category {
+    pub const NAKED: u32 = 0x01;
+    pub const EVERYDAY: u32 = 0x02;
+    pub const FORMALWEAR: u32 = 0x04;
+    pub const SLEEPWEAR: u32 = 0x08;
+    pub const SWIMWEAR: u32 = 0x10;
+    pub const ATHLETIC: u32 = 0x20;
+    pub const SINGED: u32 = 0x40;
+    pub const CAREER: u32 = 0x100;
+}
+
+#[binrw]
+struct CASPBody {
+    #[br(temp)]
+    #[bw(try_calc = presets.len().try_into())]
+    preset_count: u32,
+    #[br(count = preset_count)]
+    presets: Vec<Preset>,
+    // UTF-16BE, with a .NET 7-bit length in bytes
+    #[br(parse_with = parse_7bit_utf16be)]
+    #[bw(write_with = write_7bit_utf16be)]
+    name: String,
+    sort_priority: f32,
+    secondary_sort_index: u8,
+    clothing: u32,
+    data_type: u32,
+    age_gender: u32,
+    category: u32,
+    cas_part_indices: [u8; 2],
+    blend_indices: [u8; 4],
+    overlay_priority: u32,
+    #[br(temp)]
+    #[bw(try_calc = vpxy_indices.len().try_into())]
+    vpxy_count: u8,
+    #[br(count = vpxy_count)]
+    vpxy_indices: Vec<u8>,
+    #[br(temp)]
+    #[bw(try_calc = lods.len().try_into())]
+    lod_count: u8,
+    #[br(count = lod_count)]
+    lods: Vec<LodInfo>,
+    #[br(temp)]
+    #[bw(try_calc = diffuse_indices.len().try_into())]
+    diffuse_count: u8,
+    #[br(count = diffuse_count)]
+    diffuse_indices: Vec<u8>,
+    #[br(temp)]
+    #[bw(try_calc = specular_indices.len().try_into())]
+    specular_count: u8,
+    #[br(count = specular_count)]
+    specular_indices: Vec<u8>,
+    #[br(temp)]
+    #[bw(try_calc = bond_indices.len().try_into())]
+    bond_count: u8,
+    #[br(count = bond_count)]
+    bond_indices: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CASP {
+    pub version: u32,
+    pub presets: Vec<Preset>,
+    pub name: String,
+    pub sort_priority: f32,
+    pub secondary_sort_index: u8,
+    /// See [`ClothingType`].
+    pub clothing: u32,
+    /// See [`data_type`].
+    pub data_type: u32,
+    pub age_gender: AgeGender,
+    /// See [`category`].
+    pub category: u32,
+    /// Indices into `tgis` of the parts shown for the top and bottom halves, for full body outfits.
+    pub cas_part_indices: [u8; 2],
+    /// Indices into `tgis` of the fat, fit, thin and special morphs.
+    pub blend_indices: [u8; 4],
+    pub overlay_priority: u32,
+    /// Indices into `tgis` of the VPXYs that list the meshes.
+    pub vpxy_indices: Vec<u8>,
+    pub lods: Vec<LodInfo>,
+    /// Indices into `tgis` of the diffuse textures.
+    pub diffuse_indices: Vec<u8>,
+    /// Indices into `tgis` of the specular textures.
+    pub specular_indices: Vec<u8>,
+    /// Indices into `tgis` of the bone deltas (BOND).
+    pub bond_indices: Vec<u8>,
+    /// Whatever lies between the known fields and the TGI table.
+    pub trailing: Vec<u8>,
+    pub tgis: Vec<TGI>,
+}
+
+impl CASP {
+    fn resolve<'a>(&'a self, indices: &'a [u8]) -> impl Iterator<Item = &'a TGI> + 'a {
+        indices.iter().filter_map(|&i| self.tgis.get(i as usize))
+    }
+
+    /// The VPXYs that list this part's meshes.
+    pub fn vpxys(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.resolve(&self.vpxy_indices)
+    }
+
+    pub fn diffuse_maps(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.resolve(&self.diffuse_indices)
+    }
+
+    pub fn specular_maps(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.resolve(&self.specular_indices)
+    }
+
+    pub fn bonds(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.resolve(&self.bond_indices)
+    }
+
+    /// The fat, fit, thin and special morphs. An index pointing outside the TGI table means there is none.
+    pub fn blend_infos(&self) -> [Option<&TGI>; 4] {
+        self.blend_indices.map(|i| self.tgis.get(i as usize))
+    }
+
+    /// Every GEOM in the TGI table. These are the part's meshes, for all LODs.
+    pub fn geometries(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.tgis.iter().filter(|t| t.resource_type == ResourceType::GEOM as u32)
+    }
+
+    pub fn clothing_type(&self) -> Option<ClothingType> {
+        num_traits::FromPrimitive::from_u32(self.clothing)
+    }
+
+    pub fn set_clothing_type(&mut self, clothing: ClothingType) {
+        self.clothing = clothing as u32;
+    }
+
+    pub fn has_category(&self, flags: u32) -> bool {
+        self.category & flags == flags
+    }
+
+    pub fn has_data_type(&self, flags: u32) -> bool {
+        self.data_type & flags == flags
+    }
+
+    /// Index of `tgi` in the TGI table, adding it if needed.
+    pub fn tgi_index(&mut self, tgi: TGI) -> Option<u8> {
+        match self.tgis.iter().position(|t| *t == tgi) {
+            Some(i) => i.try_into().ok(),
+            None => {
+                let i = self.tgis.len().try_into().ok()?;
+                self.tgis.push(tgi);
+                Some(i)
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+}
+
+impl BinRead for CASP {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let (version, tgi_offset) = <(u32, u32)>::read_options(reader, endian, ())?;
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 8 + tgi_offset as u64;
+
+        let body = CASPBody::read_options(reader, endian, ())?;
+        let end = reader.stream_position()?;
+        if end > tgi_pos {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: "CASP fields overlap the TGI table".to_string(),
+            });
+        }
+        let mut trailing = Vec::new();
+        reader.take(tgi_pos - end).read_to_end(&mut trailing)?;
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u8::read_options(reader, endian, ())?;
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::IGT,
+            },
+        )?;
+
+        Ok(CASP {
+            version,
+            presets: body.presets,
+            name: body.name,
+            sort_priority: body.sort_priority,
+            secondary_sort_index: body.secondary_sort_index,
+            clothing: body.clothing,
+            data_type: body.data_type,
+            age_gender: AgeGender(body.age_gender),
+            category: body.category,
+            cas_part_indices: body.cas_part_indices,
+            blend_indices: body.blend_indices,
+            overlay_priority: body.overlay_priority,
+            vpxy_indices: body.vpxy_indices,
+            lods: body.lods,
+            diffuse_indices: body.diffuse_indices,
+            specular_indices: body.specular_indices,
+            bond_indices: body.bond_indices,
+            trailing,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for CASP {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many {} in CASP", what),
+        };
+        let mut body = io::Cursor::new(Vec::new());
+        CASPBody {
+            presets: self.presets.clone(),
+            name: self.name.clone(),
+            sort_priority: self.sort_priority,
+            secondary_sort_index: self.secondary_sort_index,
+            clothing: self.clothing,
+            data_type: self.data_type,
+            age_gender: self.age_gender.0,
+            category: self.category,
+            cas_part_indices: self.cas_part_indices,
+            blend_indices: self.blend_indices,
+            overlay_priority: self.overlay_priority,
+            vpxy_indices: self.vpxy_indices.clone(),
+            lods: self.lods.clone(),
+            diffuse_indices: self.diffuse_indices.clone(),
+            specular_indices: self.specular_indices.clone(),
+            bond_indices: self.bond_indices.clone(),
+        }
+        .write_options(&mut body, endian, ())?;
+        body.write_all(&self.trailing)?;
+        let body = body.into_inner();
+
+        let tgi_count: u8 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        // relative to the end of the offset field, which is where the body starts
+        let tgi_offset: u32 = body.len().try_into().map_err(|_| overflow("bytes"))?;
+        (self.version, tgi_offset).write_options(writer, endian, ())?;
+        writer.write_all(&body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::IGT)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn igt(tgi: TGI) -> Vec<u8> {
+        let mut bytes = tgi.instance.to_le_bytes().to_vec();
+        bytes.extend(tgi.resource_group.to_le_bytes());
+        bytes.extend(tgi.resource_type.to_le_bytes());
+        bytes
+    }
+
+    /// A part with two presets, a name long enough to need a two byte length, and a few trailing
+    /// bytes before the TGI table.
+    fn sample() -> (Vec<u8>, CASP) {
+        let xml = "<preset><complate name=\"CasRgbMask\"/></preset>";
+        let name = "afBodyDressLong".repeat(5);
+        let vpxy = TGI::new(ResourceType::VPXY as u32, 0, 0x1111);
+        let diffuse = TGI::new(0x00B2D882, 0, 0x2222);
+        let geom = TGI::new(ResourceType::GEOM as u32, 0, 0x3333);
+
+        let mut body = Vec::new();
+        body.extend(2u32.to_le_bytes());
+        for (xml, unknown) in [(xml, 7u32), ("", 8)] {
+            body.extend((xml.encode_utf16().count() as u32).to_le_bytes());
+            body.extend(xml.encode_utf16().flat_map(u16::to_le_bytes));
+            body.extend(unknown.to_le_bytes());
+        }
+        let name_bytes: Vec<u8> = name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(name_bytes.len(), 150);
+        body.extend([0x96, 0x01]);
+        body.extend(&name_bytes);
+        body.extend(1.5f32.to_le_bytes());
+        body.push(3);
+        for field in [ClothingType::Body as u32, data_type::BODY, 0x2030, category::EVERYDAY] {
+            body.extend(field.to_le_bytes());
+        }
+        body.extend([0xFF, 0xFF]);
+        body.extend([0xFF, 0xFF, 0xFF, 0xFF]);
+        body.extend(9u32.to_le_bytes());
+        body.extend([1, 0]); // vpxy
+        body.extend([1, 0, 0, 0, 0, 0, 1]); // one LOD with one asset
+        for field in [4u32, 5, 6] {
+            body.extend(field.to_le_bytes());
+        }
+        body.extend([1, 1]); // diffuse
+        body.extend([0]); // specular
+        body.extend([0]); // bond
+        body.extend([0xDE, 0xAD]);
+
+        let mut bytes = 18u32.to_le_bytes().to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(&body);
+        bytes.push(3);
+        for tgi in [vpxy, diffuse, geom] {
+            bytes.extend(igt(tgi));
+        }
+
+        let casp = CASP {
+            version: 18,
+            presets: vec![
+                Preset {
+                    xml: xml.to_string(),
+                    unknown: 7,
+                },
+                Preset {
+                    xml: String::new(),
+                    unknown: 8,
+                },
+            ],
+            name,
+            sort_priority: 1.5,
+            secondary_sort_index: 3,
+            clothing: ClothingType::Body as u32,
+            data_type: data_type::BODY,
+            age_gender: AgeGender(0x2030),
+            category: category::EVERYDAY,
+            cas_part_indices: [0xFF; 2],
+            blend_indices: [0xFF; 4],
+            overlay_priority: 9,
+            vpxy_indices: vec![0],
+            lods: vec![LodInfo {
+                level: 0,
+                unused: 0,
+                assets: vec![LodAsset {
+                    sorting: 4,
+                    spec_level: 5,
+                    cast_shadow: 6,
+                }],
+            }],
+            diffuse_indices: vec![1],
+            specular_indices: vec![],
+            bond_indices: vec![],
+            trailing: vec![0xDE, 0xAD],
+            tgis: vec![vpxy, diffuse, geom],
+        };
+        (bytes, casp)
+    }
+
+    #[test]
+    fn read_write_round_trip() {
+        let (bytes, expected) = sample();
+        let casp = CASP::read_le(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(casp, expected);
+        assert_eq!(casp.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn indices_resolve() {
+        let (_, casp) = sample();
+        assert_eq!(casp.vpxys().collect::<Vec<_>>(), [&casp.tgis[0]]);
+        assert_eq!(casp.diffuse_maps().collect::<Vec<_>>(), [&casp.tgis[1]]);
+        assert_eq!(casp.geometries().collect::<Vec<_>>(), [&casp.tgis[2]]);
+        assert_eq!(casp.blend_infos(), [None; 4]);
+        assert_eq!(casp.clothing_type(), Some(ClothingType::Body));
+        assert_eq!(casp.age_gender.to_string(), "YA-F");
+        assert_eq!(casp.age_gender.species(), Some(Species::Human));
+    }
+
+    #[test]
+    fn rejects_overlapping_tgi_offset() {
+        let (mut bytes, _) = sample();
+        bytes[4..8].copy_from_slice(&4u32.to_le_bytes());
+        assert!(CASP::read_le(&mut io::Cursor::new(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,745 @@
+//! DDS textures, as stored in `_IMG` resources, and a software decoder for the formats the game uses:
+//! DXT1/3/5 (BC1/2/3) and uncompressed RGB(A), luminance and alpha. Images can be encoded back
+//! to DXT1 or DXT5, with mipmaps.
+
+use binrw::{binrw, helpers::until_eof, BinResult};
+use std::fmt;
+use std::io::{self, BufRead, Seek, Write};
+
+use crate::error::Error;
+
+pub const DDSD_CAPS: u32 = 0x1;
+pub const DDSD_HEIGHT: u32 = 0x2;
+pub const DDSD_WIDTH: u32 = 0x4;
+pub const DDSD_PITCH: u32 = 0x8;
+pub const DDSD_PIXELFORMAT: u32 = 0x1000;
+pub const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+pub const DDSD_LINEARSIZE: u32 = 0x80000;
+
+pub const DDPF_ALPHAPIXELS: u32 = 0x1;
+pub const DDPF_ALPHA: u32 = 0x2;
+pub const DDPF_FOURCC: u32 = 0x4;
+pub const DDPF_RGB: u32 = 0x40;
+pub const DDPF_LUMINANCE: u32 = 0x20000;
+
+pub const DDSCAPS_COMPLEX: u32 = 0x8;
+pub const DDSCAPS_TEXTURE: u32 = 0x1000;
+pub const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+#[binrw]
+#[brw(magic = 32u32)] // size
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PixelFormat {
+    pub flags: u32,
+    pub four_cc: [u8; 4],
+    pub rgb_bit_count: u32,
+    pub r_mask: u32,
+    pub g_mask: u32,
+    pub b_mask: u32,
+    pub a_mask: u32,
+}
+
+#[binrw]
+#[brw(magic = b"DDS \x7C\0\0\0")] // magic, then the header size (124)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DDSHeader {
+    pub flags: u32,
+    pub height: u32,
+    pub width: u32,
+    pub pitch_or_linear_size: u32,
+    pub depth: u32,
+    pub mip_map_count: u32,
+    pub reserved1: [u32; 11],
+    pub pixel_format: PixelFormat,
+    pub caps: u32,
+    pub caps2: u32,
+    pub caps3: u32,
+    pub caps4: u32,
+    pub reserved2: u32,
+}
+
+#[binrw]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DDS {
+    pub header: DDSHeader,
+    /// Every mip level, largest first.
+    #[br(parse_with = until_eof)]
+    pub data: Vec<u8>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// DXT1
+    BC1,
+    /// DXT2/3
+    BC2,
+    /// DXT4/5
+    BC3,
+    /// RGB(A), luminance or alpha, described by the masks in the [`PixelFormat`].
+    Uncompressed,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::BC1 => "DXT1",
+            Format::BC2 => "DXT3",
+            Format::BC3 => "DXT5",
+            Format::Uncompressed => "uncompressed",
+        })
+    }
+}
+
+impl Format {
+    fn block_size(&self) -> usize {
+        match self {
+            Format::BC1 => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// 8-bit RGBA pixels, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Read a PNG, converting it to 8-bit RGBA.
+    pub fn read_png<R: BufRead + Seek>(reader: R) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        buf.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "indexed PNG wasn't expanded"))
+            }
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let at = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[at..at + 4].try_into().unwrap()
+    }
+
+    /// Bilinear resize, for images that don't match the texture they replace.
+    pub fn resize(&self, width: u32, height: u32) -> Image {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            let sy = ((y as f32 + 0.5) * self.height as f32 / height as f32 - 0.5).max(0.0);
+            let (y0, fy) = (sy as u32, sy.fract());
+            let y1 = (y0 + 1).min(self.height - 1);
+            for x in 0..width {
+                let sx = ((x as f32 + 0.5) * self.width as f32 / width as f32 - 0.5).max(0.0);
+                let (x0, fx) = (sx as u32, sx.fract());
+                let x1 = (x0 + 1).min(self.width - 1);
+                let [a, b, c, d] = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| self.pixel(x, y));
+                for i in 0..4 {
+                    let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+                    let bottom = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+                    pixels.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+                }
+            }
+        }
+        Image { width, height, pixels }
+    }
+
+    /// The next mip level: half the size (at least 1x1), box filtered.
+    pub fn half(&self) -> Image {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = ((x * 2).min(self.width - 1), (y * 2).min(self.height - 1));
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let texels = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].map(|(x, y)| self.pixel(x, y));
+                for i in 0..4 {
+                    pixels.push(((texels.iter().map(|t| t[i] as u32).sum::<u32>() + 2) / 4) as u8);
+                }
+            }
+        }
+        Image { width, height, pixels }
+    }
+}
+
+fn unsupported(message: String) -> binrw::Error {
+    Error::UnknownFormat {
+        tgi: None,
+        offset: 0,
+        message,
+    }
+    .into()
+}
+
+impl DDS {
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    pub fn format(&self) -> Option<Format> {
+        let pf = &self.header.pixel_format;
+        if pf.flags & DDPF_FOURCC != 0 {
+            match &pf.four_cc {
+                b"DXT1" => Some(Format::BC1),
+                b"DXT2" | b"DXT3" => Some(Format::BC2),
+                b"DXT4" | b"DXT5" => Some(Format::BC3),
+                _ => None,
+            }
+        } else if pf.flags & (DDPF_RGB | DDPF_LUMINANCE | DDPF_ALPHA) != 0
+            && matches!(pf.rgb_bit_count, 8 | 16 | 24 | 32)
+        {
+            Some(Format::Uncompressed)
+        } else {
+            None
+        }
+    }
+
+    /// Number of bytes in the largest mip level, or `None` if that doesn't fit in memory.
+    fn top_level_size(&self, format: Format) -> Option<usize> {
+        let (w, h) = (self.width() as usize, self.height() as usize);
+        match format {
+            Format::Uncompressed => w.checked_mul(h)?.checked_mul(self.header.pixel_format.rgb_bit_count as usize / 8),
+            _ => w.div_ceil(4).checked_mul(h.div_ceil(4))?.checked_mul(format.block_size()),
+        }
+    }
+
+    /// Encode `image` as DXT1 or DXT5 with `mip_count` levels (at least one).
+    pub fn encode(image: &Image, format: Format, mip_count: u32) -> BinResult<DDS> {
+        let four_cc = match format {
+            Format::BC1 => *b"DXT1",
+            Format::BC3 => *b"DXT5",
+            _ => return Err(unsupported(format!("can't encode {:?}", format))),
+        };
+        let mip_count = mip_count.max(1);
+        let mut data = Vec::new();
+        let mut level = image.clone();
+        for i in 0..mip_count {
+            if i != 0 {
+                level = level.half();
+            }
+            encode_blocks(&level, format, &mut data);
+        }
+
+        let mut header = DDSHeader {
+            flags: DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_LINEARSIZE,
+            height: image.height,
+            width: image.width,
+            pitch_or_linear_size: (image.width.div_ceil(4) * image.height.div_ceil(4)) * format.block_size() as u32,
+            pixel_format: PixelFormat {
+                flags: DDPF_FOURCC,
+                four_cc,
+                ..Default::default()
+            },
+            caps: DDSCAPS_TEXTURE,
+            ..Default::default()
+        };
+        if mip_count > 1 {
+            header.flags |= DDSD_MIPMAPCOUNT;
+            header.mip_map_count = mip_count;
+            header.caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+        }
+        Ok(DDS { header, data })
+    }
+
+    /// Encode `image` so that it can stand in for this texture: same size (resizing if needed),
+    /// same number of mip levels, and DXT1 if this is DXT1, otherwise DXT5. Compare the [`format`](Self::format)
+    /// of the two to find out whether it changed.
+    pub fn encode_like(&self, image: &Image) -> BinResult<DDS> {
+        let format = match self.format() {
+            Some(Format::BC1) => Format::BC1,
+            _ => Format::BC3,
+        };
+        let mip_count = if self.header.flags & DDSD_MIPMAPCOUNT != 0 {
+            self.header.mip_map_count
+        } else {
+            1
+        };
+        DDS::encode(&image.resize(self.width(), self.height()), format, mip_count)
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+
+    /// Decode the largest mip level.
+    pub fn decode(&self) -> BinResult<Image> {
+        let format = self.format().ok_or_else(|| {
+            let pf = &self.header.pixel_format;
+            unsupported(format!(
+                "unsupported DDS format (flags {:X}, fourCC {:?}, {} bits)",
+                pf.flags,
+                String::from_utf8_lossy(&pf.four_cc),
+                pf.rgb_bit_count
+            ))
+        })?;
+        let size = self.top_level_size(format);
+        let data = size
+            .and_then(|size| self.data.get(..size))
+            .ok_or_else(|| Error::SizeMismatch {
+                tgi: None,
+                offset: 128,
+                expected: size.map_or(u64::MAX, |size| size as u64),
+                found: self.data.len() as u64,
+            })?;
+
+        let (width, height) = (self.width(), self.height());
+        let pixels = match format {
+            Format::Uncompressed => decode_uncompressed(data, &self.header.pixel_format, width, height),
+            _ => decode_blocks(data, format, width, height),
+        };
+        Ok(Image { width, height, pixels })
+    }
+}
+
+/// Expand a masked channel to 8 bits. Missing channels come out as `default`.
+fn channel(pixel: u32, mask: u32, default: u8) -> u8 {
+    if mask == 0 {
+        return default;
+    }
+    // in u64, since masks can be up to 32 bits wide
+    let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    ((value * 255 + max / 2) / max) as u8
+}
+
+fn decode_uncompressed(data: &[u8], pf: &PixelFormat, width: u32, height: u32) -> Vec<u8> {
+    let bytes = pf.rgb_bit_count as usize / 8;
+    let has_alpha = pf.flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA) != 0;
+    let a_mask = if has_alpha { pf.a_mask } else { 0 };
+    let mut out = Vec::with_capacity(width as usize * height as usize * 4);
+    for px in data.chunks_exact(bytes) {
+        let mut raw = [0u8; 4];
+        raw[..bytes].copy_from_slice(px);
+        let pixel = u32::from_le_bytes(raw);
+        let a = channel(pixel, a_mask, 255);
+        if pf.flags & DDPF_LUMINANCE != 0 {
+            let l = channel(pixel, pf.r_mask, 0);
+            out.extend([l, l, l, a]);
+        } else if pf.flags & DDPF_RGB != 0 {
+            out.extend([
+                channel(pixel, pf.r_mask, 0),
+                channel(pixel, pf.g_mask, 0),
+                channel(pixel, pf.b_mask, 0),
+                a,
+            ]);
+        } else {
+            // alpha only
+            out.extend([255, 255, 255, a]);
+        }
+    }
+    out
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let r = ((c >> 11) & 0x1F) as u32;
+    let g = ((c >> 5) & 0x3F) as u32;
+    let b = (c & 0x1F) as u32;
+    [(r * 255 + 15) / 31, (g * 255 + 31) / 63, (b * 255 + 15) / 31].map(|x| x as u8)
+}
+
+/// Decode the colour part of a block. `four_colour` is false for BC1 blocks with c0 <= c1,
+/// which have three colours and transparent black.
+fn decode_colour_block(block: &[u8], four_colour: bool, out: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u32, wb: u32, d: u32| -> [u8; 4] {
+        let m = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb + d / 2) / d) as u8;
+        [m(0), m(1), m(2), 255]
+    };
+    let palette = if four_colour || c0 > c1 {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, px) in out.iter_mut().enumerate() {
+        *px = palette[(indices >> (2 * i) & 3) as usize];
+    }
+}
+
+fn decode_bc2_alpha(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, px) in out.iter_mut().enumerate() {
+        px[3] = ((bits >> (4 * i)) & 0xF) as u8 * 17;
+    }
+}
+
+fn decode_bc3_alpha(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1 + 3) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1 + 2) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+    let mut raw = [0u8; 8];
+    raw[..6].copy_from_slice(&block[2..8]);
+    let bits = u64::from_le_bytes(raw);
+    for (i, px) in out.iter_mut().enumerate() {
+        px[3] = palette[((bits >> (3 * i)) & 7) as usize] as u8;
+    }
+}
+
+fn decode_blocks(data: &[u8], format: Format, width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let mut out = vec![0u8; width * height * 4];
+    for (n, block) in data.chunks_exact(format.block_size()).enumerate() {
+        let (bx, by) = (n % blocks_wide * 4, n / blocks_wide * 4);
+        let mut texels = [[0u8; 4]; 16];
+        match format {
+            Format::BC1 => decode_colour_block(block, false, &mut texels),
+            Format::BC2 => {
+                decode_colour_block(&block[8..], true, &mut texels);
+                decode_bc2_alpha(block, &mut texels);
+            }
+            Format::BC3 => {
+                decode_colour_block(&block[8..], true, &mut texels);
+                decode_bc3_alpha(block, &mut texels);
+            }
+            Format::Uncompressed => unreachable!(),
+        }
+        // blocks hanging over the right or bottom edge are cropped
+        for (i, texel) in texels.iter().enumerate() {
+            let (x, y) = (bx + i % 4, by + i / 4);
+            if x < width && y < height {
+                let at = (y * width + x) * 4;
+                out[at..at + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    out
+}
+
+fn to_565(c: [f32; 3]) -> u16 {
+    let q = |v: f32, max: f32| (v.clamp(0.0, 255.0) * max / 255.0).round() as u16;
+    (q(c[0], 31.0) << 11) | (q(c[1], 63.0) << 5) | q(c[2], 31.0)
+}
+
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+/// Encode the colour part of a block. Endpoints come from the bounding box of the colours,
+/// along whichever diagonal follows their spread, inset slightly to reduce error.
+///
+/// With `punch_through`, texels with alpha below 128 become transparent (BC1 three-colour mode).
+fn encode_colour_block(texels: &[[u8; 4]; 16], punch_through: bool) -> [u8; 8] {
+    let transparent = |t: &[u8; 4]| punch_through && t[3] < 128;
+    let opaque: Vec<_> = texels.iter().filter(|t| !transparent(t)).collect();
+    if opaque.is_empty() {
+        // c0 <= c1 with every index 3: all transparent
+        return [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+    }
+
+    let mut min = [255.0f32; 3];
+    let mut max = [0.0f32; 3];
+    let mut mean = [0.0f32; 3];
+    for t in &opaque {
+        for i in 0..3 {
+            min[i] = min[i].min(t[i] as f32);
+            max[i] = max[i].max(t[i] as f32);
+            mean[i] += t[i] as f32 / opaque.len() as f32;
+        }
+    }
+    // flip green/blue if they go down as red goes up
+    let mut cov = [0.0f32; 2];
+    for t in &opaque {
+        let d = [0, 1, 2].map(|i| t[i] as f32 - mean[i]);
+        cov[0] += d[0] * d[1];
+        cov[1] += d[0] * d[2];
+    }
+    if cov[0] < 0.0 {
+        std::mem::swap(&mut min[1], &mut max[1]);
+    }
+    if cov[1] < 0.0 {
+        std::mem::swap(&mut min[2], &mut max[2]);
+    }
+    for i in 0..3 {
+        let inset = (max[i] - min[i]) / 16.0;
+        min[i] += inset;
+        max[i] -= inset;
+    }
+
+    let (mut c0, mut c1) = (to_565(max), to_565(min));
+    let three_colour = punch_through && opaque.len() != 16;
+    if three_colour ^ (c0 <= c1) {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+    if !three_colour && c0 == c1 {
+        // a single colour: any index works, but c0 > c1 must hold for four colour mode
+        let bytes = [c0.to_le_bytes(), c1.to_le_bytes()].concat();
+        return [bytes[0], bytes[1], bytes[2], bytes[3], 0, 0, 0, 0];
+    }
+
+    let mut block = [0u8; 8];
+    block[..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+    // reuse the decoder to get the exact palette the game will see, with indices 0..3 for the first texels
+    let mut probe = block;
+    probe[4] = 0b11_10_01_00;
+    let mut palette = [[0u8; 4]; 16];
+    decode_colour_block(&probe, false, &mut palette);
+    let palette = [palette[0], palette[1], palette[2], palette[3]];
+    let candidates = if three_colour { 3 } else { 4 };
+
+    let mut indices = 0u32;
+    for (i, t) in texels.iter().enumerate() {
+        let index = if transparent(t) {
+            3
+        } else {
+            (0..candidates).min_by_key(|&p| distance(*t, palette[p])).unwrap() as u32
+        };
+        indices |= index << (2 * i);
+    }
+    block[4..].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+fn encode_bc3_alpha(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let min = texels.iter().map(|t| t[3]).min().unwrap();
+    let max = texels.iter().map(|t| t[3]).max().unwrap();
+    let mut block = [0u8; 8];
+    if min == max {
+        // a0 == a1 picks the six-value mode; index 0 is a0 either way
+        block[0] = max;
+        block[1] = min;
+        return block;
+    }
+    // eight-value mode, a0 > a1
+    block[0] = max;
+    block[1] = min;
+    let mut palette = [[0u8; 4]; 16];
+    let mut probe = block;
+    // indices 0..7 for the first eight texels
+    let bits: u64 = (0..8u64).fold(0, |acc, i| acc | (i << (3 * i)));
+    probe[2..8].copy_from_slice(&bits.to_le_bytes()[..6]);
+    decode_bc3_alpha(&probe, &mut palette);
+
+    let mut indices = 0u64;
+    for (i, t) in texels.iter().enumerate() {
+        let index = (0..8).min_by_key(|&p| (palette[p][3] as i32 - t[3] as i32).abs()).unwrap() as u64;
+        indices |= index << (3 * i);
+    }
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+fn encode_blocks(image: &Image, format: Format, out: &mut Vec<u8>) {
+    let punch_through = format == Format::BC1 && image.pixels.chunks_exact(4).any(|p| p[3] < 128);
+    for by in (0..image.height).step_by(4) {
+        for bx in (0..image.width).step_by(4) {
+            // pixels past the edge repeat the last row/column
+            let texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+                let x = (bx + i as u32 % 4).min(image.width - 1);
+                let y = (by + i as u32 / 4).min(image.height - 1);
+                image.pixel(x, y)
+            });
+            match format {
+                Format::BC1 => out.extend(encode_colour_block(&texels, punch_through)),
+                _ => {
+                    out.extend(encode_bc3_alpha(&texels));
+                    out.extend(encode_colour_block(&texels, false));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinRead;
+
+    fn compressed(four_cc: &[u8; 4], width: u32, height: u32, data: Vec<u8>) -> DDS {
+        DDS {
+            header: DDSHeader {
+                width,
+                height,
+                pixel_format: PixelFormat {
+                    flags: DDPF_FOURCC,
+                    four_cc: *four_cc,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            data,
+        }
+    }
+
+    fn texels(image: &Image) -> Vec<[u8; 4]> {
+        image.pixels.chunks_exact(4).map(|p| p.try_into().unwrap()).collect()
+    }
+
+    /// Red and blue endpoints, with the first four texels using indices 0 to 3.
+    fn colour_block(c0: u16, c1: u16) -> Vec<u8> {
+        let mut block = [c0.to_le_bytes(), c1.to_le_bytes()].concat();
+        block.extend([0b11_10_01_00, 0, 0, 0]);
+        block
+    }
+
+    #[test]
+    fn decodes_bc1() {
+        let image = compressed(b"DXT1", 4, 4, colour_block(0xF800, 0x001F)).decode().unwrap();
+        assert_eq!(
+            texels(&image)[..5],
+            [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255], [255, 0, 0, 255]]
+        );
+
+        // c0 <= c1: three colours and transparent black
+        let image = compressed(b"DXT1", 4, 4, colour_block(0x001F, 0xF800)).decode().unwrap();
+        assert_eq!(texels(&image)[..4], [[0, 0, 255, 255], [255, 0, 0, 255], [128, 0, 128, 255], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn decodes_bc2() {
+        let mut data = vec![0x8F, 0, 0, 0, 0, 0, 0, 0];
+        // BC2 and BC3 colours are always four colour, even with c0 <= c1
+        data.extend(colour_block(0x001F, 0xF800));
+        let image = compressed(b"DXT3", 4, 4, data).decode().unwrap();
+        assert_eq!(texels(&image)[..4], [[0, 0, 255, 255], [255, 0, 0, 136], [85, 0, 170, 0], [170, 0, 85, 0]]);
+    }
+
+    #[test]
+    fn decodes_bc3() {
+        let indices = [0x88, 0x0E, 0, 0, 0, 0];
+        let mut data = vec![255, 0];
+        data.extend(indices);
+        data.extend(colour_block(0xF800, 0x001F));
+        let image = compressed(b"DXT5", 4, 4, data).decode().unwrap();
+        assert_eq!(texels(&image)[..4].iter().map(|t| t[3]).collect::<Vec<_>>(), [255, 0, 219, 36]);
+
+        // a0 <= a1: six values, then 0 and 255
+        let mut data = vec![0, 255];
+        data.extend(indices);
+        data.extend(colour_block(0xF800, 0x001F));
+        let image = compressed(b"DXT5", 4, 4, data).decode().unwrap();
+        assert_eq!(texels(&image)[..4].iter().map(|t| t[3]).collect::<Vec<_>>(), [0, 255, 51, 255]);
+    }
+
+    #[test]
+    fn crops_partial_blocks() {
+        let data = [colour_block(0xF800, 0x001F), colour_block(0x001F, 0xF800)].concat();
+        let image = compressed(b"DXT1", 5, 3, data).decode().unwrap();
+        assert_eq!((image.width, image.height, image.pixels.len()), (5, 3, 5 * 3 * 4));
+        assert_eq!(texels(&image)[4], [0, 0, 255, 255]);
+    }
+
+    /// Colours along one line through RGB, which is what a block can hold, and alpha across.
+    fn gradient(width: u32, height: u32) -> Image {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let v = ((x + y) * 255 / (width + height - 2)) as u8;
+                pixels.extend([v, v / 2, 255 - v, (x * 255 / (width - 1)) as u8]);
+            }
+        }
+        Image { width, height, pixels }
+    }
+
+    fn max_error(a: &Image, b: &Image, channels: std::ops::Range<usize>) -> u8 {
+        texels(a)
+            .iter()
+            .zip(texels(b))
+            .flat_map(|(a, b)| channels.clone().map(move |i| a[i].abs_diff(b[i])))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn bc1_round_trip() {
+        let solid = Image {
+            width: 8,
+            height: 8,
+            pixels: [255, 0, 0, 255].repeat(64),
+        };
+        let dds = DDS::encode(&solid, Format::BC1, 1).unwrap();
+        assert_eq!(dds.data.len(), 4 * 8);
+        assert_eq!(dds.decode().unwrap(), solid);
+
+        let mut image = gradient(8, 8);
+        let decoded = DDS::encode(&image, Format::BC1, 1).unwrap().decode().unwrap();
+        // alpha below 128 is punched through, the rest is opaque
+        for (original, decoded) in texels(&image).iter().zip(texels(&decoded)) {
+            assert_eq!(decoded[3], if original[3] < 128 { 0 } else { 255 });
+        }
+
+        image.pixels.chunks_exact_mut(4).for_each(|p| p[3] = 255);
+        let decoded = DDS::encode(&image, Format::BC1, 1).unwrap().decode().unwrap();
+        assert!(max_error(&image, &decoded, 0..4) <= 24);
+    }
+
+    #[test]
+    fn bc3_round_trip() {
+        let image = gradient(8, 8);
+        let dds = DDS::encode(&image, Format::BC3, 1).unwrap();
+        assert_eq!(dds.data.len(), 4 * 16);
+        let decoded = dds.decode().unwrap();
+        assert!(max_error(&image, &decoded, 0..3) <= 24);
+        assert!(max_error(&image, &decoded, 3..4) <= 8);
+    }
+
+    #[test]
+    fn encodes_mipmaps() {
+        let dds = DDS::encode(&gradient(8, 8), Format::BC1, 4).unwrap();
+        // 8x8, 4x4, 2x2 and 1x1 each take at least a block
+        assert_eq!(dds.data.len(), 8 * (4 + 1 + 1 + 1));
+        assert_eq!(dds.header.mip_map_count, 4);
+        let dds = DDS::read_le(&mut io::Cursor::new(dds.to_bytes().unwrap())).unwrap();
+        assert_eq!(dds.encode_like(&gradient(4, 4)).unwrap().data.len(), dds.data.len());
+        assert!(DDS::encode(&gradient(8, 8), Format::BC2, 1).is_err());
+
+        // DXT3 can't be encoded, so it turns into DXT5
+        let mut dxt3 = dds;
+        dxt3.header.pixel_format.four_cc = *b"DXT3";
+        assert_eq!(dxt3.encode_like(&gradient(8, 8)).unwrap().format(), Some(Format::BC3));
+    }
+
+    #[test]
+    fn wide_channel_masks() {
+        assert_eq!(channel(0xFFFF_FFFF, 0xFFFF_FFFF, 0), 255);
+        assert_eq!(channel(0x8000_0000, 0xFFFF_FFFF, 0), 128);
+        assert_eq!(channel(0xFFFF_FF00, 0xFFFF_FF00, 0), 255);
+        assert_eq!(channel(0x0000_F800, 0x0000_F800, 0), 255);
+        assert_eq!(channel(0x1234, 0, 7), 7);
+    }
+}
@@ -0,0 +1,533 @@
+//! GEOM, the mesh format used by CAS parts. Always found inside an [`RCOL`](super::rcol::RCOL).
+//!
+//! Only version 5 (the version shipped with Sims 3) is supported.
+
+use super::mtnf::MTNF;
+use super::rcol::{ChunkTag, RCOL};
+use super::tgi::{TGIOrder, TGI};
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum VertexUsage {
+    Position = 1,
+    Normal = 2,
+    UV = 3,
+    BoneAssignment = 4,
+    Weights = 5,
+    TangentNormal = 6,
+    Color = 7,
+    VertexID = 10,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum VertexDataType {
+    Float = 1,
+    Byte = 2,
+    ARGB = 3,
+    UInt32 = 4,
+}
+
+#[binrw]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct VertexFormat {
+    pub usage: u32,
+    pub data_type: u32,
+    pub size: u8,
+}
+
+impl VertexFormat {
+    pub fn new(usage: VertexUsage, data_type: VertexDataType, size: u8) -> Self {
+        VertexFormat {
+            usage: usage as u32,
+            data_type: data_type as u32,
+            size,
+        }
+    }
+
+    pub fn usage(&self) -> Option<VertexUsage> {
+        num_traits::FromPrimitive::from_u32(self.usage)
+    }
+
+    pub fn data_type(&self) -> Option<VertexDataType> {
+        num_traits::FromPrimitive::from_u32(self.data_type)
+    }
+
+    // Which field of `Vertices` this element is decoded into.
+    fn element(&self) -> Element {
+        use VertexDataType as D;
+        use VertexUsage as U;
+        match (self.usage(), self.data_type(), self.size) {
+            (Some(U::Position), Some(D::Float), 12) => Element::Position,
+            (Some(U::Normal), Some(D::Float), 12) => Element::Normal,
+            (Some(U::UV), Some(D::Float), 8) => Element::UV,
+            (Some(U::BoneAssignment), Some(D::Byte), 4) => Element::BoneAssignment,
+            (Some(U::Weights), Some(D::Float), 16) => Element::Weights,
+            (Some(U::Weights), Some(D::Byte), 4) => Element::ByteWeights,
+            (Some(U::TangentNormal), Some(D::Float), 12) => Element::Tangent,
+            (Some(U::Color), Some(D::ARGB | D::UInt32), 4) => Element::Color,
+            (Some(U::VertexID), Some(D::UInt32), 4) => Element::VertexID,
+            _ => Element::Raw,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Element {
+    Position,
+    Normal,
+    UV,
+    BoneAssignment,
+    Weights,
+    ByteWeights,
+    Tangent,
+    Color,
+    VertexID,
+    Raw,
+}
+
+/// Decoded vertex data, one `Vec` per attribute.
+///
+/// Attributes the vertex format doesn't have are left empty. There is one UV set per UV element.
+/// Elements that don't decode to a known attribute are kept as raw bytes, in format order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vertices {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<Vec<[f32; 2]>>,
+    /// Indices into [`Geometry::bone_hashes`].
+    pub bone_assignments: Vec<[u8; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    pub tangents: Vec<[f32; 3]>,
+    /// ARGB
+    pub colors: Vec<u32>,
+    pub vertex_ids: Vec<u32>,
+    pub raw: Vec<Vec<Vec<u8>>>,
+}
+
+fn read_f32s<const N: usize>(data: &[u8]) -> [f32; N] {
+    std::array::from_fn(|i| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap()))
+}
+
+impl Vertices {
+    fn read(data: &[u8], formats: &[VertexFormat], count: usize) -> Self {
+        let stride: usize = formats.iter().map(|f| f.size as usize).sum();
+        let uv_sets = formats.iter().filter(|f| f.element() == Element::UV).count();
+        let raw_sets = formats.iter().filter(|f| f.element() == Element::Raw).count();
+        // there can't be more vertices than fit in the data, whatever the header says
+        let count = count.min(data.len().checked_div(stride).unwrap_or(0));
+        let mut v = Vertices {
+            uvs: vec![Vec::with_capacity(count); uv_sets],
+            raw: vec![Vec::with_capacity(count); raw_sets],
+            ..Default::default()
+        };
+        if stride == 0 {
+            return v;
+        }
+
+        for vertex in data.chunks_exact(stride).take(count) {
+            let mut offset = 0;
+            let (mut uv, mut raw) = (0, 0);
+            for format in formats {
+                let e = &vertex[offset..offset + format.size as usize];
+                offset += format.size as usize;
+                match format.element() {
+                    Element::Position => v.positions.push(read_f32s(e)),
+                    Element::Normal => v.normals.push(read_f32s(e)),
+                    Element::Tangent => v.tangents.push(read_f32s(e)),
+                    Element::UV => {
+                        v.uvs[uv].push(read_f32s(e));
+                        uv += 1;
+                    }
+                    Element::BoneAssignment => v.bone_assignments.push(e.try_into().unwrap()),
+                    Element::Weights => v.weights.push(read_f32s(e)),
+                    Element::ByteWeights => v.weights.push(std::array::from_fn(|i| e[i] as f32 / 255.0)),
+                    Element::Color => v.colors.push(u32::from_le_bytes(e.try_into().unwrap())),
+                    Element::VertexID => v.vertex_ids.push(u32::from_le_bytes(e.try_into().unwrap())),
+                    Element::Raw => {
+                        v.raw[raw].push(e.to_vec());
+                        raw += 1;
+                    }
+                }
+            }
+        }
+        v
+    }
+
+    fn write(&self, out: &mut Vec<u8>, formats: &[VertexFormat], count: usize) -> Result<(), String> {
+        let missing = |what: &str| format!("vertex format has {} but vertex data doesn't", what);
+        fn get<T: Copy>(v: &[T], i: usize, what: &str) -> Result<T, String> {
+            v.get(i).copied().ok_or_else(|| format!("missing {} for vertex {}", what, i))
+        }
+        let floats = |out: &mut Vec<u8>, f: &[f32]| f.iter().for_each(|x| out.extend(x.to_le_bytes()));
+
+        for i in 0..count {
+            let (mut uv, mut raw) = (0, 0);
+            for format in formats {
+                match format.element() {
+                    Element::Position => floats(out, &get(&self.positions, i, "position")?),
+                    Element::Normal => floats(out, &get(&self.normals, i, "normal")?),
+                    Element::Tangent => floats(out, &get(&self.tangents, i, "tangent")?),
+                    Element::UV => {
+                        let set = self.uvs.get(uv).ok_or_else(|| missing("another UV set"))?;
+                        floats(out, &get(set, i, "UV")?);
+                        uv += 1;
+                    }
+                    Element::BoneAssignment => out.extend(get(&self.bone_assignments, i, "bone assignment")?),
+                    Element::Weights => floats(out, &get(&self.weights, i, "weights")?),
+                    Element::ByteWeights => out.extend(
+                        get(&self.weights, i, "weights")?.map(|w| (w.clamp(0.0, 1.0) * 255.0).round() as u8),
+                    ),
+                    Element::Color => out.extend(get(&self.colors, i, "color")?.to_le_bytes()),
+                    Element::VertexID => out.extend(get(&self.vertex_ids, i, "vertex id")?.to_le_bytes()),
+                    Element::Raw => {
+                        let set = self.raw.get(raw).ok_or_else(|| missing("an unknown element"))?;
+                        let data = set.get(i).ok_or_else(|| format!("missing raw element for vertex {}", i))?;
+                        if data.len() != format.size as usize {
+                            return Err(format!("raw element for vertex {} has the wrong size", i));
+                        }
+                        out.extend(data);
+                        raw += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Index buffer of a GEOM. Always triangle lists.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubMesh {
+    /// 2 or 4
+    pub bytes_per_index: u8,
+    pub indices: Vec<u32>,
+}
+
+impl SubMesh {
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+#[binrw]
+#[brw(magic = b"GEOM")]
+struct GeometryHeader {
+    version: u32,
+    tgi_offset: u32,
+    tgi_size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Geometry {
+    pub version: u32,
+    /// Name hash of the shader, or 0 if there is no embedded material.
+    pub shader: u32,
+    pub material: Option<MTNF>,
+    pub merge_group: u32,
+    pub sort_order: u32,
+    pub vertex_formats: Vec<VertexFormat>,
+    pub vertex_count: u32,
+    pub vertices: Vertices,
+    pub sub_meshes: Vec<SubMesh>,
+    /// Index into `tgis` of the skin controller (the rig).
+    pub skin_index: u32,
+    pub bone_hashes: Vec<u32>,
+    pub tgis: Vec<TGI>,
+}
+
+impl Geometry {
+    pub fn triangle_count(&self) -> usize {
+        self.sub_meshes.iter().map(SubMesh::triangle_count).sum()
+    }
+
+    /// Read the first GEOM chunk from an RCOL.
+    pub fn from_rcol(rcol: &RCOL) -> BinResult<Self> {
+        let chunk = rcol.find(ChunkTag::GEOM).ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: "RCOL has no GEOM chunk".to_string(),
+        })?;
+        Self::read_le(&mut chunk.reader())
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+
+    /// Replace the first GEOM chunk of an RCOL with this one.
+    pub fn store_into(&self, rcol: &mut RCOL) -> BinResult<()> {
+        let data = self.to_bytes()?;
+        let chunk = rcol.find_mut(ChunkTag::GEOM).ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: "RCOL has no GEOM chunk".to_string(),
+        })?;
+        chunk.data = data;
+        Ok(())
+    }
+}
+
+fn assert_fail(pos: u64, message: impl Into<String>) -> binrw::Error {
+    binrw::Error::AssertFail {
+        pos,
+        message: message.into(),
+    }
+}
+
+impl BinRead for Geometry {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = GeometryHeader::read_options(reader, endian, ())?;
+        if header.version != 5 {
+            return Err(assert_fail(start, format!("unsupported GEOM version {}", header.version)));
+        }
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 12 + header.tgi_offset as u64;
+
+        let shader = u32::read_options(reader, endian, ())?;
+        let material = if shader != 0 {
+            let size = u32::read_options(reader, endian, ())?;
+            let material_start = reader.stream_position()?;
+            let material = MTNF::read_options(reader, endian, ())?;
+            reader.seek(SeekFrom::Start(material_start + size as u64))?;
+            Some(material)
+        } else {
+            None
+        };
+
+        let (merge_group, sort_order, vertex_count, format_count) =
+            <(u32, u32, u32, u32)>::read_options(reader, endian, ())?;
+        let vertex_formats: Vec<VertexFormat> = binrw::helpers::count(format_count as usize)(reader, endian, ())?;
+
+        let stride: usize = vertex_formats.iter().map(|f| f.size as usize).sum();
+        let vertex_bytes = stride
+            .checked_mul(vertex_count as usize)
+            .ok_or_else(|| assert_fail(start, "GEOM vertex buffer too large"))?;
+        let mut data = Vec::new();
+        reader.take(vertex_bytes as u64).read_to_end(&mut data)?;
+        if data.len() != vertex_bytes {
+            return Err(assert_fail(start, "GEOM vertex buffer is truncated"));
+        }
+        let vertices = Vertices::read(&data, &vertex_formats, vertex_count as usize);
+
+        let sub_mesh_count = u32::read_options(reader, endian, ())?;
+        let mut sub_meshes = Vec::new();
+        for _ in 0..sub_mesh_count {
+            let pos = reader.stream_position()?;
+            let (bytes_per_index, index_count) = <(u8, u32)>::read_options(reader, endian, ())?;
+            let indices = match bytes_per_index {
+                2 => binrw::helpers::count(index_count as usize)(reader, endian, ())
+                    .map(|i: Vec<u16>| i.into_iter().map(u32::from).collect())?,
+                4 => binrw::helpers::count(index_count as usize)(reader, endian, ())?,
+                n => return Err(assert_fail(pos, format!("unsupported GEOM index size {}", n))),
+            };
+            sub_meshes.push(SubMesh {
+                bytes_per_index,
+                indices,
+            });
+        }
+
+        let skin_index = u32::read_options(reader, endian, ())?;
+        let bone_count = u32::read_options(reader, endian, ())?;
+        let bone_hashes = binrw::helpers::count(bone_count as usize)(reader, endian, ())?;
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u32::read_options(reader, endian, ())?;
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::TGI,
+            },
+        )?;
+
+        Ok(Geometry {
+            version: header.version,
+            shader,
+            material,
+            merge_group,
+            sort_order,
+            vertex_formats,
+            vertex_count,
+            vertices,
+            sub_meshes,
+            skin_index,
+            bone_hashes,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for Geometry {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        // Lay out everything between the header and the TGI table first, so that we know the offset.
+        let mut body = io::Cursor::new(Vec::new());
+        let overflow = |what: &str| assert_fail(0, format!("too many {} in GEOM", what));
+
+        self.shader.write_options(&mut body, endian, ())?;
+        if self.shader != 0 {
+            let material = self
+                .material
+                .as_ref()
+                .ok_or_else(|| assert_fail(0, "GEOM has a shader but no material"))?;
+            let size: u32 = material.size().try_into().map_err(|_| overflow("material parameters"))?;
+            size.write_options(&mut body, endian, ())?;
+            material.write_options(&mut body, endian, ())?;
+        }
+
+        let format_count: u32 = self.vertex_formats.len().try_into().map_err(|_| overflow("vertex formats"))?;
+        (self.merge_group, self.sort_order, self.vertex_count, format_count).write_options(&mut body, endian, ())?;
+        self.vertex_formats.write_options(&mut body, endian, ())?;
+        let mut data = Vec::new();
+        self.vertices
+            .write(&mut data, &self.vertex_formats, self.vertex_count as usize)
+            .map_err(|e| assert_fail(0, e))?;
+        body.write_all(&data)?;
+
+        let sub_mesh_count: u32 = self.sub_meshes.len().try_into().map_err(|_| overflow("sub meshes"))?;
+        sub_mesh_count.write_options(&mut body, endian, ())?;
+        for mesh in &self.sub_meshes {
+            let index_count: u32 = mesh.indices.len().try_into().map_err(|_| overflow("indices"))?;
+            (mesh.bytes_per_index, index_count).write_options(&mut body, endian, ())?;
+            match mesh.bytes_per_index {
+                2 => {
+                    for &i in &mesh.indices {
+                        let i: u16 = i.try_into().map_err(|_| overflow("vertices for 16-bit indices"))?;
+                        i.write_options(&mut body, endian, ())?;
+                    }
+                }
+                4 => mesh.indices.write_options(&mut body, endian, ())?,
+                n => return Err(assert_fail(0, format!("unsupported GEOM index size {}", n))),
+            }
+        }
+
+        let bone_count: u32 = self.bone_hashes.len().try_into().map_err(|_| overflow("bones"))?;
+        (self.skin_index, bone_count).write_options(&mut body, endian, ())?;
+        self.bone_hashes.write_options(&mut body, endian, ())?;
+        let body = body.into_inner();
+
+        let tgi_count: u32 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        let header = GeometryHeader {
+            version: self.version,
+            // relative to the end of this field, which is 4 bytes before the body
+            tgi_offset: (body.len() + 4).try_into().map_err(|_| overflow("bytes"))?,
+            tgi_size: 4 + 16 * tgi_count,
+        };
+        header.write_options(writer, endian, ())?;
+        writer.write_all(&body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::TGI)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mtnf::{ShaderDataType, ShaderParam};
+    use super::*;
+
+    fn sample() -> Geometry {
+        let formats = vec![
+            VertexFormat::new(VertexUsage::Position, VertexDataType::Float, 12),
+            VertexFormat::new(VertexUsage::Normal, VertexDataType::Float, 12),
+            VertexFormat::new(VertexUsage::UV, VertexDataType::Float, 8),
+            VertexFormat::new(VertexUsage::BoneAssignment, VertexDataType::Byte, 4),
+            VertexFormat::new(VertexUsage::Weights, VertexDataType::Float, 16),
+            VertexFormat::new(VertexUsage::TangentNormal, VertexDataType::Float, 12),
+            VertexFormat::new(VertexUsage::Color, VertexDataType::ARGB, 4),
+            VertexFormat::new(VertexUsage::VertexID, VertexDataType::UInt32, 4),
+            // not decoded, so it's kept as raw bytes
+            VertexFormat::new(VertexUsage::UV, VertexDataType::Byte, 2),
+        ];
+        let vertices = Vertices {
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            normals: vec![[0.0, 0.0, 1.0]; 3],
+            uvs: vec![vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]],
+            bone_assignments: vec![[0, 1, 0, 0]; 3],
+            weights: vec![[0.75, 0.25, 0.0, 0.0]; 3],
+            tangents: vec![[1.0, 0.0, 0.0]; 3],
+            colors: vec![0xFF00_80FF; 3],
+            vertex_ids: vec![10, 11, 12],
+            raw: vec![vec![vec![1, 2], vec![3, 4], vec![5, 6]]],
+        };
+        let material = MTNF {
+            unknown: 0,
+            params: vec![ShaderParam {
+                field: crate::hash::fnv32("DiffuseMap"),
+                data_type: ShaderDataType::Texture as u32,
+                count: 1,
+                data: 1u32.to_le_bytes().to_vec(),
+            }],
+        };
+        Geometry {
+            version: 5,
+            shader: crate::hash::fnv32("CASRGBMask"),
+            material: Some(material),
+            merge_group: 0,
+            sort_order: 0x4000,
+            vertex_formats: formats,
+            vertex_count: 3,
+            vertices,
+            sub_meshes: vec![SubMesh {
+                bytes_per_index: 2,
+                indices: vec![0, 1, 2],
+            }],
+            skin_index: 0,
+            bone_hashes: vec![crate::hash::fnv32("b__ROOT_bind__"), crate::hash::fnv32("b__Pelvis__")],
+            tgis: vec![TGI::new(0x00AE6C67, 0, 1), TGI::new(0x00B2D882, 0, 2)],
+        }
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let geom = sample();
+        let bytes = geom.to_bytes().unwrap();
+        assert_eq!(&bytes[..8], b"GEOM\x05\x00\x00\x00");
+        let read = Geometry::read_le(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(read, geom);
+        assert_eq!(read.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn tgi_table_is_at_the_offset() {
+        let bytes = sample().to_bytes().unwrap();
+        let tgi_offset = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let tgi_size = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        assert_eq!(12 + tgi_offset + tgi_size, bytes.len());
+        assert_eq!(&bytes[12 + tgi_offset..16 + tgi_offset], 2u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[4] = 6;
+        assert!(Geometry::read_le(&mut io::Cursor::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn missing_vertex_data_is_an_error() {
+        let mut geom = sample();
+        geom.vertices.normals.pop();
+        assert!(geom.to_bytes().is_err());
+    }
+}
@@ -0,0 +1,465 @@
+//! Object mesh chunks: MODL, MLOD, VRTF, VBUF and IBUF. These live inside [`RCOL`]s.
+//!
+//! A MODL lists the LODs of an object, each of which is (usually) an MLOD resource.
+//! An MLOD is a list of meshes, each pointing at a vertex format, vertex buffer and index buffer.
+
+use super::rcol::{ChunkReference, RCOL};
+
+use binrw::{binread, binrw, BinRead, BinResult, Endian};
+use std::io::{Read, Seek, SeekFrom};
+
+#[binrw]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BoundingBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum LodId {
+    High = 0x0000_0000,
+    Medium = 0x0000_0001,
+    Low = 0x0000_0002,
+    HighShadow = 0x0001_0000,
+    MediumShadow = 0x0001_0001,
+    LowShadow = 0x0001_0002,
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModlLod {
+    /// Usually an external reference to an MLOD resource.
+    pub model_lod: ChunkReference,
+    pub flags: u32,
+    pub id: u32,
+    pub min_z: f32,
+    pub max_z: f32,
+}
+
+impl ModlLod {
+    pub fn id(&self) -> Option<LodId> {
+        num_traits::FromPrimitive::from_u32(self.id)
+    }
+}
+
+#[binrw]
+#[brw(magic = b"MODL")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Modl {
+    pub version: u32,
+    #[br(temp)]
+    #[bw(try_calc = lods.len().try_into())]
+    lod_count: u32,
+    pub bounds: BoundingBox,
+    #[br(if(version >= 258))]
+    #[bw(if(*version >= 258))]
+    #[br(temp)]
+    #[bw(try_calc = extra_bounds.len().try_into())]
+    extra_bounds_count: u32,
+    #[br(if(version >= 258))]
+    #[bw(if(*version >= 258))]
+    #[br(count = extra_bounds_count)]
+    pub extra_bounds: Vec<BoundingBox>,
+    #[br(if(version >= 258))]
+    #[bw(if(*version >= 258))]
+    pub fade_type: u32,
+    #[br(if(version >= 258))]
+    #[bw(if(*version >= 258))]
+    pub custom_fade_distance: f32,
+    #[br(count = lod_count)]
+    pub lods: Vec<ModlLod>,
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeometryState {
+    pub name: u32,
+    pub start_index: u32,
+    pub min_vertex_index: u32,
+    pub vertex_count: u32,
+    pub primitive_count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MlodMesh {
+    pub name: u32,
+    pub material: ChunkReference,
+    pub vertex_format: ChunkReference,
+    pub vertex_buffer: ChunkReference,
+    pub index_buffer: ChunkReference,
+    /// Low byte is the primitive type (3 being a triangle list), the rest are flags.
+    pub primitive_type_flags: u32,
+    pub stream_offset: u32,
+    pub start_vertex: u32,
+    pub start_index: u32,
+    pub min_vertex_index: u32,
+    pub vertex_count: u32,
+    pub primitive_count: u32,
+    pub bounds: BoundingBox,
+    pub skin_controller: ChunkReference,
+    /// Bone name hashes, indexed by the vertices' blend indices.
+    pub joints: Vec<u32>,
+    pub scale_offset: ChunkReference,
+    pub geometry_states: Vec<GeometryState>,
+}
+
+impl MlodMesh {
+    pub fn primitive_type(&self) -> u8 {
+        self.primitive_type_flags as u8
+    }
+}
+
+#[binread]
+struct MlodMeshFields {
+    name: u32,
+    material: ChunkReference,
+    vertex_format: ChunkReference,
+    vertex_buffer: ChunkReference,
+    index_buffer: ChunkReference,
+    primitive_type_flags: u32,
+    stream_offset: u32,
+    start_vertex: u32,
+    start_index: u32,
+    min_vertex_index: u32,
+    vertex_count: u32,
+    primitive_count: u32,
+    bounds: BoundingBox,
+    skin_controller: ChunkReference,
+    #[br(temp)]
+    joint_count: u32,
+    #[br(count = joint_count)]
+    joints: Vec<u32>,
+    scale_offset: ChunkReference,
+    #[br(temp)]
+    geometry_state_count: u32,
+    #[br(count = geometry_state_count)]
+    geometry_states: Vec<GeometryState>,
+}
+
+#[binread]
+#[br(magic = b"MLOD")]
+struct MlodHeader {
+    version: u32,
+    count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mlod {
+    pub version: u32,
+    pub meshes: Vec<MlodMesh>,
+}
+
+impl BinRead for Mlod {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let header = MlodHeader::read_options(reader, endian, ())?;
+        let mut meshes = Vec::new();
+        for _ in 0..header.count {
+            // Each mesh is prefixed by its size, which lets us skip fields newer versions add.
+            let size = u32::read_options(reader, endian, ())?;
+            let mesh_start = reader.stream_position()?;
+            let f = MlodMeshFields::read_options(reader, endian, ())?;
+            reader.seek(SeekFrom::Start(mesh_start + size as u64))?;
+            meshes.push(MlodMesh {
+                name: f.name,
+                material: f.material,
+                vertex_format: f.vertex_format,
+                vertex_buffer: f.vertex_buffer,
+                index_buffer: f.index_buffer,
+                primitive_type_flags: f.primitive_type_flags,
+                stream_offset: f.stream_offset,
+                start_vertex: f.start_vertex,
+                start_index: f.start_index,
+                min_vertex_index: f.min_vertex_index,
+                vertex_count: f.vertex_count,
+                primitive_count: f.primitive_count,
+                bounds: f.bounds,
+                skin_controller: f.skin_controller,
+                joints: f.joints,
+                scale_offset: f.scale_offset,
+                geometry_states: f.geometry_states,
+            });
+        }
+        Ok(Mlod {
+            version: header.version,
+            meshes,
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ElementUsage {
+    Position = 0,
+    Normal = 1,
+    UV = 2,
+    BlendIndex = 3,
+    BlendWeight = 4,
+    Tangent = 5,
+    Color = 6,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ElementFormat {
+    Float1 = 0,
+    Float2 = 1,
+    Float3 = 2,
+    Float4 = 3,
+    UByte4 = 4,
+    ColorUByte4 = 5,
+    Short2 = 6,
+    Short4 = 7,
+    UByte4N = 8,
+    Short2N = 9,
+    Short4N = 10,
+    UShort2N = 11,
+    UShort4N = 12,
+    Dec3N = 13,
+    UDec3N = 14,
+    Float16_2 = 15,
+    Float16_4 = 16,
+}
+
+impl ElementFormat {
+    pub fn size(self) -> usize {
+        use ElementFormat::*;
+        match self {
+            Float1 | UByte4 | ColorUByte4 | Short2 | UByte4N | Short2N | UShort2N | Dec3N
+            | UDec3N | Float16_2 => 4,
+            Float2 | Short4 | Short4N | UShort4N | Float16_4 => 8,
+            Float3 => 12,
+            Float4 => 16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VertexElement {
+    pub usage: u32,
+    pub usage_index: u32,
+    pub format: u32,
+    pub offset: u32,
+}
+
+impl VertexElement {
+    pub fn usage(&self) -> Option<ElementUsage> {
+        num_traits::FromPrimitive::from_u32(self.usage)
+    }
+
+    pub fn format(&self) -> Option<ElementFormat> {
+        num_traits::FromPrimitive::from_u32(self.format)
+    }
+}
+
+#[binrw]
+#[brw(magic = b"VRTF")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vrtf {
+    pub version: u32,
+    pub stride: u32,
+    #[br(temp)]
+    #[bw(try_calc = elements.len().try_into())]
+    count: u32,
+    #[br(temp)]
+    #[bw(calc = 1)]
+    extended: u32,
+    #[br(parse_with = parse_elements, args(count, extended != 0))]
+    #[bw(map = |e: &Vec<VertexElement>| e.iter().map(|e| [e.usage, e.usage_index, e.format, e.offset]).collect::<Vec<_>>())]
+    pub elements: Vec<VertexElement>,
+}
+
+// non-extended formats pack each field into a byte
+#[binrw::parser(reader, endian)]
+fn parse_elements(count: u32, extended: bool) -> BinResult<Vec<VertexElement>> {
+    (0..count)
+        .map(|_| {
+            if extended {
+                <[u32; 4]>::read_options(reader, endian, ()).map(VertexElement::from)
+            } else {
+                <[u8; 4]>::read_options(reader, endian, ()).map(|e| VertexElement::from(e.map(u32::from)))
+            }
+        })
+        .collect()
+}
+
+impl From<[u32; 4]> for VertexElement {
+    fn from([usage, usage_index, format, offset]: [u32; 4]) -> Self {
+        VertexElement {
+            usage,
+            usage_index,
+            format,
+            offset,
+        }
+    }
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((h >> 10) & 0x1F) as i32;
+    let frac = (h & 0x3FF) as f32;
+    match exp {
+        0 => sign * frac * 2f32.powi(-24),
+        31 if frac == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + frac / 1024.0) * 2f32.powi(exp - 15),
+    }
+}
+
+/// Decode one vertex element into up to four floats.
+///
+/// Integer formats are normalized where the format says so, except for `Short4` positions,
+/// where the fourth component is a scale (with 0 meaning `i16::MAX`).
+pub fn decode_element(format: ElementFormat, usage: ElementUsage, data: &[u8]) -> [f32; 4] {
+    use ElementFormat::*;
+    let f32_at = |i: usize| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+    let i16_at = |i: usize| i16::from_le_bytes(data[i * 2..i * 2 + 2].try_into().unwrap());
+    let u16_at = |i: usize| u16::from_le_bytes(data[i * 2..i * 2 + 2].try_into().unwrap());
+    let mut out = [0.0; 4];
+    match format {
+        Float1 | Float2 | Float3 | Float4 => {
+            let n = format.size() / 4;
+            (0..n).for_each(|i| out[i] = f32_at(i));
+        }
+        UByte4 => (0..4).for_each(|i| out[i] = data[i] as f32),
+        UByte4N => (0..4).for_each(|i| out[i] = data[i] as f32 / 255.0),
+        // stored as BGRA
+        ColorUByte4 => {
+            let bgra = [data[2], data[1], data[0], data[3]];
+            match usage {
+                ElementUsage::Normal | ElementUsage::Tangent => {
+                    (0..4).for_each(|i| out[i] = bgra[i] as f32 / 127.5 - 1.0)
+                }
+                _ => (0..4).for_each(|i| out[i] = bgra[i] as f32 / 255.0),
+            }
+        }
+        Short2 | Short2N => (0..2).for_each(|i| out[i] = i16_at(i) as f32 / i16::MAX as f32),
+        Short4 if usage == ElementUsage::Position => {
+            let scale = match i16_at(3) {
+                0 => i16::MAX as f32,
+                s => s as f32,
+            };
+            (0..3).for_each(|i| out[i] = i16_at(i) as f32 / scale);
+            out[3] = 1.0;
+        }
+        Short4 | Short4N => (0..4).for_each(|i| out[i] = i16_at(i) as f32 / i16::MAX as f32),
+        UShort2N => (0..2).for_each(|i| out[i] = u16_at(i) as f32 / u16::MAX as f32),
+        UShort4N => (0..4).for_each(|i| out[i] = u16_at(i) as f32 / u16::MAX as f32),
+        Dec3N | UDec3N => {
+            let v = u32::from_le_bytes(data[..4].try_into().unwrap());
+            for (i, o) in out.iter_mut().take(3).enumerate() {
+                let bits = (v >> (10 * i)) & 0x3FF;
+                *o = if format == Dec3N {
+                    // sign-extend 10 bits
+                    (((bits << 22) as i32) >> 22) as f32 / 511.0
+                } else {
+                    bits as f32 / 1023.0
+                };
+            }
+        }
+        Float16_2 => (0..2).for_each(|i| out[i] = half_to_f32(u16_at(i))),
+        Float16_4 => (0..4).for_each(|i| out[i] = half_to_f32(u16_at(i))),
+    }
+    out
+}
+
+#[binrw]
+#[brw(magic = b"VBUF")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vbuf {
+    pub version: u32,
+    pub flags: u32,
+    pub swizzle_info: ChunkReference,
+    #[br(parse_with = binrw::helpers::until_eof)]
+    pub buffer: Vec<u8>,
+}
+
+impl Vbuf {
+    pub const COLLAPSED: u32 = 0x1;
+    pub const DIFFERENCED_VERTICES: u32 = 0x4;
+
+    /// Decode `count` vertices starting at byte `offset`, as a list of elements per vertex.
+    pub fn vertices(&self, vrtf: &Vrtf, offset: usize, count: usize) -> Vec<Vec<[f32; 4]>> {
+        let stride = vrtf.stride as usize;
+        (0..count)
+            .map_while(|i| self.buffer.get(offset + i * stride..offset + (i + 1) * stride))
+            .map(|vertex| {
+                vrtf.elements
+                    .iter()
+                    .map(|e| match (e.format(), e.usage()) {
+                        (Some(format), Some(usage)) => vertex
+                            .get(e.offset as usize..e.offset as usize + format.size())
+                            .map(|data| decode_element(format, usage, data))
+                            .unwrap_or_default(),
+                        _ => [0.0; 4],
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[binrw]
+#[brw(magic = b"IBUF")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ibuf {
+    pub version: u32,
+    pub flags: u32,
+    pub display_list_usage: u32,
+    #[br(parse_with = binrw::helpers::until_eof)]
+    pub buffer: Vec<u8>,
+}
+
+impl Ibuf {
+    pub const DIFFERENCED_INDICES: u32 = 0x1;
+    pub const USES_32BIT_INDICES: u32 = 0x2;
+    pub const IS_DISPLAY_LIST: u32 = 0x4;
+
+    /// All indices in the buffer, undoing delta encoding if present.
+    pub fn indices(&self) -> Vec<u32> {
+        let raw: Vec<u32> = if self.flags & Self::USES_32BIT_INDICES != 0 {
+            self.buffer
+                .chunks_exact(4)
+                .map(|i| u32::from_le_bytes(i.try_into().unwrap()))
+                .collect()
+        } else {
+            self.buffer
+                .chunks_exact(2)
+                .map(|i| u16::from_le_bytes(i.try_into().unwrap()) as u32)
+                .collect()
+        };
+        if self.flags & Self::DIFFERENCED_INDICES != 0 {
+            let mut last = 0i32;
+            let wide = self.flags & Self::USES_32BIT_INDICES != 0;
+            raw.into_iter()
+                .map(|delta| {
+                    let delta = if wide { delta as i32 } else { delta as u16 as i16 as i32 };
+                    last = last.wrapping_add(delta);
+                    last as u32
+                })
+                .collect()
+        } else {
+            raw
+        }
+    }
+}
+
+/// Read a chunk referenced from within `rcol` as `T`.
+pub fn read_chunk<T>(rcol: &RCOL, reference: ChunkReference) -> BinResult<T>
+where
+    T: for<'a> BinRead<Args<'a> = ()>,
+{
+    let chunk = rcol
+        .resolve_chunk(reference)
+        .ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("unresolvable chunk reference {:08X}", reference.0),
+        })?;
+    T::read_le(&mut chunk.reader())
+}
@@ -0,0 +1,229 @@
+//! MTNF, the shader parameter block embedded in GEOM and MATD.
+
+use binrw::{binrw, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ShaderDataType {
+    Float = 1,
+    Int = 2,
+    Texture = 4,
+}
+
+#[binrw]
+#[brw(magic = b"MTNF")]
+struct MTNFHeader {
+    unknown: u32,
+    data_len: u32,
+    count: u32,
+}
+
+#[binrw]
+struct ShaderParamHeader {
+    field: u32,
+    data_type: u32,
+    count: u32,
+    offset: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderParam {
+    /// FNV32 of the parameter name, e.g. `DiffuseMap`.
+    pub field: u32,
+    pub data_type: u32,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+impl ShaderParam {
+    pub fn data_type(&self) -> Option<ShaderDataType> {
+        num_traits::FromPrimitive::from_u32(self.data_type)
+    }
+
+    fn words(&self) -> impl Iterator<Item = [u8; 4]> + '_ {
+        self.data
+            .chunks_exact(4)
+            .map(|w| w.try_into().unwrap())
+    }
+
+    pub fn as_floats(&self) -> Vec<f32> {
+        self.words().map(f32::from_le_bytes).collect()
+    }
+
+    pub fn as_ints(&self) -> Vec<u32> {
+        self.words().map(u32::from_le_bytes).collect()
+    }
+
+    /// For textures, the index of the texture in the owning resource's TGI table.
+    pub fn texture_index(&self) -> Option<u32> {
+        match self.data_type() {
+            Some(ShaderDataType::Texture) => self.as_ints().first().copied(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct MTNF {
+    pub unknown: u32,
+    pub params: Vec<ShaderParam>,
+}
+
+impl MTNF {
+    pub fn param(&self, field: u32) -> Option<&ShaderParam> {
+        self.params.iter().find(|p| p.field == field)
+    }
+
+    pub fn param_by_name(&self, name: &str) -> Option<&ShaderParam> {
+        self.param(crate::hash::fnv32(name))
+    }
+
+    fn header_size(&self) -> usize {
+        16 + 16 * self.params.len()
+    }
+
+    /// Size in bytes when written.
+    pub fn size(&self) -> usize {
+        self.header_size() + self.params.iter().map(|p| p.data.len()).sum::<usize>()
+    }
+}
+
+// Parameter offsets are relative to the start of the MTNF tag.
+// Each parameter's data is assumed to run until the next parameter's data (or the end),
+// since the element size depends on the parameter type.
+impl BinRead for MTNF {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = MTNFHeader::read_options(reader, endian, ())?;
+        let headers: Vec<ShaderParamHeader> = binrw::helpers::count(header.count as usize)(reader, endian, ())?;
+
+        let data_start = reader.stream_position()? - start;
+        let data_end = data_start + header.data_len as u64;
+        let mut offsets: Vec<u64> = headers.iter().map(|h| h.offset as u64).collect();
+        offsets.push(data_end);
+        offsets.sort_unstable();
+
+        let mut params = Vec::with_capacity(headers.len());
+        for h in headers {
+            let offset = h.offset as u64;
+            if offset < data_start || offset > data_end {
+                return Err(binrw::Error::AssertFail {
+                    pos: start + offset,
+                    message: format!("MTNF parameter {:08X} points outside of its data", h.field),
+                });
+            }
+            let next = offsets.iter().copied().find(|&o| o > offset).unwrap_or(data_end);
+            reader.seek(SeekFrom::Start(start + offset))?;
+            let data = crate::util::read_bytes(reader, endian, (next - offset,))?;
+            params.push(ShaderParam {
+                field: h.field,
+                data_type: h.data_type,
+                count: h.count,
+                data,
+            });
+        }
+        reader.seek(SeekFrom::Start(start + data_end))?;
+
+        Ok(MTNF {
+            unknown: header.unknown,
+            params,
+        })
+    }
+}
+
+impl BinWrite for MTNF {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let too_large = || binrw::Error::AssertFail {
+            pos: 0,
+            message: "MTNF too large".to_string(),
+        };
+        let mut offset = self.header_size();
+        let mut headers = Vec::with_capacity(self.params.len());
+        for p in &self.params {
+            headers.push(ShaderParamHeader {
+                field: p.field,
+                data_type: p.data_type,
+                count: p.count,
+                offset: offset.try_into().map_err(|_| too_large())?,
+            });
+            offset += p.data.len();
+        }
+        let data_len: u32 = (offset - self.header_size()).try_into().map_err(|_| too_large())?;
+        let count: u32 = self.params.len().try_into().map_err(|_| too_large())?;
+
+        let header = MTNFHeader {
+            unknown: self.unknown,
+            data_len,
+            count,
+        };
+        header.write_options(writer, endian, ())?;
+        headers.write_options(writer, endian, ())?;
+        for p in &self.params {
+            writer.write_all(&p.data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample() -> Vec<u8> {
+        let mut bytes = b"MTNF".to_vec();
+        // unknown, data_len, count
+        for field in [0u32, 16, 2] {
+            bytes.extend(field.to_le_bytes());
+        }
+        let diffuse = crate::hash::fnv32("DiffuseMap");
+        let shininess = crate::hash::fnv32("Shininess");
+        // field, type, count, offset (from the MTNF tag, after the 16 byte header and 2 * 16 byte params)
+        for field in [diffuse, ShaderDataType::Texture as u32, 1, 48] {
+            bytes.extend(field.to_le_bytes());
+        }
+        for field in [shininess, ShaderDataType::Float as u32, 3, 52] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(2u32.to_le_bytes());
+        for x in [0.5f32, 1.0, 2.0] {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn read_write_round_trip() {
+        let bytes = sample();
+        let mtnf = MTNF::read_le(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(mtnf.params.len(), 2);
+        assert_eq!(mtnf.param_by_name("DiffuseMap").unwrap().texture_index(), Some(2));
+        assert_eq!(mtnf.param_by_name("Shininess").unwrap().as_floats(), [0.5, 1.0, 2.0]);
+        assert_eq!(mtnf.size(), bytes.len());
+
+        let mut out = Cursor::new(Vec::new());
+        mtnf.write_le(&mut out).unwrap();
+        assert_eq!(out.into_inner(), bytes);
+    }
+
+    #[test]
+    fn rejects_offsets_outside_the_data() {
+        let mut bytes = sample();
+        bytes[28..32].copy_from_slice(&100u32.to_le_bytes());
+        assert!(MTNF::read_le(&mut Cursor::new(&bytes)).is_err());
+    }
+}
@@ -38,9 +38,15 @@ pub fn gather_names_into<'brand>(ctx: &mut impl FileCtx<'brand>, entry: &DBPFInd
         return Err(binrw::Error::AssertFail { pos: 0, message: "Not an NMAP tag.".to_string() });
     }
 
-    let mut reader = entry.chunk.get_reader(ctx)?;
+    let mut reader = entry.get_reader(ctx)?;
     let nmap: NMAP = BinRead::read_le(&mut reader)?;
     name_map.extend(nmap.map.into_iter().map(|(i, s)| (i, String::from_utf8_lossy(&s.inner).into_owned())));
 
     Ok(())
-}
\ No newline at end of file
+}
+impl NMAP {
+    /// Instance -> name, for every name in the map.
+    pub fn names(&self) -> impl Iterator<Item = (u64, String)> + '_ {
+        self.map.iter().map(|(&i, s)| (i, String::from_utf8_lossy(&s.inner).into_owned()))
+    }
+}
@@ -0,0 +1,401 @@
+//! OBJD, the object catalog resource. Describes how an object shows up in buy/build mode
+//! (name, description, price, icon) and points at the resources that make it up.
+//!
+//! The object, placement, room and function flags after the common catalog block are decoded.
+//! What follows them (material groupings, moodlets, modular pieces, etc.) changes with the resource
+//! version and is kept as raw bytes. The object's key (OBJK), models, footprints and slots are
+//! found through the TGI table.
+
+use super::tgi::{TGIOrder, TGI};
+use super::ResourceType;
+use crate::util::{parse_7bit_utf16be, write_7bit_utf16be};
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A material override, kept as raw bytes.
+///
+/// Materials have their own offsets and TGI table, all relative to the material itself,
+/// so they can be moved around as a block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub data: Vec<u8>,
+}
+
+impl BinRead for Material {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(reader: &mut R, endian: Endian, _args: Self::Args<'_>) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let material_type = u8::read_options(reader, endian, ())?;
+        if material_type != 1 {
+            u32::read_options(reader, endian, ())?;
+        }
+        // relative to the end of the offset field, and followed by one more u32
+        let end_offset = u32::read_options(reader, endian, ())?;
+        let end = reader.stream_position()? + end_offset as u64 + 4;
+
+        reader.seek(SeekFrom::Start(start))?;
+        let mut data = Vec::new();
+        reader.take(end - start).read_to_end(&mut data)?;
+        if data.len() as u64 != end - start {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: "OBJD material is truncated".to_string(),
+            });
+        }
+        Ok(Material { data })
+    }
+}
+
+impl BinWrite for Material {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(&self, writer: &mut W, _endian: Endian, _args: Self::Args<'_>) -> BinResult<()> {
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+/// The block every catalog resource (objects, walls, floors, ...) starts with.
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogCommon {
+    pub version: u32,
+    /// STBL key of the catalog name.
+    pub name_hash: u64,
+    /// STBL key of the catalog description.
+    pub description_hash: u64,
+    #[br(parse_with = parse_7bit_utf16be)]
+    #[bw(write_with = write_7bit_utf16be)]
+    pub name: String,
+    #[br(parse_with = parse_7bit_utf16be)]
+    #[bw(write_with = write_7bit_utf16be)]
+    pub description: String,
+    pub price: f32,
+    pub niceness: f32,
+    pub crap_score: f32,
+    pub status_flags: u8,
+    /// Instance of the catalog icon.
+    pub icon: u64,
+}
+
+/// A cutout that a door or window makes in the wall it's placed in.
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct WallCutout {
+    pub width: f32,
+    pub height: f32,
+    pub mask_index: u32,
+    pub unknown: u32,
+}
+
+/// The flags that decide where an object goes, and where buy and build mode list it.
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+#[brw(import(version: u32))]
+pub struct CatalogFlags {
+    /// Index into [`OBJD::tgis`] of the object key.
+    pub objk_index: u32,
+    pub object_type: u32,
+    /// Only present from version 0x1A.
+    #[brw(if(version >= 0x1A))]
+    pub object_type2: Option<u32>,
+    pub wall_placement: u32,
+    pub movement: u32,
+    pub cutout_tiles_per_level: u32,
+    pub levels: u32,
+    #[br(temp)]
+    #[bw(try_calc = wall_cutouts.len().try_into())]
+    cutout_count: u32,
+    #[br(count = cutout_count)]
+    pub wall_cutouts: Vec<WallCutout>,
+    pub script_enabled: u8,
+    pub diagonal_index: u32,
+    pub ambience_type: u32,
+    /// The buy mode rooms the object is listed under.
+    pub room_category: u32,
+    /// The buy mode functions the object is listed under.
+    pub function_category: u32,
+    pub function_subcategory: u64,
+    /// Only present from version 0x1C.
+    #[brw(if(version >= 0x1C))]
+    pub function_subcategory2: Option<u64>,
+    pub room_subcategory: u64,
+    /// The build mode categories the object is listed under.
+    pub build_category: u32,
+}
+
+#[binrw]
+struct OBJDHeader {
+    version: u32,
+    tgi_offset: u32,
+    tgi_size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OBJD {
+    pub version: u32,
+    /// Only present from version 0x16.
+    pub materials: Vec<Material>,
+    /// Only present from version 0x16.
+    pub instance_name: Option<String>,
+    pub common: CatalogCommon,
+    pub flags: CatalogFlags,
+    /// Everything between the flags and the TGI table.
+    pub rest: Vec<u8>,
+    pub tgis: Vec<TGI>,
+}
+
+impl OBJD {
+    fn of_type(&self, ty: ResourceType) -> impl Iterator<Item = &TGI> + '_ {
+        self.tgis.iter().filter(move |t| t.resource_type == ty as u32)
+    }
+
+    /// The object key, which has the script class.
+    pub fn objk(&self) -> Option<&TGI> {
+        self.of_type(ResourceType::OBJK).next()
+    }
+
+    pub fn models(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.of_type(ResourceType::MODL)
+    }
+
+    pub fn footprints(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.of_type(ResourceType::FTPT)
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.of_type(ResourceType::RSLT)
+    }
+
+    pub fn price(&self) -> f32 {
+        self.common.price
+    }
+
+    pub fn set_price(&mut self, price: f32) {
+        self.common.price = price;
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+}
+
+impl BinRead for OBJD {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = OBJDHeader::read_options(reader, endian, ())?;
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 8 + header.tgi_offset as u64;
+
+        let (materials, instance_name) = if header.version >= 0x16 {
+            let count = u8::read_options(reader, endian, ())?;
+            let materials = binrw::helpers::count(count as usize)(reader, endian, ())?;
+            (materials, Some(parse_7bit_utf16be(reader, endian, ())?))
+        } else {
+            (Vec::new(), None)
+        };
+        let common = CatalogCommon::read_options(reader, endian, ())?;
+        let flags = CatalogFlags::read_options(reader, endian, (header.version,))?;
+
+        let end = reader.stream_position()?;
+        if end > tgi_pos {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: "OBJD fields overlap the TGI table".to_string(),
+            });
+        }
+        let mut rest = Vec::new();
+        reader.take(tgi_pos - end).read_to_end(&mut rest)?;
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u32::read_options(reader, endian, ())?;
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::TGI,
+            },
+        )?;
+
+        Ok(OBJD {
+            version: header.version,
+            materials,
+            instance_name,
+            common,
+            flags,
+            rest,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for OBJD {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many {} in OBJD", what),
+        };
+        let mut body = io::Cursor::new(Vec::new());
+        if self.version >= 0x16 {
+            let count: u8 = self.materials.len().try_into().map_err(|_| overflow("materials"))?;
+            count.write_options(&mut body, endian, ())?;
+            self.materials.write_options(&mut body, endian, ())?;
+            let name = self.instance_name.clone().unwrap_or_default();
+            write_7bit_utf16be(&name, &mut body, endian, ())?;
+        }
+        self.common.write_options(&mut body, endian, ())?;
+        self.flags.write_options(&mut body, endian, (self.version,))?;
+        body.write_all(&self.rest)?;
+        let body = body.into_inner();
+
+        let tgi_count: u32 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        let header = OBJDHeader {
+            version: self.version,
+            // relative to the end of this field, which is 4 bytes before the body
+            tgi_offset: (body.len() + 4).try_into().map_err(|_| overflow("bytes"))?,
+            tgi_size: 4 + 16 * tgi_count,
+        };
+        header.write_options(writer, endian, ())?;
+        writer.write_all(&body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::TGI)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16be(s: &str) -> Vec<u8> {
+        let bytes: Vec<u8> = s.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut out = vec![bytes.len() as u8];
+        out.extend(bytes);
+        out
+    }
+
+    /// A version 0x1C object with no materials, one wall cutout and a few raw bytes after the
+    /// flags.
+    fn sample() -> (Vec<u8>, OBJD) {
+        let objk = TGI::new(ResourceType::OBJK as u32, 0, 0x1111);
+        let mut body = vec![0];
+        body.extend(utf16be("chair"));
+        body.extend(0x1Cu32.to_le_bytes());
+        body.extend(1u64.to_le_bytes());
+        body.extend(2u64.to_le_bytes());
+        body.extend(utf16be("Chair"));
+        body.extend(utf16be(""));
+        for field in [150f32, 2.0, 0.5] {
+            body.extend(field.to_le_bytes());
+        }
+        body.push(0x10);
+        body.extend(3u64.to_le_bytes());
+
+        for field in [0u32, 0x10, 0x20, 0x40, 0x80, 1, 1, 1] {
+            body.extend(field.to_le_bytes());
+        }
+        body.extend(1.5f32.to_le_bytes());
+        body.extend(2.5f32.to_le_bytes());
+        body.extend(7u32.to_le_bytes());
+        body.extend(8u32.to_le_bytes());
+        body.push(1);
+        for field in [9u32, 10, 0x4, 0x8] {
+            body.extend(field.to_le_bytes());
+        }
+        for field in [0x100u64, 0x200, 0x400] {
+            body.extend(field.to_le_bytes());
+        }
+        body.extend(0x20u32.to_le_bytes());
+        body.extend([0xDE, 0xAD]);
+
+        let mut bytes = 0x1Cu32.to_le_bytes().to_vec();
+        bytes.extend((body.len() as u32 + 4).to_le_bytes());
+        bytes.extend(20u32.to_le_bytes());
+        bytes.extend(&body);
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(objk.resource_type.to_le_bytes());
+        bytes.extend(objk.resource_group.to_le_bytes());
+        bytes.extend(objk.instance.to_le_bytes());
+
+        let objd = OBJD {
+            version: 0x1C,
+            materials: vec![],
+            instance_name: Some("chair".to_string()),
+            common: CatalogCommon {
+                version: 0x1C,
+                name_hash: 1,
+                description_hash: 2,
+                name: "Chair".to_string(),
+                description: String::new(),
+                price: 150.0,
+                niceness: 2.0,
+                crap_score: 0.5,
+                status_flags: 0x10,
+                icon: 3,
+            },
+            flags: CatalogFlags {
+                objk_index: 0,
+                object_type: 0x10,
+                object_type2: Some(0x20),
+                wall_placement: 0x40,
+                movement: 0x80,
+                cutout_tiles_per_level: 1,
+                levels: 1,
+                wall_cutouts: vec![WallCutout {
+                    width: 1.5,
+                    height: 2.5,
+                    mask_index: 7,
+                    unknown: 8,
+                }],
+                script_enabled: 1,
+                diagonal_index: 9,
+                ambience_type: 10,
+                room_category: 0x4,
+                function_category: 0x8,
+                function_subcategory: 0x100,
+                function_subcategory2: Some(0x200),
+                room_subcategory: 0x400,
+                build_category: 0x20,
+            },
+            rest: vec![0xDE, 0xAD],
+            tgis: vec![objk],
+        };
+        (bytes, objd)
+    }
+
+    #[test]
+    fn read_write_round_trip() {
+        let (bytes, expected) = sample();
+        let objd = OBJD::read_le(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(objd, expected);
+        assert_eq!(objd.to_bytes().unwrap(), bytes);
+        assert_eq!(objd.objk(), Some(&objd.tgis[0]));
+    }
+
+    #[test]
+    fn older_versions_have_fewer_flags() {
+        let (_, mut objd) = sample();
+        objd.version = 0x19;
+        objd.flags.object_type2 = None;
+        objd.flags.function_subcategory2 = None;
+        let bytes = objd.to_bytes().unwrap();
+        assert_eq!(OBJD::read_le(&mut io::Cursor::new(&bytes)).unwrap(), objd);
+        assert_eq!(bytes.len(), sample().0.len() - 12);
+    }
+}
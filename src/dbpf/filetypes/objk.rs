@@ -0,0 +1,307 @@
+//! OBJK, the object key. Lists an object's components and the data they're set up with,
+//! such as its script class and the keys of its model, footprint and slots.
+
+use super::tgi::{TGIOrder, TGI};
+use crate::util::{parse_7bit_utf8, write_7bit_utf8};
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Component names, whose FNV-32 hashes are what [`OBJK::components`] holds.
+pub const COMPONENT_NAMES: [&str; 16] = [
+    "Animation",
+    "Effect",
+    "Footprint",
+    "Lighting",
+    "Location",
+    "LotObject",
+    "Model",
+    "Physics",
+    "Sacs",
+    "Script",
+    "Sim",
+    "Slot",
+    "Steering",
+    "Transform",
+    "Tree",
+    "VisualState",
+];
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComponentValue {
+    #[brw(magic = 0u8)]
+    String(
+        #[br(parse_with = parse_7bit_utf8)]
+        #[bw(write_with = write_7bit_utf8)]
+        String,
+    ),
+    /// Index into [`OBJK::tgis`].
+    #[brw(magic = 1u8)]
+    ResourceKey(u32),
+    /// Index into [`OBJK::tgis`].
+    #[brw(magic = 2u8)]
+    AssetResourceName(u32),
+    #[brw(magic = 3u8)]
+    SteeringInstance(
+        #[br(parse_with = parse_7bit_utf8)]
+        #[bw(write_with = write_7bit_utf8)]
+        String,
+    ),
+    #[brw(magic = 4u8)]
+    UInt32(u32),
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComponentData {
+    #[br(parse_with = parse_7bit_utf8)]
+    #[bw(write_with = write_7bit_utf8)]
+    pub key: String,
+    pub value: ComponentValue,
+}
+
+#[binrw]
+struct OBJKHeader {
+    version: u32,
+    tgi_offset: u32,
+    tgi_size: u32,
+}
+
+#[binrw]
+struct OBJKBody {
+    #[br(temp)]
+    #[bw(try_calc = components.len().try_into())]
+    component_count: u8,
+    #[br(count = component_count)]
+    components: Vec<u32>,
+    #[br(temp)]
+    #[bw(try_calc = data.len().try_into())]
+    data_count: u8,
+    #[br(count = data_count)]
+    data: Vec<ComponentData>,
+    unknown: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OBJK {
+    pub version: u32,
+    /// FNV-32 hashes of the component names, see [`COMPONENT_NAMES`].
+    pub components: Vec<u32>,
+    pub data: Vec<ComponentData>,
+    pub unknown: u8,
+    pub tgis: Vec<TGI>,
+}
+
+impl OBJK {
+    pub fn get(&self, key: &str) -> Option<&ComponentValue> {
+        self.data.iter().find(|d| d.key == key).map(|d| &d.value)
+    }
+
+    /// Set `key`, adding it if it isn't there yet.
+    pub fn set(&mut self, key: &str, value: ComponentValue) {
+        match self.data.iter_mut().find(|d| d.key == key) {
+            Some(d) => d.value = value,
+            None => self.data.push(ComponentData {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+
+    /// Names of the components the object has, or their hash if unknown.
+    pub fn component_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.components.iter().map(|&hash| {
+            COMPONENT_NAMES
+                .iter()
+                .find(|n| crate::hash::fnv32(n) == hash)
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| format!("{:08X}", hash))
+        })
+    }
+
+    pub fn script_class(&self) -> Option<&str> {
+        match self.get("scriptClass")? {
+            ComponentValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The resource a `ResourceKey`/`AssetResourceName` entry points at.
+    pub fn key(&self, key: &str) -> Option<&TGI> {
+        match self.get(key)? {
+            ComponentValue::ResourceKey(i) | ComponentValue::AssetResourceName(i) => self.tgis.get(*i as usize),
+            _ => None,
+        }
+    }
+
+    pub fn model(&self) -> Option<&TGI> {
+        self.key("modelKey")
+    }
+
+    pub fn footprint(&self) -> Option<&TGI> {
+        self.key("footprintKey")
+    }
+
+    pub fn slots(&self) -> Option<&TGI> {
+        self.key("slotKey")
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+}
+
+impl BinRead for OBJK {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = OBJKHeader::read_options(reader, endian, ())?;
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 8 + header.tgi_offset as u64;
+        let body = OBJKBody::read_options(reader, endian, ())?;
+        if reader.stream_position()? > tgi_pos {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: "OBJK fields overlap the TGI table".to_string(),
+            });
+        }
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u32::read_options(reader, endian, ())?;
+        if 4 + 16 * tgi_count as u64 != header.tgi_size as u64 {
+            return Err(binrw::Error::AssertFail {
+                pos: tgi_pos,
+                message: format!("OBJK has {} TGIs, but a TGI table of {} bytes", tgi_count, header.tgi_size),
+            });
+        }
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::TGI,
+            },
+        )?;
+
+        Ok(OBJK {
+            version: header.version,
+            components: body.components,
+            data: body.data,
+            unknown: body.unknown,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for OBJK {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many {} in OBJK", what),
+        };
+        let mut body = io::Cursor::new(Vec::new());
+        OBJKBody {
+            components: self.components.clone(),
+            data: self.data.clone(),
+            unknown: self.unknown,
+        }
+        .write_options(&mut body, endian, ())?;
+        let body = body.into_inner();
+
+        let tgi_count: u32 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        let header = OBJKHeader {
+            version: self.version,
+            // relative to the end of this field, which is 4 bytes before the body
+            tgi_offset: (body.len() + 4).try_into().map_err(|_| overflow("bytes"))?,
+            tgi_size: 4 + 16 * tgi_count,
+        };
+        header.write_options(writer, endian, ())?;
+        writer.write_all(&body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::TGI)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbpf::filetypes::ResourceType;
+
+    /// A script class and a model key.
+    fn sample() -> (Vec<u8>, OBJK) {
+        let model = TGI::new(ResourceType::MODL as u32, 0, 0x1111);
+        let script = crate::hash::fnv32("Script");
+        let mut body = vec![1];
+        body.extend(script.to_le_bytes());
+        body.push(2);
+        body.extend([11]);
+        body.extend(b"scriptClass");
+        body.push(0);
+        body.extend([14]);
+        body.extend(b"Sims3.Gameplay");
+        body.extend([8]);
+        body.extend(b"modelKey");
+        body.push(1);
+        body.extend(0u32.to_le_bytes());
+        body.push(0xAB);
+
+        let mut bytes = 4u32.to_le_bytes().to_vec();
+        bytes.extend((body.len() as u32 + 4).to_le_bytes());
+        bytes.extend(20u32.to_le_bytes());
+        bytes.extend(&body);
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(model.resource_type.to_le_bytes());
+        bytes.extend(model.resource_group.to_le_bytes());
+        bytes.extend(model.instance.to_le_bytes());
+
+        let objk = OBJK {
+            version: 4,
+            components: vec![script],
+            data: vec![
+                ComponentData {
+                    key: "scriptClass".to_string(),
+                    value: ComponentValue::String("Sims3.Gameplay".to_string()),
+                },
+                ComponentData {
+                    key: "modelKey".to_string(),
+                    value: ComponentValue::ResourceKey(0),
+                },
+            ],
+            unknown: 0xAB,
+            tgis: vec![model],
+        };
+        (bytes, objk)
+    }
+
+    #[test]
+    fn read_write_round_trip() {
+        let (bytes, expected) = sample();
+        let objk = OBJK::read_le(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(objk, expected);
+        assert_eq!(objk.to_bytes().unwrap(), bytes);
+        assert_eq!(objk.script_class(), Some("Sims3.Gameplay"));
+        assert_eq!(objk.model(), Some(&objk.tgis[0]));
+        assert_eq!(objk.component_names().collect::<Vec<_>>(), ["Script"]);
+    }
+
+    #[test]
+    fn rejects_bad_tgi_size() {
+        let (mut bytes, _) = sample();
+        bytes[8..12].copy_from_slice(&36u32.to_le_bytes());
+        assert!(OBJK::read_le(&mut io::Cursor::new(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+//! Patterns, and the XML that CAS presets are made of.
+//!
+//! A pattern is an XMLResource with a `<complate>` root that names the pattern and points at its
+//! textures. Pattern packages have that XML, the textures, and a PTRN so the pattern shows up in CAS.
+//! Clothing keeps a copy of the XML of every pattern its presets use, so the same pattern XML is
+//! often in many packages.
+//!
+//! Keys in this XML are written as `key:T:G:I`, see [`TGI::from_resource_key`].
+
+use super::tgi::TGI;
+use super::ResourceType;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub name: String,
+    /// The CAS pattern category, like `Fabric` or `Leather`.
+    pub category: String,
+    /// The images the pattern uses, like its RGB mask and specular map.
+    pub textures: Vec<TGI>,
+}
+
+impl Pattern {
+    /// Parse a pattern's XML. Gives `None` if it isn't a pattern, since XMLResources are used for
+    /// other things too.
+    pub fn parse(xml: &str) -> Option<Self> {
+        let doc = roxmltree::Document::parse(xml.trim_start_matches('\u{feff}')).ok()?;
+        let root = doc.root_element();
+        if !root.has_tag_name("complate") {
+            return None;
+        }
+        let category = root.attribute("category").or_else(|| root.attribute("typecategory"));
+        Some(Pattern {
+            name: root.attribute("name").unwrap_or_default().to_owned(),
+            category: category.unwrap_or_default().to_owned(),
+            textures: keys_of_type(&doc, ResourceType::IMG),
+        })
+    }
+}
+
+/// The patterns a CAS preset uses, from the preset's XML. Unreadable XML has none.
+pub fn preset_patterns(xml: &str) -> Vec<TGI> {
+    match roxmltree::Document::parse(xml.trim_start_matches('\u{feff}')) {
+        Ok(doc) => keys_of_type(&doc, ResourceType::XMLResource),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Every key of type `ty` in an attribute of the document, in order and without repeats.
+fn keys_of_type(doc: &roxmltree::Document, ty: ResourceType) -> Vec<TGI> {
+    let mut keys = Vec::new();
+    for attribute in doc.descendants().flat_map(|node| node.attributes()) {
+        match TGI::from_resource_key(attribute.value()) {
+            Some(key) if key.resource_type == ty as u32 && !keys.contains(&key) => keys.push(key),
+            _ => {}
+        }
+    }
+    keys
+}
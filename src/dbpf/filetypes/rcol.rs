@@ -0,0 +1,415 @@
+//! The RCOL ("resource collection") container that wraps nearly every mesh and object format.
+//!
+//! Layout: a small header, the internal chunk ids, the external references, a (position, size)
+//! index for each internal chunk, then the chunk data. Positions are relative to the start of the RCOL.
+
+use super::tgi::{TGIOrder, TGI};
+use crate::dbpf::ChunkHandle;
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[binrw]
+struct RCOLHeader {
+    version: u32,
+    public_chunks: u32,
+    unused: u32,
+    external_count: u32,
+    internal_count: u32,
+}
+
+#[binrw]
+struct RCOLChunkIndex {
+    position: u32,
+    size: u32,
+}
+
+/// Four-byte tag at the start of an RCOL chunk's data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkTag {
+    GEOM,
+    MODL,
+    MLOD,
+    MATD,
+    VRTF,
+    VBUF,
+    IBUF,
+    SKIN,
+    MTST,
+    LITE,
+    VPXY,
+    RSLT,
+    FTPT,
+}
+
+impl ChunkTag {
+    const ALL: [ChunkTag; 13] = [
+        ChunkTag::GEOM,
+        ChunkTag::MODL,
+        ChunkTag::MLOD,
+        ChunkTag::MATD,
+        ChunkTag::VRTF,
+        ChunkTag::VBUF,
+        ChunkTag::IBUF,
+        ChunkTag::SKIN,
+        ChunkTag::MTST,
+        ChunkTag::LITE,
+        ChunkTag::VPXY,
+        ChunkTag::RSLT,
+        ChunkTag::FTPT,
+    ];
+
+    pub fn magic(self) -> &'static [u8; 4] {
+        match self {
+            ChunkTag::GEOM => b"GEOM",
+            ChunkTag::MODL => b"MODL",
+            ChunkTag::MLOD => b"MLOD",
+            ChunkTag::MATD => b"MATD",
+            ChunkTag::VRTF => b"VRTF",
+            ChunkTag::VBUF => b"VBUF",
+            ChunkTag::IBUF => b"IBUF",
+            ChunkTag::SKIN => b"SKIN",
+            ChunkTag::MTST => b"MTST",
+            ChunkTag::LITE => b"LITE",
+            ChunkTag::VPXY => b"VPXY",
+            ChunkTag::RSLT => b"RSLT",
+            ChunkTag::FTPT => b"FTPT",
+        }
+    }
+
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        let magic = data.get(..4)?;
+        Self::ALL.into_iter().find(|t| t.magic() == magic)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RCOLChunk {
+    pub tgi: TGI,
+    pub data: Vec<u8>,
+}
+
+impl RCOLChunk {
+    pub fn tag(&self) -> Option<ChunkTag> {
+        ChunkTag::from_magic(&self.data)
+    }
+
+    pub fn reader(&self) -> io::Cursor<&[u8]> {
+        io::Cursor::new(&self.data)
+    }
+}
+
+/// A reference from one chunk to another chunk or resource, as stored inside chunk data.
+///
+/// The top nibble is the kind, the rest is a 1-based index (0 meaning "no reference").
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[binrw]
+pub struct ChunkReference(pub u32);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkReferenceKind {
+    Public,
+    Private,
+    External,
+    Delayed,
+}
+
+impl ChunkReference {
+    pub const NONE: ChunkReference = ChunkReference(0);
+
+    pub fn kind(self) -> Option<ChunkReferenceKind> {
+        match self.0 >> 28 {
+            0 => Some(ChunkReferenceKind::Public),
+            1 => Some(ChunkReferenceKind::Private),
+            2 => Some(ChunkReferenceKind::External),
+            3 => Some(ChunkReferenceKind::Delayed),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> Option<usize> {
+        (self.0 & 0x0FFF_FFFF).checked_sub(1).map(|i| i as usize)
+    }
+
+    pub fn new(kind: ChunkReferenceKind, index: usize) -> Self {
+        let kind = match kind {
+            ChunkReferenceKind::Public => 0,
+            ChunkReferenceKind::Private => 1,
+            ChunkReferenceKind::External => 2,
+            ChunkReferenceKind::Delayed => 3,
+        };
+        ChunkReference((kind << 28) | ((index as u32 + 1) & 0x0FFF_FFFF))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RCOL {
+    pub version: u32, // 3
+    /// The first `public_chunks` chunks are public, the rest are private.
+    pub public_chunks: u32,
+    pub unused: u32,
+    pub chunks: Vec<RCOLChunk>,
+    pub external: Vec<TGI>,
+}
+
+impl Default for RCOL {
+    fn default() -> Self {
+        RCOL {
+            version: 3,
+            public_chunks: 0,
+            unused: 0,
+            chunks: Vec::new(),
+            external: Vec::new(),
+        }
+    }
+}
+
+pub enum Resolved<'a> {
+    Chunk(&'a RCOLChunk),
+    External(&'a TGI),
+}
+
+impl RCOL {
+    pub fn find(&self, tag: ChunkTag) -> Option<&RCOLChunk> {
+        self.find_all(tag).next()
+    }
+
+    pub fn find_mut(&mut self, tag: ChunkTag) -> Option<&mut RCOLChunk> {
+        self.chunks.iter_mut().find(|c| c.tag() == Some(tag))
+    }
+
+    pub fn find_all(&self, tag: ChunkTag) -> impl Iterator<Item = &RCOLChunk> {
+        self.chunks.iter().filter(move |c| c.tag() == Some(tag))
+    }
+
+    pub fn find_by_tgi(&self, tgi: &TGI) -> Option<&RCOLChunk> {
+        self.chunks.iter().find(|c| c.tgi == *tgi)
+    }
+
+    pub fn resolve(&self, reference: ChunkReference) -> Option<Resolved<'_>> {
+        let index = reference.index()?;
+        match reference.kind()? {
+            ChunkReferenceKind::Public | ChunkReferenceKind::Private => {
+                self.chunks.get(index).map(Resolved::Chunk)
+            }
+            ChunkReferenceKind::External | ChunkReferenceKind::Delayed => {
+                self.external.get(index).map(Resolved::External)
+            }
+        }
+    }
+
+    pub fn resolve_chunk(&self, reference: ChunkReference) -> Option<&RCOLChunk> {
+        match self.resolve(reference)? {
+            Resolved::Chunk(c) => Some(c),
+            Resolved::External(_) => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+
+    /// Serialize into a chunk that can replace the original entry's chunk in a package.
+    pub fn to_chunk_handle<'brand>(&self) -> BinResult<ChunkHandle<'brand>> {
+        Ok(ChunkHandle::Dirty {
+            decompressed: self.to_bytes()?,
+            should_compress: true,
+        })
+    }
+}
+
+impl BinRead for RCOL {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = RCOLHeader::read_options(reader, endian, ())?;
+        if header.version != 3 {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: format!("unsupported RCOL version {}", header.version),
+            });
+        }
+        let itgs = |count: u32| binrw::VecArgs {
+            count: count as usize,
+            inner: TGIOrder::ITG,
+        };
+        let tgis = Vec::<TGI>::read_options(reader, endian, itgs(header.internal_count))?;
+        let external = Vec::<TGI>::read_options(reader, endian, itgs(header.external_count))?;
+        let index: Vec<RCOLChunkIndex> = binrw::helpers::count(header.internal_count as usize)(reader, endian, ())?;
+
+        let mut end = reader.stream_position()?;
+        let mut chunks = Vec::with_capacity(tgis.len());
+        for (tgi, index) in tgis.into_iter().zip(index) {
+            let pos = start + index.position as u64;
+            reader.seek(SeekFrom::Start(pos))?;
+            let mut data = Vec::new();
+            reader.take(index.size as u64).read_to_end(&mut data)?;
+            if data.len() != index.size as usize {
+                return Err(binrw::Error::AssertFail {
+                    pos,
+                    message: format!("RCOL chunk {} is truncated", tgi),
+                });
+            }
+            end = end.max(pos + index.size as u64);
+            chunks.push(RCOLChunk { tgi, data });
+        }
+        // leave the reader after the last chunk, rather than wherever the last seek was
+        reader.seek(SeekFrom::Start(end))?;
+
+        Ok(RCOL {
+            version: header.version,
+            public_chunks: header.public_chunks,
+            unused: header.unused,
+            chunks,
+            external,
+        })
+    }
+}
+
+impl BinWrite for RCOL {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many RCOL {}", what),
+        };
+        let header = RCOLHeader {
+            version: self.version,
+            public_chunks: self.public_chunks,
+            unused: self.unused,
+            external_count: self.external.len().try_into().map_err(|_| overflow("references"))?,
+            internal_count: self.chunks.len().try_into().map_err(|_| overflow("chunks"))?,
+        };
+
+        // chunks are laid out back to back after the index, each aligned to 4 bytes
+        let align = |x: usize| (x + 3) & !3;
+        let mut position = 20 + 16 * (self.chunks.len() + self.external.len()) + 8 * self.chunks.len();
+        let mut index = Vec::with_capacity(self.chunks.len());
+        for chunk in &self.chunks {
+            position = align(position);
+            index.push(RCOLChunkIndex {
+                position: position.try_into().map_err(|_| overflow("data"))?,
+                size: chunk.data.len().try_into().map_err(|_| overflow("data"))?,
+            });
+            position += chunk.data.len();
+        }
+
+        header.write_options(writer, endian, ())?;
+        for chunk in &self.chunks {
+            chunk.tgi.write_options(writer, endian, TGIOrder::ITG)?;
+        }
+        self.external.write_options(writer, endian, TGIOrder::ITG)?;
+        index.write_options(writer, endian, ())?;
+
+        let mut written = 20 + 16 * (self.chunks.len() + self.external.len()) + 8 * self.chunks.len();
+        for chunk in &self.chunks {
+            let padding = align(written) - written;
+            writer.write_all(&[0u8; 3][..padding])?;
+            writer.write_all(&chunk.data)?;
+            written += padding + chunk.data.len();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn itg(tgi: TGI) -> Vec<u8> {
+        let mut bytes = tgi.instance.to_le_bytes().to_vec();
+        bytes.extend(tgi.resource_type.to_le_bytes());
+        bytes.extend(tgi.resource_group.to_le_bytes());
+        bytes
+    }
+
+    /// An MLOD-like chunk that points at the second chunk, and a VRTF-like one that points at the
+    /// external TGI.
+    fn sample() -> (Vec<u8>, RCOL) {
+        let first_tgi = TGI::new(0x01D10F34, 0, 0x1111);
+        let second_tgi = TGI::new(0x01D0E75D, 0, 0x2222);
+        let external_tgi = TGI::new(0x00B2D882, 0x0080_0000, 0x3333);
+        let mut first = b"MLOD".to_vec();
+        first.extend(ChunkReference::new(ChunkReferenceKind::Private, 1).0.to_le_bytes());
+        first.push(0xAA); // makes the next chunk need padding
+        let mut second = b"VRTF".to_vec();
+        second.extend(ChunkReference::new(ChunkReferenceKind::External, 0).0.to_le_bytes());
+
+        let mut bytes = Vec::new();
+        for field in [3u32, 1, 0, 1, 2] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(itg(first_tgi));
+        bytes.extend(itg(second_tgi));
+        bytes.extend(itg(external_tgi));
+        let data_start = 20 + 16 * 3 + 8 * 2;
+        for (position, size) in [(data_start, first.len()), (data_start + 12, second.len())] {
+            bytes.extend((position as u32).to_le_bytes());
+            bytes.extend((size as u32).to_le_bytes());
+        }
+        bytes.extend(&first);
+        bytes.extend([0; 3]);
+        bytes.extend(&second);
+
+        let rcol = RCOL {
+            version: 3,
+            public_chunks: 1,
+            unused: 0,
+            chunks: vec![
+                RCOLChunk {
+                    tgi: first_tgi,
+                    data: first,
+                },
+                RCOLChunk {
+                    tgi: second_tgi,
+                    data: second,
+                },
+            ],
+            external: vec![external_tgi],
+        };
+        (bytes, rcol)
+    }
+
+    #[test]
+    fn read_write_round_trip() {
+        let (bytes, expected) = sample();
+        let rcol = RCOL::read_le(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(rcol, expected);
+        assert_eq!(rcol.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn references_resolve() {
+        let (_, rcol) = sample();
+        let mut reader = rcol.chunks[0].reader();
+        reader.set_position(4);
+        let internal = ChunkReference::read_le(&mut reader).unwrap();
+        assert_eq!(internal.kind(), Some(ChunkReferenceKind::Private));
+        assert_eq!(rcol.resolve_chunk(internal).and_then(RCOLChunk::tag), Some(ChunkTag::VRTF));
+
+        let mut reader = rcol.chunks[1].reader();
+        reader.set_position(4);
+        let external = ChunkReference::read_le(&mut reader).unwrap();
+        assert!(matches!(rcol.resolve(external), Some(Resolved::External(tgi)) if *tgi == rcol.external[0]));
+        assert!(rcol.resolve_chunk(external).is_none());
+        assert!(rcol.resolve(ChunkReference::NONE).is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let (mut bytes, _) = sample();
+        bytes[0] = 4;
+        assert!(RCOL::read_le(&mut io::Cursor::new(&bytes)).is_err());
+    }
+}
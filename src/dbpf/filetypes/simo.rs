@@ -0,0 +1,126 @@
+//! SIMO, a sim's outfit. Saves and exported households have one per outfit of every sim, and it
+//! points at the CAS parts, skin tone and face sliders that make up the sim.
+//!
+//! Only the TGI table is decoded. Everything before it (the sim's shape, and the preset of each
+//! part) changes with the resource version and is kept as raw bytes.
+
+use super::tgi::{TGIOrder, TGI};
+use super::ResourceType;
+
+use binrw::{binrw, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[binrw]
+struct SIMOHeader {
+    version: u32,
+    tgi_offset: u32,
+    tgi_size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SIMO {
+    pub version: u32,
+    /// Everything between the header and the TGI table.
+    pub body: Vec<u8>,
+    pub tgis: Vec<TGI>,
+}
+
+impl SIMO {
+    fn of_type(&self, ty: ResourceType) -> impl Iterator<Item = &TGI> + '_ {
+        self.tgis.iter().filter(move |t| t.resource_type == ty as u32)
+    }
+
+    /// The CAS parts the sim wears in this outfit, including hair, makeup and accessories.
+    pub fn parts(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.of_type(ResourceType::CASP)
+    }
+
+    pub fn skin_tone(&self) -> Option<&TGI> {
+        self.of_type(ResourceType::SkinTone).next()
+    }
+
+    /// Face sliders.
+    pub fn sliders(&self) -> impl Iterator<Item = &TGI> + '_ {
+        self.of_type(ResourceType::FACE)
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+}
+
+impl BinRead for SIMO {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = SIMOHeader::read_options(reader, endian, ())?;
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 8 + header.tgi_offset as u64;
+
+        let end = reader.stream_position()?;
+        if end > tgi_pos {
+            return Err(binrw::Error::AssertFail {
+                pos: start,
+                message: "SIMO header overlaps the TGI table".to_string(),
+            });
+        }
+        let mut body = Vec::new();
+        reader.take(tgi_pos - end).read_to_end(&mut body)?;
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u32::read_options(reader, endian, ())?;
+        if 4 + 16 * tgi_count as u64 != header.tgi_size as u64 {
+            return Err(binrw::Error::AssertFail {
+                pos: tgi_pos,
+                message: format!("SIMO has {} TGIs, but a TGI table of {} bytes", tgi_count, header.tgi_size),
+            });
+        }
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::TGI,
+            },
+        )?;
+
+        Ok(SIMO {
+            version: header.version,
+            body,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for SIMO {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many {} in SIMO", what),
+        };
+        let tgi_count: u32 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        let header = SIMOHeader {
+            version: self.version,
+            // relative to the end of this field, which is 4 bytes before the body
+            tgi_offset: (self.body.len() + 4).try_into().map_err(|_| overflow("bytes"))?,
+            tgi_size: 4 + 16 * tgi_count,
+        };
+        header.write_options(writer, endian, ())?;
+        writer.write_all(&self.body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::TGI)?;
+        Ok(())
+    }
+}
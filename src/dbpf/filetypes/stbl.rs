@@ -0,0 +1,49 @@
+use super::ResourceType;
+use crate::dbpf::{DBPFIndexEntry, FileCtx};
+use std::collections::BTreeMap;
+
+use binrw::{binrw, BinRead, BinResult};
+
+#[binrw]
+struct STBLEntry {
+    key: u64,
+    #[br(temp)]
+    #[bw(try_calc = text.len().try_into())]
+    len: u32,
+    // UTF-16LE, no terminator
+    #[br(count = len)]
+    text: Vec<u16>,
+}
+
+#[binrw]
+#[brw(magic = b"STBL")]
+pub struct STBL {
+    pub version: u8, // 2
+    unk1: u16,
+    #[br(temp)]
+    #[bw(try_calc = entries.len().try_into())]
+    count: u32,
+    unk2: [u8; 6],
+    #[br(count = count)]
+    entries: Vec<STBLEntry>,
+}
+
+impl STBL {
+    pub fn strings(&self) -> impl Iterator<Item = (u64, String)> + '_ {
+        self.entries
+            .iter()
+            .map(|e| (e.key, String::from_utf16_lossy(&e.text)))
+    }
+}
+
+pub fn gather_strings_into<'brand>(ctx: &mut impl FileCtx<'brand>, entry: &DBPFIndexEntry<'brand>, string_map: &mut BTreeMap<u64, String>) -> BinResult<()> {
+    if entry.resource_type != ResourceType::STBL as u32 {
+        return Err(binrw::Error::AssertFail { pos: 0, message: "Not an STBL tag.".to_string() });
+    }
+
+    let mut reader = entry.get_reader(ctx)?;
+    let stbl: STBL = BinRead::read_le(&mut reader)?;
+    string_map.extend(stbl.strings());
+
+    Ok(())
+}
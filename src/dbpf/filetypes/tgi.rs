@@ -0,0 +1,157 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use binrw::{io, BinRead, BinResult, BinWrite, Endian};
+
+/// A resource key. Formats that embed keys don't agree on field order, see [`TGIOrder`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Default)]
+pub struct TGI {
+    pub resource_type: u32,
+    pub resource_group: u32,
+    pub instance: u64,
+}
+
+/// Field order of a [`TGI`] on disk.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum TGIOrder {
+    #[default]
+    TGI,
+    ITG,
+    IGT,
+}
+
+impl TGI {
+    pub fn new(resource_type: u32, resource_group: u32, instance: u64) -> Self {
+        TGI {
+            resource_type,
+            resource_group,
+            instance,
+        }
+    }
+}
+
+impl BinRead for TGI {
+    type Args<'a> = TGIOrder;
+
+    fn read_options<R: io::Read + io::Seek>(
+        reader: &mut R,
+        endian: Endian,
+        order: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let (resource_type, resource_group, instance) = match order {
+            TGIOrder::TGI => <(u32, u32, u64)>::read_options(reader, endian, ())?,
+            TGIOrder::ITG => {
+                let (i, t, g) = <(u64, u32, u32)>::read_options(reader, endian, ())?;
+                (t, g, i)
+            }
+            TGIOrder::IGT => {
+                let (i, g, t) = <(u64, u32, u32)>::read_options(reader, endian, ())?;
+                (t, g, i)
+            }
+        };
+        Ok(TGI::new(resource_type, resource_group, instance))
+    }
+}
+
+impl BinWrite for TGI {
+    type Args<'a> = TGIOrder;
+
+    fn write_options<W: io::Write + io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        order: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let TGI {
+            resource_type: t,
+            resource_group: g,
+            instance: i,
+        } = *self;
+        match order {
+            TGIOrder::TGI => (t, g, i).write_options(writer, endian, ()),
+            TGIOrder::ITG => (i, t, g).write_options(writer, endian, ()),
+            TGIOrder::IGT => (i, g, t).write_options(writer, endian, ()),
+        }
+    }
+}
+
+impl Display for TGI {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:08X}:{:08X}:{:016X}",
+            self.resource_type, self.resource_group, self.instance
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTGIError(String);
+
+impl Display for ParseTGIError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid TGI '{}', expected T:G:I in hex", self.0)
+    }
+}
+
+impl std::error::Error for ParseTGIError {}
+
+/// Parses the `T:G:I` hex form used by [`Display`] and S3PE.
+impl FromStr for TGI {
+    type Err = ParseTGIError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseTGIError(s.to_owned());
+        let mut parts = s.split(':');
+        let mut next = || parts.next().ok_or_else(err);
+        let resource_type = u32::from_str_radix(next()?, 16).map_err(|_| err())?;
+        let resource_group = u32::from_str_radix(next()?, 16).map_err(|_| err())?;
+        let instance = u64::from_str_radix(next()?, 16).map_err(|_| err())?;
+        if parts.next().is_some() {
+            return Err(err());
+        }
+        Ok(TGI::new(resource_type, resource_group, instance))
+    }
+}
+
+impl TGI {
+    /// Parses the `key:T:G:I` form used in preset and pattern XML.
+    pub fn from_resource_key(s: &str) -> Option<TGI> {
+        s.strip_prefix("key:")?.parse().ok()
+    }
+
+    /// The filename S3PE exports a resource to: `S3_T_G_I_name%%+TAG.ext`.
+    /// `name` should already be safe to use in a filename.
+    pub fn s3pe_filename(&self, name: Option<&str>) -> String {
+        let (tag, extension) = super::tag_and_extension(self.resource_type);
+        let name = name.map(|n| format!("_{}", n)).unwrap_or_default();
+        format!(
+            "S3_{:08X}_{:08X}_{:016X}{}%%+{}{}",
+            self.resource_type, self.resource_group, self.instance, name, tag, extension
+        )
+    }
+
+    /// Parses a filename produced by [`TGI::s3pe_filename`] back into the key and name.
+    /// The tag and extension are optional and ignored.
+    pub fn from_s3pe_filename(filename: &str) -> Option<(TGI, Option<String>)> {
+        let rest = filename.strip_prefix("S3_")?;
+        let (t, rest) = rest.split_at_checked(8)?;
+        let (g, rest) = rest.strip_prefix('_')?.split_at_checked(8)?;
+        let (i, rest) = rest.strip_prefix('_')?.split_at_checked(16)?;
+        let tgi = TGI::new(
+            u32::from_str_radix(t, 16).ok()?,
+            u32::from_str_radix(g, 16).ok()?,
+            u64::from_str_radix(i, 16).ok()?,
+        );
+
+        let rest = match rest.find("%%+") {
+            Some(pos) => &rest[..pos],
+            None => rest.rsplit_once('.').map_or(rest, |(stem, _)| stem),
+        };
+        let name = match rest {
+            "" => None,
+            _ => Some(rest.strip_prefix('_')?.to_owned()),
+        };
+        Some((tgi, name))
+    }
+}
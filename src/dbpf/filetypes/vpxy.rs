@@ -0,0 +1,184 @@
+//! VPXY, the "visual proxy" that lists which meshes make up each LOD of a CAS part or object.
+//! Always found inside an [`RCOL`](super::rcol::RCOL).
+
+use super::rcol::{ChunkTag, RCOL};
+use super::tgi::{TGIOrder, TGI};
+
+use binrw::{binrw, io, BinRead, BinResult, BinWrite, Endian};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[binrw]
+#[brw(magic = b"VPXY")]
+struct VPXYHeader {
+    version: u32,
+    tgi_offset: u32,
+    tgi_size: u32,
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+pub enum VPXYEntry {
+    /// The resources (GEOMs or MLODs) used by one LOD, as indices into [`VPXY::tgis`].
+    #[brw(magic = 0u8)]
+    Lod {
+        id: u8,
+        #[br(temp)]
+        #[bw(try_calc = tgi_indices.len().try_into())]
+        count: u8,
+        #[br(count = count)]
+        tgi_indices: Vec<u32>,
+    },
+    /// Some other resource that belongs to the part, usually a light or an effect.
+    #[brw(magic = 1u8)]
+    Other { tgi_index: u32 },
+}
+
+#[binrw]
+#[derive(Clone, Debug, PartialEq)]
+struct VPXYBody {
+    #[br(temp)]
+    #[bw(try_calc = entries.len().try_into())]
+    entry_count: u8,
+    #[br(count = entry_count)]
+    entries: Vec<VPXYEntry>,
+    #[brw(magic = 2u8)]
+    bounds: [f32; 6],
+    unused: [u8; 4],
+    #[br(temp)]
+    #[bw(calc = ftpt_index.is_some() as u8)]
+    modular: u8,
+    #[br(if(modular != 0))]
+    ftpt_index: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VPXY {
+    pub version: u32,
+    pub entries: Vec<VPXYEntry>,
+    /// min x/y/z, then max x/y/z
+    pub bounds: [f32; 6],
+    pub unused: [u8; 4],
+    /// Index into `tgis` of the footprint, for modular objects.
+    pub ftpt_index: Option<u32>,
+    pub tgis: Vec<TGI>,
+}
+
+impl VPXY {
+    /// The resources that make up LOD `id`. Indices that are out of range are skipped.
+    pub fn lod(&self, id: u8) -> impl Iterator<Item = &TGI> + '_ {
+        self.entries
+            .iter()
+            .filter_map(move |e| match e {
+                VPXYEntry::Lod { id: i, tgi_indices } if *i == id => Some(tgi_indices),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|&i| self.tgis.get(i as usize))
+    }
+
+    /// The ids of every LOD, in the order they are listed.
+    pub fn lod_ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.entries.iter().filter_map(|e| match e {
+            VPXYEntry::Lod { id, .. } => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// Read the first VPXY chunk from an RCOL.
+    pub fn from_rcol(rcol: &RCOL) -> BinResult<Self> {
+        let chunk = rcol.find(ChunkTag::VPXY).ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: "RCOL has no VPXY chunk".to_string(),
+        })?;
+        Self::read_le(&mut chunk.reader())
+    }
+
+    pub fn to_bytes(&self) -> BinResult<Vec<u8>> {
+        crate::util::to_bytes(self)
+    }
+
+    /// Replace the first VPXY chunk of an RCOL with this one.
+    pub fn store_into(&self, rcol: &mut RCOL) -> BinResult<()> {
+        let data = self.to_bytes()?;
+        let chunk = rcol.find_mut(ChunkTag::VPXY).ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: "RCOL has no VPXY chunk".to_string(),
+        })?;
+        chunk.data = data;
+        Ok(())
+    }
+}
+
+impl BinRead for VPXY {
+    type Args<'a> = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        let start = reader.stream_position()?;
+        let header = VPXYHeader::read_options(reader, endian, ())?;
+        // the offset counts from the end of the offset field itself
+        let tgi_pos = start + 12 + header.tgi_offset as u64;
+        let body = VPXYBody::read_options(reader, endian, ())?;
+
+        reader.seek(SeekFrom::Start(tgi_pos))?;
+        let tgi_count = u32::read_options(reader, endian, ())?;
+        let tgis = Vec::<TGI>::read_options(
+            reader,
+            endian,
+            binrw::VecArgs {
+                count: tgi_count as usize,
+                inner: TGIOrder::TGI,
+            },
+        )?;
+
+        Ok(VPXY {
+            version: header.version,
+            entries: body.entries,
+            bounds: body.bounds,
+            unused: body.unused,
+            ftpt_index: body.ftpt_index,
+            tgis,
+        })
+    }
+}
+
+impl BinWrite for VPXY {
+    type Args<'a> = ();
+
+    fn write_options<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<()> {
+        let overflow = |what: &str| binrw::Error::AssertFail {
+            pos: 0,
+            message: format!("too many {} in VPXY", what),
+        };
+        let mut body = io::Cursor::new(Vec::new());
+        VPXYBody {
+            entries: self.entries.clone(),
+            bounds: self.bounds,
+            unused: self.unused,
+            ftpt_index: self.ftpt_index,
+        }
+        .write_options(&mut body, endian, ())?;
+        let body = body.into_inner();
+
+        let tgi_count: u32 = self.tgis.len().try_into().map_err(|_| overflow("TGIs"))?;
+        let header = VPXYHeader {
+            version: self.version,
+            // relative to the end of this field, which is 4 bytes before the body
+            tgi_offset: (body.len() + 4).try_into().map_err(|_| overflow("bytes"))?,
+            tgi_size: 4 + 16 * tgi_count,
+        };
+        header.write_options(writer, endian, ())?;
+        writer.write_all(&body)?;
+        tgi_count.write_options(writer, endian, ())?;
+        self.tgis.write_options(writer, endian, TGIOrder::TGI)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+//! Reading just the index of a package, for tools that only need to know what's in it.
+//!
+//! Unlike [`DBPFReader::parse`](super::DBPFReader::parse), this needs no guard and keeps nothing open,
+//! so it can be called from anywhere (like a rayon closure) on thousands of packages. It reads the
+//! header and the index with one read each.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use super::filetypes::tgi::TGI;
+use super::{read_index, IndexEntry};
+
+/// A resource in a package's index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IndexKey {
+    pub tgi: TGI,
+    /// Where the resource's data is in the file.
+    pub offset: u32,
+    /// Size of the data in the file.
+    pub filesize: u32,
+    /// Size of the data once decompressed.
+    pub memsize: u32,
+    pub compressed: bool,
+}
+
+impl From<&IndexEntry> for IndexKey {
+    fn from(e: &IndexEntry) -> Self {
+        IndexKey {
+            tgi: TGI::new(
+                e.resource_type,
+                e.resource_group,
+                ((e.instance_hi as u64) << 32) | e.instance_lo as u64,
+            ),
+            offset: e.chunk_offset,
+            filesize: e.filesize_unk1.filesize().into(),
+            memsize: e.memsize,
+            compressed: e.compressed_unk2.0 != 0,
+        }
+    }
+}
+
+/// Read the index of the package in `reader`.
+pub fn read<R: io::Read + io::Seek>(reader: &mut R) -> crate::error::Result<Vec<IndexKey>> {
+    let file_size = reader.seek(io::SeekFrom::End(0))?;
+    read_with_size(reader, file_size)
+}
+
+/// Read the index of the package at `path`.
+pub fn read_file(path: impl AsRef<Path>) -> crate::error::Result<Vec<IndexKey>> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_with_size(&mut file, file_size)
+}
+
+fn read_with_size<R: io::Read + io::Seek>(reader: &mut R, file_size: u64) -> crate::error::Result<Vec<IndexKey>> {
+    let (_, entries) = read_index(reader, file_size)?;
+    Ok(entries.iter().map(IndexKey::from).collect())
+}
@@ -0,0 +1,438 @@
+//! Recovering what's left of a damaged package, usually a half-downloaded one.
+//!
+//! If the index can be read (or part of it), every entry whose data is still there is kept:
+//! compressed resources must still decompress, and uncompressed ones are cut off at the end of
+//! the file. If there's no usable index, the file is searched for compressed resources, PNGs and
+//! DDS images instead, and those are given made-up keys.
+//!
+//! The result is a [`DBPF`] with every resource in memory, which can be written with [`DBPF::write_to`].
+
+use std::io;
+use std::ops::Range;
+
+use binrw::BinRead;
+use memchr::memmem;
+
+use super::compression;
+use super::filetypes::rcol::ChunkTag;
+use super::filetypes::tgi::TGI;
+use super::filetypes::ResourceType;
+use super::{ChunkHandle, DBPFHeader, DBPFIndexEntry, IndexEntry, IndexEntryMask, PartialIndexEntry, DBPF};
+use crate::error::Error;
+
+const HEADER_SIZE: usize = 96;
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+const DDS_MAGIC: &[u8] = b"DDS ";
+
+/// What happened to the index.
+#[derive(Debug)]
+pub enum IndexState {
+    Intact,
+    /// The index is cut off, and only the first `read` of its `expected` entries are left.
+    Partial { read: usize, expected: u32 },
+    /// The index (or header) couldn't be read at all, so the file was searched for resources.
+    Missing(Error),
+}
+
+/// How a resource's data was recovered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    Intact,
+    /// The data is cut off by the end of the file. `expected` is how long it should be.
+    Truncated { expected: u64 },
+    /// The data decompressed fine, but isn't the size the index says.
+    Resized { expected: u64 },
+    /// Found by searching the file, so the key is made up.
+    Scanned,
+}
+
+#[derive(Clone, Debug)]
+pub struct Recovered {
+    pub tgi: TGI,
+    /// Where the data was in the damaged file.
+    pub offset: u64,
+    pub recovery: Recovery,
+}
+
+#[derive(Debug)]
+pub struct SalvageReport {
+    pub index: IndexState,
+    /// Every resource in the repaired package, in order.
+    pub recovered: Vec<Recovered>,
+    /// Resources that were listed in the index, but couldn't be recovered.
+    pub dropped: Vec<Error>,
+}
+
+/// Recover what can be recovered from the package in `data`.
+pub fn salvage(data: &[u8]) -> (DBPF<'static, ()>, SalvageReport) {
+    let mut package = DBPF::new();
+    let mut report = SalvageReport {
+        index: IndexState::Intact,
+        recovered: Vec::new(),
+        dropped: Vec::new(),
+    };
+
+    let mut reader = io::Cursor::new(data);
+    let header = match DBPFHeader::read_le(&mut reader) {
+        Ok(header) => header,
+        Err(e) => {
+            report.index = IndexState::Missing(e.into());
+            scan(data, 0..data.len(), &mut package, &mut report);
+            return (package, report);
+        }
+    };
+    package.maybe_flags = header.maybe_flags;
+    package.created_timestamp = header.created_timestamp;
+    package.modified_timestamp = header.modified_timestamp;
+
+    let (entries, state) = read_index(&mut reader, &header, data.len() as u64);
+    if entries.is_empty() && header.index_entries != 0 {
+        report.index = match state {
+            IndexState::Missing(e) => IndexState::Missing(e),
+            _ => IndexState::Missing(Error::TruncatedIndex {
+                offset: header.index_position as u64,
+                entries: header.index_entries,
+                file_size: data.len() as u64,
+            }),
+        };
+        // the index is usually at the end, so there's no telling where the data stops
+        let index = header.index_position as usize;
+        let end = if index >= HEADER_SIZE && index < data.len() { index } else { data.len() };
+        scan(data, HEADER_SIZE.min(end)..end, &mut package, &mut report);
+        return (package, report);
+    }
+    report.index = state;
+
+    for entry in entries {
+        let tgi = TGI::new(
+            entry.resource_type,
+            entry.resource_group,
+            ((entry.instance_hi as u64) << 32) | entry.instance_lo as u64,
+        );
+        let offset = entry.chunk_offset as u64;
+        let filesize: u64 = u32::from(entry.filesize_unk1.filesize()).into();
+        let memsize = entry.memsize as u64;
+        let compressed = entry.compressed_unk2.0 != 0;
+
+        let start = (offset as usize).min(data.len());
+        let end = (offset.saturating_add(filesize) as usize).min(data.len());
+        let stored = &data[start..end];
+        if stored.is_empty() && filesize != 0 {
+            report.dropped.push(Error::ChunkOutOfBounds {
+                tgi: Some(tgi),
+                offset,
+                size: filesize,
+                file_size: data.len() as u64,
+            });
+            continue;
+        }
+        let mut recovery = if (stored.len() as u64) < filesize {
+            Recovery::Truncated { expected: filesize }
+        } else {
+            Recovery::Intact
+        };
+        let decompressed = if compressed {
+            match compression::decompress(stored) {
+                Ok(decompressed) => {
+                    if decompressed.len() as u64 != memsize && recovery == Recovery::Intact {
+                        recovery = Recovery::Resized { expected: memsize };
+                    }
+                    decompressed
+                }
+                Err(e) => {
+                    report.dropped.push(Error::Decompression {
+                        tgi: Some(tgi),
+                        offset,
+                        message: e,
+                    });
+                    continue;
+                }
+            }
+        } else {
+            stored.to_vec()
+        };
+
+        package.entries.push(DBPFIndexEntry {
+            resource_type: tgi.resource_type,
+            resource_group: tgi.resource_group,
+            instance: tgi.instance,
+            unk1: entry.filesize_unk1.unk1(),
+            unk2: entry.compressed_unk2.1,
+            chunk: ChunkHandle::Dirty {
+                decompressed,
+                should_compress: compressed,
+            },
+        });
+        report.recovered.push(Recovered { tgi, offset, recovery });
+    }
+
+    (package, report)
+}
+
+/// Read as much of the index as is there.
+fn read_index(reader: &mut io::Cursor<&[u8]>, header: &DBPFHeader, file_size: u64) -> (Vec<IndexEntry>, IndexState) {
+    if header.major != 2 || header.minor != 0 {
+        let (offset, version) = if header.major != 2 { (4, header.major) } else { (8, header.minor) };
+        let e = Error::UnsupportedVersion {
+            offset,
+            tgi: None,
+            version,
+        };
+        return (Vec::new(), IndexState::Missing(e));
+    }
+    reader.set_position(header.index_position as u64);
+    let common = IndexEntryMask::read_le(reader)
+        .and_then(|mask| PartialIndexEntry::read_le_args(reader, (mask,)));
+    let common = match common {
+        Ok(common) => common,
+        Err(e) => return (Vec::new(), IndexState::Missing(e.into())),
+    };
+    // not trusting the entry count, since the index might be garbage. entries that aren't all
+    // common take at least 4 bytes, so no more are read than the index (and the file) can hold.
+    let room = (header.index_size as u64).min(file_size.saturating_sub(reader.position())) / 4;
+    let mut entries = Vec::new();
+    for _ in 0..(header.index_entries as u64).min(room.max(1)) {
+        let before = reader.position();
+        match IndexEntry::read_le_args(reader, (common.clone(),)) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => break,
+        }
+        // every field is common, so the rest would be copies of this one
+        if reader.position() == before {
+            break;
+        }
+    }
+    let state = if entries.len() == header.index_entries as usize {
+        IndexState::Intact
+    } else if entries.is_empty() {
+        IndexState::Missing(Error::TruncatedIndex {
+            offset: header.index_position as u64,
+            entries: header.index_entries,
+            file_size,
+        })
+    } else {
+        IndexState::Partial {
+            read: entries.len(),
+            expected: header.index_entries,
+        }
+    };
+    (entries, state)
+}
+
+/// Something that looks like the start of a resource.
+struct Found {
+    range: Range<usize>,
+    /// Set for compressed data, which is only trusted if it decompresses.
+    decompressed: Option<Vec<u8>>,
+}
+
+/// Search `range` of `data` for compressed resources, PNGs and DDS images.
+///
+/// Compressed data and PNGs say where they end. DDS images are assumed to run until whatever is found next.
+fn scan(data: &[u8], range: Range<usize>, package: &mut DBPF<'static, ()>, report: &mut SalvageReport) {
+    let area = &data[range.clone()];
+    let mut candidates: Vec<usize> = memmem::find_iter(area, PNG_MAGIC)
+        .chain(memmem::find_iter(area, DDS_MAGIC))
+        .chain(memchr::memchr_iter(0xFB, area).filter_map(|i| i.checked_sub(1)))
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    let mut found: Vec<Found> = Vec::new();
+    let mut pos = 0;
+    for start in candidates {
+        if start < pos {
+            continue;
+        }
+        let candidate = &area[start..];
+        let hit = if candidate.starts_with(PNG_MAGIC) {
+            memmem::find(candidate, b"IEND").map(|end| Found {
+                // the IEND chunk's type and CRC, unless the file stops before them
+                range: start..(start + end + 8).min(area.len()),
+                decompressed: None,
+            })
+        } else if candidate.starts_with(DDS_MAGIC) {
+            Some(Found {
+                range: start..area.len(),
+                decompressed: None,
+            })
+        } else {
+            compression::stream_len(candidate).and_then(|len| {
+                let size = compression::header(candidate)?.size;
+                let decompressed = compression::decompress(&candidate[..len]).ok()?;
+                (size != 0 && decompressed.len() == size).then(|| Found {
+                    range: start..start + len,
+                    decompressed: Some(decompressed),
+                })
+            })
+        };
+        if let Some(hit) = hit {
+            // a DDS runs until the next thing found
+            if let Some(prev) = found.last_mut() {
+                if prev.decompressed.is_none() && prev.range.end > hit.range.start {
+                    prev.range.end = hit.range.start;
+                }
+            }
+            if hit.range.end < area.len() {
+                pos = hit.range.end;
+            }
+            found.push(hit);
+        }
+    }
+
+    for hit in found {
+        let offset = (range.start + hit.range.start) as u64;
+        let compressed = hit.decompressed.is_some();
+        let decompressed = hit.decompressed.unwrap_or_else(|| area[hit.range].to_vec());
+        let resource_type = guess_type(&decompressed);
+        let tgi = TGI::new(resource_type, 0, offset);
+        package.entries.push(DBPFIndexEntry {
+            resource_type,
+            resource_group: 0,
+            instance: offset,
+            unk1: false,
+            unk2: 1,
+            chunk: ChunkHandle::Dirty {
+                decompressed,
+                should_compress: compressed,
+            },
+        });
+        report.recovered.push(Recovered {
+            tgi,
+            offset,
+            recovery: Recovery::Scanned,
+        });
+    }
+}
+
+/// Resource type for data found by [`scan`], from its contents. 0 if it's not recognisable.
+fn guess_type(data: &[u8]) -> u32 {
+    if data.starts_with(PNG_MAGIC) {
+        return ResourceType::UIImagePNG as u32;
+    }
+    if data.starts_with(DDS_MAGIC) {
+        return ResourceType::IMG as u32;
+    }
+    match rcol_first_chunk(data).and_then(ChunkTag::from_magic) {
+        Some(ChunkTag::GEOM) => ResourceType::GEOM as u32,
+        Some(ChunkTag::MODL) => ResourceType::MODL as u32,
+        Some(ChunkTag::MLOD) => ResourceType::MLOD as u32,
+        Some(ChunkTag::VPXY) => ResourceType::VPXY as u32,
+        _ => 0,
+    }
+}
+
+/// The data of the first chunk, if `data` looks like an RCOL. See [`super::filetypes::rcol`] for the layout.
+fn rcol_first_chunk(data: &[u8]) -> Option<&[u8]> {
+    let word = |i: usize| Some(u32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?) as usize);
+    let (external, internal) = (word(12)?, word(16)?);
+    let index = 20usize.checked_add(internal.checked_add(external)?.checked_mul(16)?)?;
+    data.get(word(index)?..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinWrite;
+
+    fn entry(instance: u64, data: &[u8], should_compress: bool) -> DBPFIndexEntry<'static> {
+        DBPFIndexEntry {
+            resource_type: 0x0333406C,
+            resource_group: 0,
+            instance,
+            unk1: false,
+            unk2: 1,
+            chunk: ChunkHandle::Dirty {
+                decompressed: data.to_vec(),
+                should_compress,
+            },
+        }
+    }
+
+    fn package(entries: Vec<DBPFIndexEntry<'static>>) -> Vec<u8> {
+        let mut package = DBPF::new();
+        package.entries = entries;
+        let mut out = io::Cursor::new(Vec::new());
+        package.write_le_args(&mut out, ()).unwrap();
+        out.into_inner()
+    }
+
+    fn data(package: &DBPF<'static, ()>, i: usize) -> &[u8] {
+        match &package.entries[i].chunk {
+            ChunkHandle::Dirty { decompressed, .. } => decompressed,
+            other => panic!("expected a dirty chunk, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keeps_intact_packages() {
+        let bytes = package(vec![entry(1, b"first", false), entry(2, b"second", false)]);
+        let (package, report) = salvage(&bytes);
+        assert!(matches!(report.index, IndexState::Intact));
+        assert!(report.dropped.is_empty());
+        assert_eq!(report.recovered.iter().map(|r| r.recovery).collect::<Vec<_>>(), [Recovery::Intact; 2]);
+        assert_eq!(data(&package, 1), b"second");
+    }
+
+    #[test]
+    fn cuts_off_truncated_data() {
+        let bytes = package(vec![entry(1, b"first", false), entry(2, b"second", false)]);
+        let (package, report) = salvage(&bytes[..bytes.len() - 2]);
+        assert!(matches!(report.index, IndexState::Intact));
+        assert_eq!(report.recovered[0].recovery, Recovery::Intact);
+        assert_eq!(report.recovered[1].recovery, Recovery::Truncated { expected: 6 });
+        assert_eq!(data(&package, 1), b"seco");
+    }
+
+    #[test]
+    fn drops_compressed_data_that_no_longer_decompresses() {
+        let text = b"abcabcabcabcabcabcabcabcabcabcabcabc".repeat(8);
+        let bytes = package(vec![entry(1, b"first", false), entry(2, &text, true)]);
+        let (_, report) = salvage(&bytes[..bytes.len() - 4]);
+        assert_eq!(report.recovered.len(), 1);
+        assert!(matches!(report.dropped[..], [Error::Decompression { .. }]));
+    }
+
+    #[test]
+    fn reads_what_is_left_of_the_index() {
+        let bytes = package(vec![entry(1, b"first", false), entry(2, b"second", false)]);
+        // type, group, instance_hi and compressed_unk2 are common, so the mask and those take 20
+        // bytes and each entry 16. cut the second entry in half
+        let (_, report) = salvage(&bytes[..96 + 20 + 16 + 8]);
+        assert!(matches!(report.index, IndexState::Partial { read: 1, expected: 2 }));
+        assert!(matches!(report.dropped[..], [Error::ChunkOutOfBounds { .. }]));
+    }
+
+    #[test]
+    fn bounds_all_common_entry_counts() {
+        let mut bytes = package(vec![entry(1, b"only", false)]);
+        // one entry, so every field is common and entries take no space
+        bytes[36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (package, report) = salvage(&bytes);
+        assert!(matches!(report.index, IndexState::Partial { read: 1, expected: u32::MAX }));
+        assert_eq!(data(&package, 0), b"only");
+    }
+
+    #[test]
+    fn scans_without_an_index() {
+        let png = [PNG_MAGIC, b"\0\0\0\0IEND\xAE\x42\x60\x82"].concat();
+        let mut bytes = package(vec![entry(1, &png, false)]);
+        bytes[64..68].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (package, report) = salvage(&bytes);
+        assert!(matches!(report.index, IndexState::Missing(_)));
+        let i = report.recovered.iter().position(|r| r.tgi.resource_type == ResourceType::UIImagePNG as u32).unwrap();
+        assert_eq!(report.recovered[i].recovery, Recovery::Scanned);
+        assert_eq!(data(&package, i), png);
+    }
+
+    #[test]
+    fn scans_png_cut_off_after_iend() {
+        let png = [PNG_MAGIC, b"\0\0\0\0IEND\xAE\x42\x60\x82"].concat();
+        let mut bytes = package(vec![entry(1, &png, false)]);
+        bytes[64..68].copy_from_slice(&u32::MAX.to_le_bytes());
+        // two bytes into the CRC after IEND
+        let (package, report) = salvage(&bytes[..bytes.len() - 2]);
+        let i = report.recovered.iter().position(|r| r.tgi.resource_type == ResourceType::UIImagePNG as u32).unwrap();
+        assert_eq!(data(&package, i), &png[..png.len() - 2]);
+    }
+}
@@ -0,0 +1,469 @@
+//! Checks a package against the things [`DBPFReader::parse`](super::DBPFReader::parse) and the tools assume.
+//!
+//! The parser gives up at the first problem, and silently accepts a lot of things the game doesn't
+//! (or that mean the package was built badly), so this reads the index on its own.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::io::{self, Read};
+
+use binrw::BinRead;
+use num_traits::FromPrimitive;
+
+use super::compression;
+use super::filetypes::nmap::NMAP;
+use super::filetypes::tgi::TGI;
+use super::filetypes::{resource_is_png, ResourceType};
+use super::{DBPFHeader, IndexEntry, IndexEntryMask, PartialIndexEntry};
+use crate::error::Error;
+
+const HEADER_SIZE: u64 = 96;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    /// Worth knowing, but nothing is wrong.
+    Info,
+    /// The package loads, but something is off, and may not work the way its author meant.
+    Warning,
+    /// The package is damaged. The game or these tools will fail on (part of) it.
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Stable name for the kind of issue, like `chunk-overlap`, for filtering on.
+    pub code: &'static str,
+    /// The resource the issue is about, if it's about one.
+    pub tgi: Option<TGI>,
+    /// Where in the file the issue is.
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.severity, self.code)?;
+        if let Some(tgi) = self.tgi {
+            write!(f, " {}", tgi)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub file_size: u64,
+    /// Number of entries in the index, if it could be read.
+    pub entries: Option<usize>,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// The most severe issue found, or `None` if there weren't any.
+    pub fn worst(&self) -> Option<Severity> {
+        self.issues.iter().map(|i| i.severity).max()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues.iter().filter(|i| i.severity == severity).count()
+    }
+
+    fn push(&mut self, severity: Severity, code: &'static str, tgi: Option<TGI>, offset: Option<u64>, message: impl Display) {
+        self.issues.push(Issue {
+            severity,
+            code,
+            tgi,
+            offset,
+            message: message.to_string(),
+        });
+    }
+
+    fn push_error(&mut self, code: &'static str, e: Error) {
+        let severity = if e.is_corrupt() { Severity::Error } else { Severity::Warning };
+        let message = e.to_string();
+        // the TGI has its own field
+        let message = match e.tgi() {
+            Some(tgi) => message.strip_prefix(&format!("{}: ", tgi)).unwrap_or(&message).to_string(),
+            None => message,
+        };
+        self.issues.push(Issue {
+            severity,
+            code,
+            tgi: e.tgi(),
+            offset: e.offset(),
+            message,
+        });
+    }
+}
+
+/// What lies in a range of the file, for finding overlaps.
+#[derive(Copy, Clone)]
+enum Region {
+    Header,
+    Index,
+    Chunk(TGI),
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Header => f.write_str("the header"),
+            Region::Index => f.write_str("the index"),
+            Region::Chunk(tgi) => write!(f, "{}", tgi),
+        }
+    }
+}
+
+/// Check the package in `reader`. Problems with the package are reported in the [`Report`];
+/// this only fails if reading the file does.
+pub fn validate<R: io::Read + io::Seek>(reader: &mut R) -> io::Result<Report> {
+    let file_size = reader.seek(io::SeekFrom::End(0))?;
+    reader.seek(io::SeekFrom::Start(0))?;
+    let mut report = Report {
+        file_size,
+        ..Report::default()
+    };
+
+    // header
+    let header: DBPFHeader = match BinRead::read_le(reader).map_err(Error::from) {
+        Ok(header) => header,
+        Err(Error::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => return Err(e),
+        Err(e) => {
+            report.push_error("bad-header", e);
+            return Ok(report);
+        }
+    };
+    if header.major != 2 || header.minor != 0 {
+        let (offset, version) = if header.major != 2 { (4, header.major) } else { (8, header.minor) };
+        report.push_error(
+            "unsupported-version",
+            Error::UnsupportedVersion {
+                offset,
+                tgi: None,
+                version,
+            },
+        );
+        return Ok(report);
+    }
+    if header._index_major != 7 {
+        report.push(
+            Severity::Warning,
+            "index-version",
+            None,
+            Some(32),
+            format_args!("index version is {}, not 7", header._index_major),
+        );
+    }
+    if header.hole_index_count != 0 {
+        report.push(
+            Severity::Info,
+            "hole-index",
+            None,
+            Some(header.hole_index_position as u64),
+            format_args!("package has a hole index with {} entries, which is ignored", header.hole_index_count),
+        );
+    }
+
+    // index
+    let index_start = header.index_position as u64;
+    let index_end = index_start + header.index_size as u64;
+    let truncated = Error::TruncatedIndex {
+        offset: index_start,
+        entries: header.index_entries,
+        file_size,
+    };
+    if index_end > file_size {
+        report.push_error("index-out-of-bounds", truncated);
+        return Ok(report);
+    }
+    if index_start < HEADER_SIZE && header.index_entries != 0 {
+        report.push(
+            Severity::Error,
+            "index-overlap",
+            None,
+            Some(index_start),
+            format_args!("index at 0x{:X} overlaps the header", index_start),
+        );
+        return Ok(report);
+    }
+    reader.seek(io::SeekFrom::Start(index_start))?;
+    let entries = read_entries(reader, &header, &mut report);
+    let entries = match entries {
+        Ok(Some(entries)) => entries,
+        Ok(None) => return Ok(report),
+        Err(e) if e.is_eof() => {
+            report.push_error("index-out-of-bounds", truncated);
+            return Ok(report);
+        }
+        Err(e) => match Error::from(e) {
+            Error::Io(e) => return Err(e),
+            e => {
+                report.push_error("bad-index", e);
+                return Ok(report);
+            }
+        },
+    };
+    report.entries = Some(entries.len());
+
+    // entries
+    let mut regions = vec![(0, HEADER_SIZE, Region::Header), (index_start, index_end, Region::Index)];
+    let mut seen = HashSet::new();
+    let mut instances = HashSet::new();
+    let mut nmaps = Vec::new();
+    for entry in &entries {
+        let tgi = TGI::new(
+            entry.resource_type,
+            entry.resource_group,
+            ((entry.instance_hi as u64) << 32) | entry.instance_lo as u64,
+        );
+        let offset = entry.chunk_offset as u64;
+        let filesize: u64 = u32::from(entry.filesize_unk1.filesize()).into();
+        let memsize = entry.memsize as u64;
+        let compression = entry.compressed_unk2.0;
+        instances.insert(tgi.instance);
+
+        if !seen.insert(tgi) {
+            report.push(
+                Severity::Warning,
+                "duplicate-tgi",
+                Some(tgi),
+                Some(offset),
+                "resource appears more than once in the index; only one of them will be used",
+            );
+        }
+        if ResourceType::from_u32(tgi.resource_type).is_none() && !resource_is_png(tgi.resource_type) {
+            report.push(
+                Severity::Info,
+                "unknown-type",
+                Some(tgi),
+                Some(offset),
+                format_args!("unknown resource type {:08X}", tgi.resource_type),
+            );
+        }
+        if compression != 0 && compression != 0xFFFF {
+            report.push(
+                Severity::Warning,
+                "compression-flag",
+                Some(tgi),
+                Some(offset),
+                format_args!("compression flag is 0x{:04X}, expected 0x0000 or 0xFFFF", compression),
+            );
+        }
+        if filesize == 0 || memsize == 0 {
+            report.push(
+                Severity::Warning,
+                "empty-resource",
+                Some(tgi),
+                Some(offset),
+                "resource has no data",
+            );
+            continue;
+        }
+        if offset + filesize > file_size {
+            report.push_error(
+                "chunk-out-of-bounds",
+                Error::ChunkOutOfBounds {
+                    tgi: Some(tgi),
+                    offset,
+                    size: filesize,
+                    file_size,
+                },
+            );
+            continue;
+        }
+        regions.push((offset, offset + filesize, Region::Chunk(tgi)));
+
+        // sizes
+        let mut data = Vec::new();
+        reader.seek(io::SeekFrom::Start(offset))?;
+        reader.by_ref().take(filesize).read_to_end(&mut data)?;
+        if compression != 0 {
+            match compression::decompress(&data) {
+                Ok(decompressed) => data = decompressed,
+                Err(e) => {
+                    report.push_error(
+                        "decompression",
+                        Error::Decompression {
+                            tgi: Some(tgi),
+                            offset,
+                            message: e,
+                        },
+                    );
+                    continue;
+                }
+            }
+        }
+        if data.len() as u64 != memsize {
+            report.push(
+                if compression != 0 { Severity::Error } else { Severity::Warning },
+                "size-mismatch",
+                Some(tgi),
+                Some(offset),
+                format_args!("resource is {} bytes, but the index says {}", data.len(), memsize),
+            );
+        }
+        if tgi.resource_type == ResourceType::NMAP as u32 {
+            nmaps.push((tgi, offset, data));
+        }
+    }
+
+    // overlaps. identical ranges are shared data, which the game doesn't mind.
+    regions.sort_by_key(|&(start, end, _)| (start, end));
+    let mut furthest: Option<(u64, u64, Region)> = None;
+    for &(start, end, region) in &regions {
+        if let Some((prev_start, prev_end, prev)) = furthest {
+            if start < prev_end {
+                let tgi = match region {
+                    Region::Chunk(tgi) => Some(tgi),
+                    _ => None,
+                };
+                if (start, end) == (prev_start, prev_end) && matches!(prev, Region::Chunk(_)) {
+                    report.push(
+                        Severity::Info,
+                        "shared-data",
+                        tgi,
+                        Some(start),
+                        format_args!("resource data is shared with {}", prev),
+                    );
+                } else {
+                    report.push(
+                        Severity::Error,
+                        "chunk-overlap",
+                        tgi,
+                        Some(start),
+                        format_args!(
+                            "{} (0x{:X}..0x{:X}) overlaps {} (0x{:X}..0x{:X})",
+                            region, start, end, prev, prev_start, prev_end
+                        ),
+                    );
+                }
+            }
+            if end <= prev_end {
+                continue;
+            }
+        }
+        furthest = Some((start, end, region));
+    }
+
+    // names
+    for (tgi, offset, data) in nmaps {
+        let nmap: NMAP = match BinRead::read_le(&mut io::Cursor::new(data)) {
+            Ok(nmap) => nmap,
+            Err(e) => {
+                report.push_error("bad-nmap", Error::from(e).with_tgi(tgi));
+                continue;
+            }
+        };
+        for (instance, name) in nmap.names().filter(|(i, _)| !instances.contains(i)) {
+            report.push(
+                Severity::Warning,
+                "nmap-dangling",
+                Some(tgi),
+                Some(offset),
+                format_args!("name '{}' is for instance {:016X}, which isn't in the package", name, instance),
+            );
+        }
+    }
+
+    Ok(report)
+}
+
+/// Read the index entries, after checking that the header leaves room for them.
+/// `None` if it doesn't, which is reported.
+fn read_entries<R: io::Read + io::Seek>(
+    reader: &mut R,
+    header: &DBPFHeader,
+    report: &mut Report,
+) -> binrw::BinResult<Option<Vec<IndexEntry>>> {
+    let index_start = header.index_position as u64;
+    let mask: IndexEntryMask = BinRead::read_le(reader)?;
+    let common: PartialIndexEntry = BinRead::read_le_args(reader, (mask,))?;
+    // (mask + common_fields + different_fields * num_entries) * 4
+    let needed = (1 + mask.count_present() as u64 + (8 - mask.count_present() as u64) * header.index_entries as u64) * 4;
+    if needed != header.index_size as u64 {
+        report.push(
+            if needed > header.index_size as u64 { Severity::Error } else { Severity::Warning },
+            "index-size",
+            None,
+            Some(index_start),
+            format_args!(
+                "index has {} entries, which take {} bytes, but the header says it's {} bytes",
+                header.index_entries, needed, header.index_size
+            ),
+        );
+        if needed > header.index_size as u64 {
+            return Ok(None);
+        }
+    }
+    // when every field is common, entries take no space, so the size alone doesn't bound the count.
+    // allow at most one per byte of index, like the parser, rather than allocate for billions.
+    if header.index_entries > header.index_size {
+        report.push(
+            Severity::Error,
+            "index-size",
+            None,
+            Some(index_start),
+            format_args!(
+                "index has {} entries, more than its {} bytes can hold",
+                header.index_entries, header.index_size
+            ),
+        );
+        return Ok(None);
+    }
+    BinRead::read_le_args(
+        reader,
+        binrw::VecArgs {
+            count: header.index_entries as usize,
+            inner: (common,),
+        },
+    )
+    .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A package whose index, right after the header, has every field in common.
+    fn all_common(entries: u32) -> Vec<u8> {
+        let mut bytes = b"DBPF".to_vec();
+        for field in [2u32, 0, 0, 0, 0, 0, 0, 7, entries, 0, 36, 0, 0, 0, 3, 96] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.resize(96, 0);
+        bytes.extend(0xFFu32.to_le_bytes());
+        bytes.extend([0; 32]);
+        bytes
+    }
+
+    #[test]
+    fn reads_all_common_index() {
+        let report = validate(&mut io::Cursor::new(all_common(1))).unwrap();
+        assert_eq!(report.entries, Some(1));
+        assert!(!report.issues.iter().any(|i| i.code == "index-size"));
+    }
+
+    #[test]
+    fn rejects_more_entries_than_index_bytes() {
+        let report = validate(&mut io::Cursor::new(all_common(0xFFFF_FFFF))).unwrap();
+        assert_eq!(report.entries, None);
+        assert!(report.issues.iter().any(|i| i.code == "index-size" && i.severity == Severity::Error));
+    }
+}
@@ -0,0 +1,285 @@
+//! Errors from reading and writing packages.
+//!
+//! Most variants mean the package itself is damaged ([`Error::is_corrupt`]), as opposed to a
+//! problem reading the file or a bug. Resource parsers are written with binrw, so their errors
+//! arrive as [`Error::Parse`] unless they raise one of these variants themselves.
+
+use std::fmt::{self, Display};
+use std::io;
+
+use binrw::error::BacktraceFrame;
+
+use crate::dbpf::filetypes::tgi::TGI;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The file isn't a package, or a resource doesn't start with the magic number its format has.
+    BadMagic {
+        offset: u64,
+        tgi: Option<TGI>,
+        found: String,
+    },
+    /// A package (or resource format) version this crate can't read.
+    UnsupportedVersion {
+        offset: u64,
+        tgi: Option<TGI>,
+        version: u32,
+    },
+    /// The index is cut off, or lies past the end of the file.
+    TruncatedIndex {
+        offset: u64,
+        entries: u32,
+        file_size: u64,
+    },
+    /// A resource's data lies (partly) past the end of the file.
+    ChunkOutOfBounds {
+        tgi: Option<TGI>,
+        offset: u64,
+        size: u64,
+        file_size: u64,
+    },
+    /// A compressed resource couldn't be decompressed.
+    Decompression {
+        tgi: Option<TGI>,
+        offset: u64,
+        message: String,
+    },
+    /// A resource (or part of one) isn't the size the index or its header says it is.
+    SizeMismatch {
+        tgi: Option<TGI>,
+        offset: u64,
+        expected: u64,
+        found: u64,
+    },
+    /// A resource's contents are in a variant of its format that this crate doesn't understand.
+    UnknownFormat {
+        tgi: Option<TGI>,
+        offset: u64,
+        message: String,
+    },
+    /// A Sims3Pack's manifest isn't well-formed XML, or is missing something it needs.
+    BadManifest { offset: u64, message: String },
+    /// Something is too large to be written to a package.
+    Overflow {
+        tgi: Option<TGI>,
+        what: &'static str,
+        size: u64,
+    },
+    /// Any other problem while parsing or writing a resource.
+    Parse {
+        tgi: Option<TGI>,
+        source: binrw::Error,
+    },
+    Io(io::Error),
+}
+
+impl Error {
+    /// The resource the error is about, if it's about one.
+    pub fn tgi(&self) -> Option<TGI> {
+        match self {
+            Error::BadMagic { tgi, .. }
+            | Error::UnsupportedVersion { tgi, .. }
+            | Error::ChunkOutOfBounds { tgi, .. }
+            | Error::Decompression { tgi, .. }
+            | Error::SizeMismatch { tgi, .. }
+            | Error::UnknownFormat { tgi, .. }
+            | Error::Overflow { tgi, .. }
+            | Error::Parse { tgi, .. } => *tgi,
+            Error::TruncatedIndex { .. } | Error::BadManifest { .. } | Error::Io(_) => None,
+        }
+    }
+
+    /// Where in the file (or resource, for resource formats) the problem is.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Error::BadMagic { offset, .. }
+            | Error::UnsupportedVersion { offset, .. }
+            | Error::TruncatedIndex { offset, .. }
+            | Error::ChunkOutOfBounds { offset, .. }
+            | Error::Decompression { offset, .. }
+            | Error::SizeMismatch { offset, .. }
+            | Error::UnknownFormat { offset, .. }
+            | Error::BadManifest { offset, .. } => Some(*offset),
+            Error::Overflow { .. } | Error::Io(_) => None,
+            Error::Parse { source, .. } => match source.root_cause() {
+                binrw::Error::BadMagic { pos, .. }
+                | binrw::Error::AssertFail { pos, .. }
+                | binrw::Error::Custom { pos, .. }
+                | binrw::Error::NoVariantMatch { pos }
+                | binrw::Error::EnumErrors { pos, .. } => Some(*pos),
+                _ => None,
+            },
+        }
+    }
+
+    /// Attach the resource this error happened in, unless it already has one.
+    pub fn with_tgi(mut self, key: impl Into<Option<TGI>>) -> Self {
+        match &mut self {
+            Error::BadMagic { tgi, .. }
+            | Error::UnsupportedVersion { tgi, .. }
+            | Error::ChunkOutOfBounds { tgi, .. }
+            | Error::Decompression { tgi, .. }
+            | Error::SizeMismatch { tgi, .. }
+            | Error::UnknownFormat { tgi, .. }
+            | Error::Overflow { tgi, .. }
+            | Error::Parse { tgi, .. } => {
+                if tgi.is_none() {
+                    *tgi = key.into();
+                }
+            }
+            Error::TruncatedIndex { .. } | Error::BadManifest { .. } | Error::Io(_) => {}
+        }
+        self
+    }
+
+    /// Whether the data is at fault, rather than the file system or the caller.
+    ///
+    /// Parse errors count as corrupt data, though they can also mean a format isn't fully understood yet.
+    pub fn is_corrupt(&self) -> bool {
+        match self {
+            Error::Overflow { .. } => false,
+            Error::Io(e) => matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData),
+            Error::Parse { source, .. } => match source.root_cause() {
+                binrw::Error::Io(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(tgi) = self.tgi() {
+            write!(f, "{}: ", tgi)?;
+        }
+        match self {
+            Error::BadMagic { offset, found, .. } => write!(f, "bad magic {} at 0x{:X}", found, offset),
+            Error::UnsupportedVersion { offset, version, .. } => {
+                write!(f, "unsupported version {} at 0x{:X}", version, offset)
+            }
+            Error::TruncatedIndex {
+                offset,
+                entries,
+                file_size,
+            } => write!(
+                f,
+                "index of {} entries at 0x{:X} doesn't fit in the file ({} bytes)",
+                entries, offset, file_size
+            ),
+            Error::ChunkOutOfBounds {
+                offset,
+                size,
+                file_size,
+                ..
+            } => write!(
+                f,
+                "resource data at 0x{:X} ({} bytes) is past the end of the file ({} bytes)",
+                offset, size, file_size
+            ),
+            Error::Decompression { offset, message, .. } => {
+                write!(f, "couldn't decompress resource at 0x{:X}: {}", offset, message)
+            }
+            Error::SizeMismatch {
+                offset,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "data at 0x{:X} is {} bytes, but should be {}",
+                offset, found, expected
+            ),
+            Error::UnknownFormat { offset, message, .. } => write!(f, "unknown format at 0x{:X}: {}", offset, message),
+            Error::BadManifest { offset, message } => write!(f, "bad Sims3Pack manifest at 0x{:X}: {}", offset, message),
+            Error::Overflow { what, size, .. } => write!(f, "{} is too large to write ({})", what, size),
+            Error::Parse { source, .. } => source.fmt(f),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<binrw::Error> for Error {
+    fn from(e: binrw::Error) -> Self {
+        // `with_context(tgi)` leaves the key in the backtrace
+        let tgi = match &e {
+            binrw::Error::Backtrace(backtrace) => {
+                backtrace.frames.iter().find_map(|frame| match frame {
+                    BacktraceFrame::Custom(custom) => custom.downcast_ref::<TGI>().copied(),
+                    _ => None,
+                })
+            }
+            _ => None,
+        };
+        // errors that have a variant of their own lose the rest of the backtrace
+        let has_variant = |e: &binrw::Error| match e {
+            binrw::Error::Custom { err, .. } => err.is::<Error>(),
+            binrw::Error::BadMagic { .. } | binrw::Error::Io(_) => true,
+            _ => false,
+        };
+        let e = match e {
+            binrw::Error::Backtrace(backtrace) if has_variant(&backtrace.error) => *backtrace.error,
+            e => e,
+        };
+        let error = match e {
+            binrw::Error::Custom { pos, err } => match err.downcast::<Error>() {
+                Ok(err) => *err,
+                Err(err) => Error::Parse {
+                    tgi: None,
+                    source: binrw::Error::Custom { pos, err },
+                },
+            },
+            binrw::Error::BadMagic { pos, found } => Error::BadMagic {
+                offset: pos,
+                tgi: None,
+                found: format!("{:?}", found),
+            },
+            binrw::Error::Io(e) => Error::Io(e),
+            e => Error::Parse { tgi: None, source: e },
+        };
+        error.with_tgi(tgi)
+    }
+}
+
+/// So that resource parsers, which return [`binrw::Error`], can raise these errors too.
+impl From<Error> for binrw::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => binrw::Error::Io(e),
+            Error::Parse { tgi: None, source } => source,
+            e => binrw::Error::Custom {
+                pos: e.offset().unwrap_or(0),
+                err: Box::new(e),
+            },
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e if e.is_corrupt() => io::Error::new(io::ErrorKind::InvalidData, e),
+            e => io::Error::other(e),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! FNV hashes, as used by the game (and S3PE) to derive instance ids from names.
+//!
+//! The game hashes the *lowercased* name with FNV-1 (multiply, then xor).
+//! FNV-1a variants are provided as well, since some tools use them.
+
+use std::collections::HashMap;
+
+const FNV32_OFFSET: u32 = 0x811C_9DC5;
+const FNV32_PRIME: u32 = 0x0100_0193;
+const FNV64_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV64_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Set on instance ids by custom content creators to stay clear of EA's ids.
+pub const HIGH_BIT_32: u32 = 0x8000_0000;
+pub const HIGH_BIT_64: u64 = 0x8000_0000_0000_0000;
+
+pub fn fnv1_32_bytes(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(FNV32_OFFSET, |hash, &b| {
+        hash.wrapping_mul(FNV32_PRIME) ^ b as u32
+    })
+}
+
+pub fn fnv1_64_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV64_OFFSET, |hash, &b| {
+        hash.wrapping_mul(FNV64_PRIME) ^ b as u64
+    })
+}
+
+pub fn fnv1a_32_bytes(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(FNV32_OFFSET, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(FNV32_PRIME)
+    })
+}
+
+pub fn fnv1a_64_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV64_OFFSET, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(FNV64_PRIME)
+    })
+}
+
+// The game only ever hashes lowercased names, so these do the same.
+// Lowercasing is ASCII-only, matching S3PE's FNVHash.
+
+pub fn fnv32(name: &str) -> u32 {
+    fnv1_32_bytes(name.to_ascii_lowercase().as_bytes())
+}
+
+pub fn fnv64(name: &str) -> u64 {
+    fnv1_64_bytes(name.to_ascii_lowercase().as_bytes())
+}
+
+pub fn fnv1a32(name: &str) -> u32 {
+    fnv1a_32_bytes(name.to_ascii_lowercase().as_bytes())
+}
+
+pub fn fnv1a64(name: &str) -> u64 {
+    fnv1a_64_bytes(name.to_ascii_lowercase().as_bytes())
+}
+
+/// 24-bit xor-folded FNV-1, as used for some group ids.
+pub fn fnv24(name: &str) -> u32 {
+    fold24(fnv32(name))
+}
+
+pub fn fold24(hash: u32) -> u32 {
+    (hash >> 24) ^ (hash & 0x00FF_FFFF)
+}
+
+/// FNV-1 32 with the high bit set, the usual convention for custom content.
+pub fn fnv32_high(name: &str) -> u32 {
+    fnv32(name) | HIGH_BIT_32
+}
+
+/// FNV-1 64 with the high bit set, the usual convention for custom content.
+pub fn fnv64_high(name: &str) -> u64 {
+    fnv64(name) | HIGH_BIT_64
+}
+
+/// Reverse lookup from hash to the name that produced it.
+///
+/// Every name is registered under each of the hash variants above, so a lookup works regardless
+/// of which one produced the id. 32-bit hashes are zero-extended into the same map.
+#[derive(Default, Debug, Clone)]
+pub struct HashDictionary {
+    names: HashMap<u64, String>,
+}
+
+impl HashDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a name under every hash variant.
+    pub fn insert_name(&mut self, name: &str) {
+        for hash in [
+            fnv32(name) as u64,
+            fnv64(name),
+            fnv1a32(name) as u64,
+            fnv1a64(name),
+            fnv24(name) as u64,
+            fnv32_high(name) as u64,
+            fnv64_high(name),
+        ] {
+            self.names.entry(hash).or_insert_with(|| name.to_owned());
+        }
+    }
+
+    /// Register a label for a hash whose source string isn't known (NMAP, STBL, ...).
+    ///
+    /// Existing entries win, since those came from an actual name.
+    pub fn insert_label(&mut self, hash: u64, label: impl Into<String>) {
+        self.names.entry(hash).or_insert_with(|| label.into());
+    }
+
+    pub fn lookup(&self, hash: u64) -> Option<&str> {
+        self.names.get(&hash).map(String::as_str)
+    }
+
+    pub fn lookup32(&self, hash: u32) -> Option<&str> {
+        self.lookup(hash as u64)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Names from an NMAP are stored as-is against their instance, and also hashed,
+    /// since the name is usually the source string of the instance id.
+    pub fn extend_from_nmap<'a>(&mut self, names: impl IntoIterator<Item = (&'a u64, &'a String)>) {
+        for (&instance, name) in names {
+            self.names.insert(instance, name.clone());
+            self.insert_name(name);
+        }
+    }
+
+    /// STBL keys are hashes of string keys we don't have, so label them with the text instead.
+    pub fn extend_from_stbl<'a>(&mut self, strings: impl IntoIterator<Item = (&'a u64, &'a String)>) {
+        for (&key, text) in strings {
+            self.insert_label(key, text.clone());
+        }
+    }
+}
+
+impl<S: AsRef<str>> Extend<S> for HashDictionary {
+    fn extend<T: IntoIterator<Item = S>>(&mut self, iter: T) {
+        for name in iter {
+            self.insert_name(name.as_ref());
+        }
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for HashDictionary {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        let mut dict = Self::new();
+        dict.extend(iter);
+        dict
+    }
+}
+
+lazy_static! {
+    /// Names that show up often enough in packages that it's worth knowing them up front.
+    pub static ref KNOWN_NAMES: HashDictionary = [
+        // shader parameters
+        "DiffuseMap", "NormalMap", "SpecularMap", "AmbientOcclusionMap", "Multiplier",
+        "Mask", "Overlay", "Shininess", "Transparency", "AlphaMap", "EmissionMap",
+        // bones
+        "ROOT_bind", "b__ROOT__", "b__ROOT_bind__", "b__Pelvis__", "b__Spine0__",
+        "b__Spine1__", "b__Spine2__", "b__Neck__", "b__Head__", "b__Jaw__",
+        "b__L_Clavicle__", "b__L_UpperArm__", "b__L_Forearm__", "b__L_Hand__",
+        "b__R_Clavicle__", "b__R_UpperArm__", "b__R_Forearm__", "b__R_Hand__",
+        "b__L_Thigh__", "b__L_Calf__", "b__L_Foot__", "b__L_Toe__",
+        "b__R_Thigh__", "b__R_Calf__", "b__R_Foot__", "b__R_Toe__",
+        "b__L_Breast__", "b__R_Breast__", "b__L_Eye__", "b__R_Eye__", "b__L_Ear__", "b__R_Ear__",
+        // shaders
+        "CASRGBMask", "Phong", "PhongAlpha", "SimSkin", "SimHair", "SimEyes",
+    ]
+    .into_iter()
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv_vectors() {
+        // from the FNV reference test suite
+        assert_eq!(fnv1_32_bytes(b""), FNV32_OFFSET);
+        assert_eq!(fnv1_64_bytes(b""), FNV64_OFFSET);
+        assert_eq!(fnv1_32_bytes(b"a"), 0x050C_5D7E);
+        assert_eq!(fnv1_64_bytes(b"a"), 0xAF63_BD4C_8601_B7BE);
+        assert_eq!(fnv1a_32_bytes(b"a"), 0xE40C_292C);
+        assert_eq!(fnv1a_64_bytes(b"a"), 0xAF63_DC4C_8601_EC8C);
+    }
+
+    #[test]
+    fn game_names() {
+        assert_eq!(fnv32("DiffuseMap"), 0x6CC0_FD85);
+        assert_eq!(fnv32("DIFFUSEMAP"), fnv32("diffusemap"));
+        assert_eq!(fnv64("DiffuseMap"), 0x2622_A589_CB0C_0245);
+        assert_eq!(fnv1a32("DiffuseMap"), 0xFCD0_CF49);
+        assert_eq!(fnv1a64("DiffuseMap"), 0x582F_EC5E_795D_BFC9);
+        assert_eq!(fnv24("DiffuseMap"), 0x00C0_FDE9);
+        assert_eq!(fold24(0x6CC0_FD85), 0x00C0_FDE9);
+        assert_eq!(fnv32_high("DiffuseMap"), 0xECC0_FD85);
+        assert_eq!(fnv64_high("DiffuseMap"), 0xA622_A589_CB0C_0245);
+    }
+
+    #[test]
+    fn dictionary_knows_every_variant() {
+        let dict: HashDictionary = ["DiffuseMap"].into_iter().collect();
+        for hash in [
+            0x6CC0_FD85,
+            0x2622_A589_CB0C_0245,
+            0xFCD0_CF49,
+            0x582F_EC5E_795D_BFC9,
+            0x00C0_FDE9,
+            0xECC0_FD85,
+            0xA622_A589_CB0C_0245,
+        ] {
+            assert_eq!(dict.lookup(hash), Some("DiffuseMap"), "{:X}", hash);
+        }
+    }
+}
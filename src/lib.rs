@@ -3,6 +3,13 @@ extern crate num_derive;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod cache;
+pub mod cli;
 pub mod dbpf;
+pub mod error;
+pub mod hash;
+pub mod mesh;
+pub mod resource_cfg;
+pub mod sims3pack;
 
 pub(crate) mod util;
\ No newline at end of file
@@ -0,0 +1,428 @@
+//! Format-independent meshes, for exchanging geometry with other tools.
+//!
+//! Both CAS meshes ([`Geometry`]) and object meshes ([`Mlod`]) convert into a list of [`Lod`]s,
+//! which the [`obj`] and [`gltf`] modules know how to write. Going the other way,
+//! [`replace_geometry`] puts an imported mesh back into an existing GEOM.
+
+pub mod gltf;
+pub mod obj;
+
+use crate::dbpf::filetypes::geom::{Geometry, SubMesh, VertexUsage, Vertices};
+use crate::dbpf::filetypes::model::{read_chunk, ElementUsage, Ibuf, Mlod, Vbuf, Vrtf};
+use crate::dbpf::filetypes::rcol::{ChunkTag, RCOLChunk, RCOL};
+
+use binrw::BinResult;
+use std::fmt;
+
+/// A triangle list with optional skinning.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<Vec<[f32; 2]>>,
+    /// Per vertex, indices into `bones`.
+    pub joints: Vec<[u8; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    /// Bone name hashes.
+    pub bones: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn is_skinned(&self) -> bool {
+        !self.bones.is_empty()
+            && self.joints.len() == self.positions.len()
+            && self.weights.len() == self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Combine several meshes into one, e.g. all the groups of an imported LOD.
+    ///
+    /// Attributes that not every mesh has are dropped. Joints are one byte, so the meshes can use at
+    /// most 256 different bones between them.
+    pub fn merge(name: impl Into<String>, meshes: &[Mesh]) -> Result<Self, ImportError> {
+        let mut out = Mesh {
+            name: name.into(),
+            ..Default::default()
+        };
+        let all = |f: &dyn Fn(&Mesh) -> bool| meshes.iter().all(f);
+        let has_normals = all(&|m| m.normals.len() == m.positions.len());
+        let uv_sets = meshes.iter().map(|m| m.uvs.iter().take_while(|uv| uv.len() == m.positions.len()).count()).min().unwrap_or(0);
+        let skinned = all(&Mesh::is_skinned);
+
+        for mesh in meshes {
+            let base = out.positions.len() as u32;
+            out.positions.extend_from_slice(&mesh.positions);
+            if has_normals {
+                out.normals.extend_from_slice(&mesh.normals);
+            }
+            out.uvs.resize(uv_sets, Vec::new());
+            for (set, uv) in out.uvs.iter_mut().zip(&mesh.uvs) {
+                set.extend_from_slice(uv);
+            }
+            if skinned {
+                let mut remap = Vec::with_capacity(mesh.bones.len());
+                for bone in &mesh.bones {
+                    let i = match out.bones.iter().position(|o| o == bone) {
+                        Some(i) => i,
+                        None => {
+                            out.bones.push(*bone);
+                            out.bones.len() - 1
+                        }
+                    };
+                    remap.push(u8::try_from(i).map_err(|_| ImportError::TooManyBones(i + 1))?);
+                }
+                out.joints.extend(mesh.joints.iter().map(|j| j.map(|i| remap.get(i as usize).copied().unwrap_or(0))));
+                out.weights.extend_from_slice(&mesh.weights);
+            }
+            out.indices.extend(mesh.indices.iter().map(|i| base + i));
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lod {
+    pub name: String,
+    pub meshes: Vec<Mesh>,
+}
+
+impl Mesh {
+    pub fn from_geometry(name: impl Into<String>, geom: &Geometry) -> Self {
+        let v = &geom.vertices;
+        Mesh {
+            name: name.into(),
+            positions: v.positions.clone(),
+            normals: v.normals.clone(),
+            uvs: v.uvs.clone(),
+            joints: v.bone_assignments.clone(),
+            weights: v.weights.clone(),
+            bones: geom.bone_hashes.clone(),
+            indices: geom.sub_meshes.iter().flat_map(|m| m.indices.iter().copied()).collect(),
+        }
+    }
+
+    /// Decode every mesh of the first MLOD chunk in `rcol`.
+    ///
+    /// UVs stored as shorts are normalized, but the material's UV scale isn't applied.
+    pub fn from_mlod(rcol: &RCOL) -> BinResult<Vec<Self>> {
+        let chunk = rcol.find(ChunkTag::MLOD).ok_or_else(|| binrw::Error::AssertFail {
+            pos: 0,
+            message: "RCOL has no MLOD chunk".to_string(),
+        })?;
+        Self::from_mlod_chunk(rcol, chunk)
+    }
+
+    /// Decode every mesh of `chunk`, an MLOD in `rcol`. Objects keep each LOD in its own MLOD,
+    /// so this is the one to use with the chunk a MODL LOD points at.
+    pub fn from_mlod_chunk(rcol: &RCOL, chunk: &RCOLChunk) -> BinResult<Vec<Self>> {
+        let mlod: Mlod = binrw::BinRead::read_le(&mut chunk.reader())?;
+
+        mlod.meshes
+            .iter()
+            .filter(|m| m.primitive_type() == 3) // triangle lists only
+            .map(|m| {
+                let vrtf: Vrtf = read_chunk(rcol, m.vertex_format)?;
+                let vbuf: Vbuf = read_chunk(rcol, m.vertex_buffer)?;
+                let ibuf: Ibuf = read_chunk(rcol, m.index_buffer)?;
+
+                let offset = m.stream_offset as usize + m.start_vertex as usize * vrtf.stride as usize;
+                let vertices = vbuf.vertices(&vrtf, offset, m.vertex_count as usize);
+                let mut mesh = Mesh {
+                    name: crate::hash::KNOWN_NAMES
+                        .lookup32(m.name)
+                        .map(str::to_owned)
+                        .unwrap_or_else(|| format!("{:08X}", m.name)),
+                    bones: m.joints.clone(),
+                    ..Default::default()
+                };
+
+                let uv_sets = vrtf
+                    .elements
+                    .iter()
+                    .filter(|e| e.usage() == Some(ElementUsage::UV))
+                    .count();
+                mesh.uvs = vec![Vec::with_capacity(vertices.len()); uv_sets];
+                for vertex in &vertices {
+                    let mut uv = 0;
+                    for (element, value) in vrtf.elements.iter().zip(vertex) {
+                        let [x, y, z, w] = *value;
+                        match element.usage() {
+                            Some(ElementUsage::Position) => mesh.positions.push([x, y, z]),
+                            Some(ElementUsage::Normal) => mesh.normals.push([x, y, z]),
+                            Some(ElementUsage::UV) => {
+                                mesh.uvs[uv].push([x, y]);
+                                uv += 1;
+                            }
+                            Some(ElementUsage::BlendIndex) => {
+                                mesh.joints.push([x as u8, y as u8, z as u8, w as u8])
+                            }
+                            Some(ElementUsage::BlendWeight) => mesh.weights.push([x, y, z, w]),
+                            _ => {}
+                        }
+                    }
+                }
+
+                let count = m.primitive_count as usize * 3;
+                mesh.indices = ibuf
+                    .indices()
+                    .into_iter()
+                    .skip(m.start_index as usize)
+                    .take(count)
+                    .map(|i| i.saturating_sub(m.min_vertex_index))
+                    .collect();
+                Ok(mesh)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// The index count isn't a multiple of three.
+    NotTriangles,
+    IndexOutOfRange(u32),
+    TooManyVertices { count: usize, max: usize },
+    /// The GEOM has more UV sets than the mesh.
+    MissingUVs { needed: usize, found: usize },
+    /// The GEOM is skinned but the mesh isn't.
+    NotSkinned,
+    /// The mesh uses a bone that isn't in the GEOM's bone list.
+    UnknownBone(u32),
+    /// More bones than one-byte joint indices can refer to.
+    TooManyBones(usize),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::NotTriangles => write!(f, "mesh is not a triangle list"),
+            ImportError::IndexOutOfRange(i) => write!(f, "index {} is out of range", i),
+            ImportError::TooManyVertices { count, max } => {
+                write!(f, "mesh has {} vertices, but at most {} fit the index size", count, max)
+            }
+            ImportError::MissingUVs { needed, found } => {
+                write!(f, "GEOM needs {} UV sets, but the mesh has {}", needed, found)
+            }
+            ImportError::NotSkinned => write!(f, "GEOM is skinned, but the mesh has no joints and weights"),
+            ImportError::UnknownBone(hash) => write!(f, "bone {:08X} isn't in the GEOM's bone list", hash),
+            ImportError::TooManyBones(count) => write!(f, "mesh uses {} bones, but at most 256 fit", count),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: [f32; 3], fallback: [f32; 3]) -> [f32; 3] {
+    let len = dot(v, v).sqrt();
+    if len > 1e-12 {
+        v.map(|x| x / len)
+    } else {
+        fallback
+    }
+}
+
+/// Area-weighted smooth normals, for meshes that don't have their own.
+fn compute_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![[0.0; 3]; positions.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| tri[i] as usize);
+        let n = cross(sub(positions[b], positions[a]), sub(positions[c], positions[a]));
+        for v in [a, b, c] {
+            for k in 0..3 {
+                normals[v][k] += n[k];
+            }
+        }
+    }
+    normals.into_iter().map(|n| normalize(n, [0.0, 1.0, 0.0])).collect()
+}
+
+/// Per-vertex tangents along the U direction of `uvs`, orthogonalized against the normals.
+fn compute_tangents(positions: &[[f32; 3]], normals: &[[f32; 3]], uvs: &[[f32; 2]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut tangents = vec![[0.0; 3]; positions.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| tri[i] as usize);
+        let (e1, e2) = (sub(positions[b], positions[a]), sub(positions[c], positions[a]));
+        let (du1, dv1) = (uvs[b][0] - uvs[a][0], uvs[b][1] - uvs[a][1]);
+        let (du2, dv2) = (uvs[c][0] - uvs[a][0], uvs[c][1] - uvs[a][1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let t = [0, 1, 2].map(|k| (e1[k] * dv2 - e2[k] * dv1) / det);
+        for v in [a, b, c] {
+            for k in 0..3 {
+                tangents[v][k] += t[k];
+            }
+        }
+    }
+    tangents
+        .into_iter()
+        .zip(normals)
+        .map(|(t, &n)| {
+            let t = sub(t, n.map(|x| x * dot(n, t)));
+            // any direction perpendicular to the normal will do for degenerate UVs
+            let fallback = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+            normalize(t, normalize(cross(n, fallback), [1.0, 0.0, 0.0]))
+        })
+        .collect()
+}
+
+/// Replace the vertices and faces of `geom` with `mesh`.
+///
+/// The GEOM's vertex format, bone list, material and TGI table are kept, so the result still works
+/// with the rest of the CAS part. Tangents are recomputed, and normals are computed if the mesh has none.
+/// Colors, vertex IDs and unknown elements are kept if the vertex count didn't change, and otherwise
+/// filled with the first original value.
+pub fn replace_geometry(geom: &mut Geometry, mesh: &Mesh) -> Result<(), ImportError> {
+    let count = mesh.positions.len();
+    if mesh.indices.len() % 3 != 0 {
+        return Err(ImportError::NotTriangles);
+    }
+    if let Some(&i) = mesh.indices.iter().find(|&&i| i as usize >= count) {
+        return Err(ImportError::IndexOutOfRange(i));
+    }
+    let bytes_per_index = geom.sub_meshes.first().map_or(2, |m| m.bytes_per_index);
+    let max = match bytes_per_index {
+        1 => 0x100,
+        2 => 0x10000,
+        _ => u32::MAX as usize,
+    };
+    if count > max {
+        return Err(ImportError::TooManyVertices { count, max });
+    }
+
+    let has = |usage| geom.vertex_formats.iter().any(|f| f.usage() == Some(usage));
+    let uv_sets = geom.vertices.uvs.len();
+    let found = mesh.uvs.iter().take_while(|uv| uv.len() == count).count();
+    if found < uv_sets || (found == 0 && has(VertexUsage::TangentNormal)) {
+        return Err(ImportError::MissingUVs {
+            needed: uv_sets.max(1),
+            found,
+        });
+    }
+
+    let (mut bone_assignments, mut weights) = (Vec::new(), Vec::new());
+    if has(VertexUsage::BoneAssignment) || has(VertexUsage::Weights) {
+        if !mesh.is_skinned() {
+            return Err(ImportError::NotSkinned);
+        }
+        let remap = mesh
+            .bones
+            .iter()
+            .map(|&hash| match geom.bone_hashes.iter().position(|&b| b == hash) {
+                Some(i) if i < 0x100 => Ok(i as u8),
+                _ => Err(hash),
+            })
+            .collect::<Vec<_>>();
+        for (joints, w) in mesh.joints.iter().zip(&mesh.weights) {
+            let mut out = [0; 4];
+            for k in 0..4 {
+                // unused influences may point anywhere
+                if w[k] <= 0.0 {
+                    continue;
+                }
+                out[k] = match remap.get(joints[k] as usize) {
+                    Some(Ok(i)) => *i,
+                    Some(Err(hash)) => return Err(ImportError::UnknownBone(*hash)),
+                    None => return Err(ImportError::IndexOutOfRange(joints[k] as u32)),
+                };
+            }
+            let sum: f32 = w.iter().map(|x| x.max(0.0)).sum();
+            weights.push(if sum > 0.0 { w.map(|x| x.max(0.0) / sum) } else { [1.0, 0.0, 0.0, 0.0] });
+            bone_assignments.push(out);
+        }
+    }
+
+    let normals = if mesh.normals.len() == count {
+        mesh.normals.iter().map(|&n| normalize(n, [0.0, 1.0, 0.0])).collect()
+    } else {
+        compute_normals(&mesh.positions, &mesh.indices)
+    };
+    let tangents = if has(VertexUsage::TangentNormal) {
+        compute_tangents(&mesh.positions, &normals, &mesh.uvs[0], &mesh.indices)
+    } else {
+        Vec::new()
+    };
+
+    fn keep<T: Clone + Default>(old: &[T], count: usize) -> Vec<T> {
+        if old.len() == count {
+            old.to_vec()
+        } else {
+            vec![old.first().cloned().unwrap_or_default(); count]
+        }
+    }
+    let old = &geom.vertices;
+    geom.vertices = Vertices {
+        positions: mesh.positions.clone(),
+        normals,
+        uvs: mesh.uvs[..uv_sets].to_vec(),
+        bone_assignments,
+        weights,
+        tangents,
+        colors: keep(&old.colors, count),
+        vertex_ids: if old.vertex_ids.len() == count {
+            old.vertex_ids.clone()
+        } else {
+            (0..count as u32).collect()
+        },
+        raw: old.raw.iter().map(|set| keep(set, count)).collect(),
+    };
+    geom.vertex_count = count as u32;
+    geom.sub_meshes = vec![SubMesh {
+        bytes_per_index,
+        indices: mesh.indices.clone(),
+    }];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skinned(bones: std::ops::Range<u32>) -> Mesh {
+        Mesh {
+            positions: vec![[0.0; 3]],
+            joints: vec![[0; 4]],
+            weights: vec![[1.0, 0.0, 0.0, 0.0]],
+            bones: bones.collect(),
+            indices: vec![0, 0, 0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_remaps_bones() {
+        let mut second = skinned(10..12);
+        second.joints = vec![[1, 0, 0, 0]];
+        let merged = Mesh::merge("merged", &[skinned(10..11), second]).unwrap();
+        assert_eq!(merged.bones, [10, 11]);
+        assert_eq!(merged.joints, [[0; 4], [1, 0, 0, 0]]);
+        assert_eq!(merged.indices, [0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn merge_rejects_more_than_256_bones() {
+        assert!(Mesh::merge("merged", &[skinned(0..200), skinned(200..256)]).is_ok());
+        assert_eq!(
+            Mesh::merge("merged", &[skinned(0..200), skinned(200..257)]),
+            Err(ImportError::TooManyBones(257))
+        );
+    }
+}
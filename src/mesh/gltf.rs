@@ -0,0 +1,571 @@
+//! Binary glTF 2.0 (`.glb`) import and export.
+//!
+//! Every LOD becomes a node with one primitive per mesh. LODs whose meshes are all skinned share a
+//! single skin, whose joints are nodes named after the bones (or their hash, if the name isn't known).
+//! The rig resource isn't parsed, so joints are arranged by [`RIG_PARENTS`], the hierarchy of the
+//! standard rig, and have no bind pose. Bones that aren't in it hang off the skeleton's root node.
+
+use super::{Lod, Mesh};
+use crate::hash::HashDictionary;
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// `(bone, parent)` for the bones of the standard Sims 3 rig.
+pub const RIG_PARENTS: [(&str, &str); 30] = [
+    ("b__ROOT_bind__", "b__ROOT__"),
+    ("b__Pelvis__", "b__ROOT_bind__"),
+    ("b__Spine0__", "b__Pelvis__"),
+    ("b__Spine1__", "b__Spine0__"),
+    ("b__Spine2__", "b__Spine1__"),
+    ("b__Neck__", "b__Spine2__"),
+    ("b__Head__", "b__Neck__"),
+    ("b__L_Clavicle__", "b__Spine2__"),
+    ("b__L_UpperArm__", "b__L_Clavicle__"),
+    ("b__L_Forearm__", "b__L_UpperArm__"),
+    ("b__L_Hand__", "b__L_Forearm__"),
+    ("b__R_Clavicle__", "b__Spine2__"),
+    ("b__R_UpperArm__", "b__R_Clavicle__"),
+    ("b__R_Forearm__", "b__R_UpperArm__"),
+    ("b__R_Hand__", "b__R_Forearm__"),
+    ("b__L_Thigh__", "b__Pelvis__"),
+    ("b__L_Calf__", "b__L_Thigh__"),
+    ("b__L_Foot__", "b__L_Calf__"),
+    ("b__L_Toe__", "b__L_Foot__"),
+    ("b__R_Thigh__", "b__Pelvis__"),
+    ("b__R_Calf__", "b__R_Thigh__"),
+    ("b__R_Foot__", "b__R_Calf__"),
+    ("b__R_Toe__", "b__R_Foot__"),
+    ("b__L_Breast__", "b__Spine2__"),
+    ("b__R_Breast__", "b__Spine2__"),
+    ("b__Jaw__", "b__Head__"),
+    ("b__L_Eye__", "b__Head__"),
+    ("b__R_Eye__", "b__Head__"),
+    ("b__L_Ear__", "b__Head__"),
+    ("b__R_Ear__", "b__Head__"),
+];
+
+/// The parent of a bone in [`RIG_PARENTS`], by hash.
+fn rig_parent(bone: u32) -> Option<u32> {
+    RIG_PARENTS
+        .iter()
+        .find(|(name, _)| crate::hash::fnv32(name) == bone)
+        .map(|(_, parent)| crate::hash::fnv32(parent))
+}
+
+#[derive(Default)]
+struct Builder {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Builder {
+    fn push(&mut self, data: &[u8], target: Option<u32>, accessor: Value) -> usize {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.bin.extend_from_slice(data);
+        self.views.push(view);
+
+        let mut accessor = accessor;
+        accessor["bufferView"] = (self.views.len() - 1).into();
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn floats<const N: usize>(&mut self, data: &[[f32; N]], ty: &str, bounds: bool) -> usize {
+        let bytes: Vec<u8> = data.iter().flatten().flat_map(|f| f.to_le_bytes()).collect();
+        let mut accessor = json!({
+            "componentType": FLOAT,
+            "count": data.len(),
+            "type": ty,
+        });
+        if bounds {
+            // POSITION accessors are required to have bounds
+            let min: Vec<f32> = (0..N).map(|i| data.iter().map(|v| v[i]).fold(f32::INFINITY, f32::min)).collect();
+            let max: Vec<f32> = (0..N).map(|i| data.iter().map(|v| v[i]).fold(f32::NEG_INFINITY, f32::max)).collect();
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.push(&bytes, Some(ARRAY_BUFFER), accessor)
+    }
+}
+
+fn normalized(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len > 0.0 {
+        v.map(|x| x / len)
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+fn primitive(b: &mut Builder, mesh: &Mesh, joint_map: &BTreeMap<u32, usize>, skinned: bool) -> Value {
+    let count = mesh.positions.len();
+    let mut attributes = json!({ "POSITION": b.floats(&mesh.positions, "VEC3", true) });
+    if mesh.normals.len() == count {
+        let normals: Vec<_> = mesh.normals.iter().copied().map(normalized).collect();
+        attributes["NORMAL"] = b.floats(&normals, "VEC3", false).into();
+    }
+    for (i, uv) in mesh.uvs.iter().enumerate().filter(|(_, uv)| uv.len() == count) {
+        attributes[format!("TEXCOORD_{}", i)] = b.floats(uv, "VEC2", false).into();
+    }
+
+    if skinned {
+        // remap per-mesh bone indices to indices into the shared skin
+        let joints: Vec<u8> = mesh
+            .joints
+            .iter()
+            .flatten()
+            .flat_map(|&j| {
+                let global = mesh.bones.get(j as usize).and_then(|h| joint_map.get(h)).copied().unwrap_or(0);
+                (global as u16).to_le_bytes()
+            })
+            .collect();
+        attributes["JOINTS_0"] = b
+            .push(
+                &joints,
+                Some(ARRAY_BUFFER),
+                json!({ "componentType": UNSIGNED_SHORT, "count": count, "type": "VEC4" }),
+            )
+            .into();
+        let weights: Vec<[f32; 4]> = mesh
+            .weights
+            .iter()
+            .map(|w| {
+                let sum: f32 = w.iter().sum();
+                if sum > 0.0 {
+                    w.map(|x| x / sum)
+                } else {
+                    [1.0, 0.0, 0.0, 0.0]
+                }
+            })
+            .collect();
+        attributes["WEIGHTS_0"] = b.floats(&weights, "VEC4", false).into();
+    }
+
+    let indices: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+    let indices = b.push(
+        &indices,
+        Some(ELEMENT_ARRAY_BUFFER),
+        json!({ "componentType": UNSIGNED_INT, "count": mesh.indices.len(), "type": "SCALAR" }),
+    );
+    json!({ "attributes": attributes, "indices": indices, "mode": 4 })
+}
+
+/// Build the glTF JSON document and binary buffer.
+pub fn build(lods: &[Lod], names: &HashDictionary) -> (Value, Vec<u8>) {
+    let mut b = Builder::default();
+
+    // a skinned node needs joints and weights on every primitive, so only skin LODs that have them
+    let skinned: Vec<bool> = lods
+        .iter()
+        .map(|l| {
+            let mut meshes = l.meshes.iter().filter(|m| !m.positions.is_empty()).peekable();
+            meshes.peek().is_some() && meshes.all(Mesh::is_skinned)
+        })
+        .collect();
+
+    // joint hash -> index into the skin's joints
+    let mut joint_map = BTreeMap::new();
+    for mesh in lods.iter().zip(&skinned).filter(|(_, &s)| s).flat_map(|(l, _)| &l.meshes) {
+        for &bone in &mesh.bones {
+            let next = joint_map.len();
+            joint_map.entry(bone).or_insert(next);
+        }
+    }
+
+    let mut nodes = Vec::new();
+    let mut meshes = Vec::new();
+    for (lod, &skinned) in lods.iter().zip(&skinned) {
+        let primitives: Vec<_> = lod
+            .meshes
+            .iter()
+            .filter(|m| !m.positions.is_empty())
+            .map(|m| primitive(&mut b, m, &joint_map, skinned))
+            .collect();
+        if primitives.is_empty() {
+            continue;
+        }
+        meshes.push(json!({ "name": lod.name, "primitives": primitives }));
+        let mut node = json!({ "name": lod.name, "mesh": meshes.len() - 1 });
+        if skinned {
+            node["skin"] = 0.into();
+        }
+        nodes.push(node);
+    }
+    let mut scene_nodes: Vec<usize> = (0..nodes.len()).collect();
+
+    let mut skins = Vec::new();
+    if !joint_map.is_empty() {
+        let mut joints: Vec<(u32, usize)> = joint_map.iter().map(|(&hash, &i)| (hash, i)).collect();
+        joints.sort_by_key(|&(_, i)| i);
+        let first_joint = nodes.len();
+        // each joint goes under its closest ancestor that is also a joint
+        let mut children = vec![Vec::new(); joints.len()];
+        let mut top = Vec::new();
+        for &(hash, i) in &joints {
+            let mut parent = rig_parent(hash);
+            while let Some(p) = parent.filter(|p| !joint_map.contains_key(p)) {
+                parent = rig_parent(p);
+            }
+            match parent.and_then(|p| joint_map.get(&p)) {
+                Some(&p) => children[p].push(first_joint + i),
+                None => top.push(first_joint + i),
+            }
+        }
+        for ((hash, _), children) in joints.iter().zip(children) {
+            let name = names
+                .lookup32(*hash)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("{:08X}", hash));
+            let mut node = json!({ "name": name });
+            if !children.is_empty() {
+                node["children"] = children.into();
+            }
+            nodes.push(node);
+        }
+        let joint_nodes: Vec<usize> = (first_joint..nodes.len()).collect();
+        nodes.push(json!({ "name": "skeleton", "children": top }));
+        scene_nodes.push(nodes.len() - 1);
+        skins.push(json!({ "joints": joint_nodes, "skeleton": nodes.len() - 1 }));
+    }
+
+    let mut doc = json!({
+        "asset": { "version": "2.0", "generator": "sims3_rs" },
+        "scene": 0,
+        "scenes": [{ "nodes": scene_nodes }],
+        "nodes": nodes,
+        "meshes": meshes,
+    });
+    // an empty buffer isn't allowed
+    if !b.bin.is_empty() {
+        doc["buffers"] = json!([{ "byteLength": b.bin.len() }]);
+        doc["bufferViews"] = b.views.into();
+        doc["accessors"] = b.accessors.into();
+    }
+    if !skins.is_empty() {
+        doc["skins"] = skins.into();
+    }
+    (doc, b.bin)
+}
+
+pub fn write_glb<W: Write>(writer: &mut W, lods: &[Lod], names: &HashDictionary) -> io::Result<()> {
+    let (doc, mut bin) = build(lods, names);
+    let mut json = serde_json::to_vec(&doc)?;
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let too_large = || io::Error::other("glTF buffer too large");
+    let bin_chunk_len = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let total: u32 = (12 + 8 + json.len() + bin_chunk_len).try_into().map_err(|_| too_large())?;
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&total.to_le_bytes())?;
+
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+
+    if !bin.is_empty() {
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&bin)?;
+    }
+    Ok(())
+}
+
+const BYTE: u32 = 5120;
+const UNSIGNED_BYTE: u32 = 5121;
+const SHORT: u32 = 5122;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Read an accessor as up to four floats per element, applying `normalized` if it's set.
+fn read_accessor(doc: &Value, bin: &[u8], index: &Value) -> io::Result<Vec<[f32; 4]>> {
+    let accessor = index
+        .as_u64()
+        .and_then(|i| doc["accessors"].get(i as usize))
+        .ok_or_else(|| invalid("bad accessor index"))?;
+    let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+    let components = match accessor["type"].as_str() {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") => 4,
+        other => return Err(invalid(format!("unsupported accessor type {:?}", other))),
+    };
+    let component_type = accessor["componentType"].as_u64().unwrap_or(0) as u32;
+    let size = match component_type {
+        BYTE | UNSIGNED_BYTE => 1,
+        SHORT | UNSIGNED_SHORT => 2,
+        UNSIGNED_INT | FLOAT => 4,
+        other => return Err(invalid(format!("unsupported component type {}", other))),
+    };
+    let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+    // sparse accessors and accessors without a view are all zeros, which is useless to us
+    let view = accessor["bufferView"]
+        .as_u64()
+        .and_then(|i| doc["bufferViews"].get(i as usize))
+        .ok_or_else(|| invalid("accessor has no buffer view"))?;
+    if view["buffer"].as_u64().unwrap_or(0) != 0 {
+        return Err(invalid("only the GLB buffer is supported"));
+    }
+    let start = view["byteOffset"].as_u64().unwrap_or(0) as usize + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let stride = view["byteStride"].as_u64().map_or(size * components, |s| s as usize);
+    // `count` comes from the file, so check it against the buffer before allocating for it
+    if let Some(last) = count.checked_sub(1) {
+        let end = last.checked_mul(stride).and_then(|offset| offset.checked_add(start + components * size));
+        if end.is_none_or(|end| end > bin.len()) {
+            return Err(invalid("accessor out of bounds"));
+        }
+    }
+
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut value = [0.0; 4];
+        for (c, v) in value.iter_mut().enumerate().take(components) {
+            let at = start + i * stride + c * size;
+            let bytes = bin.get(at..at + size).ok_or_else(|| invalid("accessor out of bounds"))?;
+            *v = match component_type {
+                BYTE if normalized => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+                BYTE => bytes[0] as i8 as f32,
+                UNSIGNED_BYTE if normalized => bytes[0] as f32 / 255.0,
+                UNSIGNED_BYTE => bytes[0] as f32,
+                SHORT if normalized => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+                SHORT => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                UNSIGNED_SHORT if normalized => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+                UNSIGNED_SHORT => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                UNSIGNED_INT => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+                _ => f32::from_le_bytes(bytes.try_into().unwrap()),
+            };
+        }
+        out.push(value);
+    }
+    Ok(out)
+}
+
+/// Read the indices of an accessor, which unlike [`read_accessor`] mustn't go through `f32`.
+fn read_indices(doc: &Value, bin: &[u8], index: &Value) -> io::Result<Vec<u32>> {
+    let accessor = index
+        .as_u64()
+        .and_then(|i| doc["accessors"].get(i as usize))
+        .ok_or_else(|| invalid("bad accessor index"))?;
+    if accessor["componentType"].as_u64() != Some(UNSIGNED_INT as u64) {
+        return Ok(read_accessor(doc, bin, index)?.into_iter().map(|v| v[0] as u32).collect());
+    }
+    let view = accessor["bufferView"]
+        .as_u64()
+        .and_then(|i| doc["bufferViews"].get(i as usize))
+        .ok_or_else(|| invalid("accessor has no buffer view"))?;
+    let start = view["byteOffset"].as_u64().unwrap_or(0) as usize + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+    let end = count.checked_mul(4).and_then(|len| len.checked_add(start));
+    end.and_then(|end| bin.get(start..end))
+        .ok_or_else(|| invalid("accessor out of bounds"))
+        .map(|b| b.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect())
+}
+
+/// The bone hash of a joint node: the hex hash we export unknown bones as, or the FNV-32 of its name.
+fn joint_hash(name: &str) -> u32 {
+    match u32::from_str_radix(name, 16) {
+        Ok(hash) if name.len() == 8 => hash,
+        _ => crate::hash::fnv32(name),
+    }
+}
+
+/// Read a binary glTF file. Every node with a mesh becomes a LOD, with one mesh per primitive.
+///
+/// Only triangle lists are read, and only the first set of joints and weights.
+pub fn read_glb(data: &[u8]) -> io::Result<Vec<Lod>> {
+    let u32_at = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    if data.get(0..4) != Some(b"glTF") || u32_at(4) != Some(2) {
+        return Err(invalid("not a glTF 2.0 binary"));
+    }
+
+    let mut doc = None;
+    let mut bin: &[u8] = &[];
+    let mut at = 12;
+    while let (Some(len), Some(kind)) = (u32_at(at), data.get(at + 4..at + 8)) {
+        let chunk = data
+            .get(at + 8..at + 8 + len as usize)
+            .ok_or_else(|| invalid("truncated glTF chunk"))?;
+        match kind {
+            b"JSON" => doc = Some(serde_json::from_slice::<Value>(chunk)?),
+            b"BIN\0" => bin = chunk,
+            _ => {}
+        }
+        at += 8 + len as usize;
+    }
+    let doc = doc.ok_or_else(|| invalid("glTF has no JSON chunk"))?;
+
+    let joint_hashes = |skin: &Value| -> Vec<u32> {
+        skin.as_u64()
+            .and_then(|s| doc["skins"][s as usize]["joints"].as_array())
+            .into_iter()
+            .flatten()
+            .map(|j| {
+                let node = &doc["nodes"][j.as_u64().unwrap_or(0) as usize];
+                joint_hash(node["name"].as_str().unwrap_or(""))
+            })
+            .collect()
+    };
+
+    let mut lods = Vec::new();
+    for node in doc["nodes"].as_array().into_iter().flatten() {
+        let Some(mesh) = node["mesh"].as_u64().map(|m| &doc["meshes"][m as usize]) else {
+            continue;
+        };
+        let bones = joint_hashes(&node["skin"]);
+        let mut lod = Lod {
+            name: node["name"].as_str().or(mesh["name"].as_str()).unwrap_or("").to_string(),
+            meshes: Vec::new(),
+        };
+
+        for primitive in mesh["primitives"].as_array().into_iter().flatten() {
+            if primitive["mode"].as_u64().unwrap_or(4) != 4 {
+                continue;
+            }
+            let attributes = &primitive["attributes"];
+            let attribute = |name: &str| -> io::Result<Option<Vec<[f32; 4]>>> {
+                match &attributes[name] {
+                    Value::Null => Ok(None),
+                    index => read_accessor(&doc, bin, index).map(Some),
+                }
+            };
+
+            let positions: Vec<[f32; 3]> = attribute("POSITION")?
+                .ok_or_else(|| invalid("primitive has no positions"))?
+                .into_iter()
+                .map(|[x, y, z, _]| [x, y, z])
+                .collect();
+            let count = positions.len();
+            let mut out = Mesh {
+                name: format!("{}_{}", lod.name, lod.meshes.len()),
+                positions,
+                normals: attribute("NORMAL")?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|[x, y, z, _]| [x, y, z])
+                    .collect(),
+                ..Default::default()
+            };
+            while let Some(uv) = attribute(&format!("TEXCOORD_{}", out.uvs.len()))? {
+                out.uvs.push(uv.into_iter().map(|[u, v, _, _]| [u, v]).collect());
+            }
+
+            if let (Some(joints), Some(weights)) = (attribute("JOINTS_0")?, attribute("WEIGHTS_0")?) {
+                if joints.iter().flatten().any(|&j| j as usize >= bones.len().min(256)) {
+                    return Err(invalid(format!("{}: joint index out of range", out.name)));
+                }
+                out.joints = joints.into_iter().map(|j| j.map(|x| x as u8)).collect();
+                out.weights = weights;
+                out.bones = bones.clone();
+            }
+
+            out.indices = match &primitive["indices"] {
+                Value::Null => (0..count as u32).collect(),
+                index => read_indices(&doc, bin, index)?,
+            };
+            lod.meshes.push(out);
+        }
+        if !lod.meshes.is_empty() {
+            lods.push(lod);
+        }
+    }
+    Ok(lods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::fnv32;
+
+    fn triangle(bones: &[&str]) -> Mesh {
+        let skinned = !bones.is_empty();
+        Mesh {
+            name: "triangle".to_string(),
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            joints: if skinned { vec![[0, 1, 0, 0]; 3] } else { Vec::new() },
+            weights: if skinned { vec![[0.5, 0.5, 0.0, 0.0]; 3] } else { Vec::new() },
+            bones: bones.iter().map(|b| fnv32(b)).collect(),
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        }
+    }
+
+    fn node<'a>(doc: &'a Value, name: &str) -> &'a Value {
+        doc["nodes"].as_array().unwrap().iter().find(|n| n["name"] == name).unwrap()
+    }
+
+    #[test]
+    fn joints_follow_the_rig() {
+        let lods = [Lod {
+            name: "LOD0".to_string(),
+            meshes: vec![triangle(&["b__Head__", "b__Spine1__", "b__Pelvis__"])],
+        }];
+        let (doc, _) = build(&lods, &crate::hash::KNOWN_NAMES);
+        let index = |name: &str| doc["nodes"].as_array().unwrap().iter().position(|n| n["name"] == name).unwrap();
+
+        // the spine and neck bones in between aren't joints, so the head goes straight under Spine1
+        assert_eq!(node(&doc, "b__Pelvis__")["children"], json!([index("b__Spine1__")]));
+        assert_eq!(node(&doc, "b__Spine1__")["children"], json!([index("b__Head__")]));
+        assert_eq!(node(&doc, "skeleton")["children"], json!([index("b__Pelvis__")]));
+        assert_eq!(node(&doc, "LOD0")["skin"], 0);
+        assert!(doc["meshes"][0]["primitives"][0]["attributes"]["JOINTS_0"].is_u64());
+    }
+
+    #[test]
+    fn partly_skinned_lods_have_no_skin() {
+        let lods = [Lod {
+            name: "LOD0".to_string(),
+            meshes: vec![triangle(&["b__Head__", "b__Neck__"]), triangle(&[])],
+        }];
+        let (doc, _) = build(&lods, &crate::hash::KNOWN_NAMES);
+        assert!(node(&doc, "LOD0").get("skin").is_none());
+        assert!(doc.get("skins").is_none());
+        for primitive in doc["meshes"][0]["primitives"].as_array().unwrap() {
+            assert!(primitive["attributes"].get("JOINTS_0").is_none());
+        }
+    }
+
+    #[test]
+    fn accessor_counts_past_the_buffer_are_rejected() {
+        let lods = [Lod {
+            name: "LOD0".to_string(),
+            meshes: vec![triangle(&[])],
+        }];
+        let (mut doc, bin) = build(&lods, &crate::hash::KNOWN_NAMES);
+        let position = doc["meshes"][0]["primitives"][0]["attributes"]["POSITION"].clone();
+        assert_eq!(read_accessor(&doc, &bin, &position).unwrap().len(), 3);
+
+        doc["accessors"][position.as_u64().unwrap() as usize]["count"] = json!(u64::MAX / 2);
+        assert!(read_accessor(&doc, &bin, &position).is_err());
+        let indices = doc["meshes"][0]["primitives"][0]["indices"].clone();
+        doc["accessors"][indices.as_u64().unwrap() as usize]["count"] = json!(u64::MAX / 2);
+        assert!(read_indices(&doc, &bin, &indices).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Wavefront OBJ import and export. OBJ has no skinning and only one UV set, so those are dropped.
+
+use super::{Lod, Mesh};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// Write each LOD as an object, and each of its meshes as a group.
+pub fn write_obj<W: Write>(writer: &mut W, lods: &[Lod]) -> io::Result<()> {
+    writeln!(writer, "# exported by sims3_rs")?;
+    // OBJ indices are 1-based and global across the whole file
+    let (mut v_base, mut vt_base, mut vn_base) = (1, 1, 1);
+    for lod in lods {
+        writeln!(writer, "o {}", lod.name)?;
+        for mesh in &lod.meshes {
+            writeln!(writer, "g {}", mesh.name)?;
+            for [x, y, z] in &mesh.positions {
+                writeln!(writer, "v {} {} {}", x, y, z)?;
+            }
+            let uvs = mesh.uvs.first().filter(|uv| uv.len() == mesh.positions.len());
+            for [u, v] in uvs.into_iter().flatten() {
+                // OBJ puts the origin at the bottom left, the game at the top left
+                writeln!(writer, "vt {} {}", u, 1.0 - v)?;
+            }
+            let normals = Some(&mesh.normals).filter(|n| n.len() == mesh.positions.len());
+            for [x, y, z] in normals.into_iter().flatten() {
+                writeln!(writer, "vn {} {} {}", x, y, z)?;
+            }
+
+            for tri in mesh.indices.chunks_exact(3) {
+                write!(writer, "f")?;
+                for &i in tri {
+                    match (uvs.is_some(), normals.is_some()) {
+                        (true, true) => write!(writer, " {}/{}/{}", v_base + i, vt_base + i, vn_base + i)?,
+                        (true, false) => write!(writer, " {}/{}", v_base + i, vt_base + i)?,
+                        (false, true) => write!(writer, " {}//{}", v_base + i, vn_base + i)?,
+                        (false, false) => write!(writer, " {}", v_base + i)?,
+                    }
+                }
+                writeln!(writer)?;
+            }
+
+            let count = mesh.positions.len() as u32;
+            v_base += count;
+            if uvs.is_some() {
+                vt_base += count;
+            }
+            if normals.is_some() {
+                vn_base += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read an OBJ file. Each object becomes a LOD and each group a mesh; polygons are triangulated as fans.
+///
+/// Vertices are split wherever faces use different UVs or normals for the same position,
+/// since the game (like glTF) has a single index per vertex.
+pub fn read_obj<R: BufRead>(reader: R) -> io::Result<Vec<Lod>> {
+    let invalid = |line: usize, msg: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("OBJ line {}: {}", line, msg))
+    };
+
+    let (mut v, mut vt, mut vn) = (Vec::new(), Vec::new(), Vec::new());
+    let mut lods: Vec<Lod> = Vec::new();
+    // (position, uv, normal) -> vertex index in the current mesh
+    let mut seen: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let n = n + 1;
+        let mut parts = line.split_whitespace();
+        let floats = |parts: std::str::SplitWhitespace| -> io::Result<Vec<f32>> {
+            parts
+                .map(|p| p.parse::<f32>().map_err(|_| invalid(n, "bad number")))
+                .collect()
+        };
+        match parts.next() {
+            Some("v") => match floats(parts)?[..] {
+                [x, y, z, ..] => v.push([x, y, z]),
+                _ => return Err(invalid(n, "vertex needs 3 coordinates")),
+            },
+            // flip back, see write_obj
+            Some("vt") => match floats(parts)?[..] {
+                [s, t, ..] => vt.push([s, 1.0 - t]),
+                _ => return Err(invalid(n, "texture coordinate needs 2 components")),
+            },
+            Some("vn") => match floats(parts)?[..] {
+                [x, y, z, ..] => vn.push([x, y, z]),
+                _ => return Err(invalid(n, "normal needs 3 components")),
+            },
+            Some("o") => {
+                lods.push(Lod {
+                    name: parts.collect::<Vec<_>>().join(" "),
+                    meshes: Vec::new(),
+                });
+                seen.clear();
+            }
+            Some("g") => {
+                if lods.is_empty() {
+                    lods.push(Lod::default());
+                }
+                let lod = lods.last_mut().unwrap();
+                lod.meshes.push(Mesh {
+                    name: parts.collect::<Vec<_>>().join(" "),
+                    ..Default::default()
+                });
+                seen.clear();
+            }
+            Some("f") => {
+                if lods.is_empty() {
+                    lods.push(Lod::default());
+                }
+                let lod = lods.last_mut().unwrap();
+                if lod.meshes.is_empty() {
+                    lod.meshes.push(Mesh::default());
+                }
+                let mesh = lod.meshes.last_mut().unwrap();
+
+                // OBJ indices are 1-based, negative ones count back from the end
+                let resolve = |s: &str, len: usize| -> io::Result<Option<usize>> {
+                    if s.is_empty() {
+                        return Ok(None);
+                    }
+                    let i: i64 = s.parse().map_err(|_| invalid(n, "bad index"))?;
+                    let i = if i < 0 { len as i64 + i } else { i - 1 };
+                    if i < 0 || i as usize >= len {
+                        return Err(invalid(n, "index out of range"));
+                    }
+                    Ok(Some(i as usize))
+                };
+                let mut polygon = Vec::new();
+                for corner in parts {
+                    let mut idx = corner.split('/');
+                    let key = (
+                        resolve(idx.next().unwrap_or(""), v.len())?.ok_or_else(|| invalid(n, "missing vertex index"))?,
+                        resolve(idx.next().unwrap_or(""), vt.len())?,
+                        resolve(idx.next().unwrap_or(""), vn.len())?,
+                    );
+                    let index = *seen.entry(key).or_insert_with(|| {
+                        mesh.positions.push(v[key.0]);
+                        if let Some(t) = key.1 {
+                            if mesh.uvs.is_empty() {
+                                mesh.uvs.push(Vec::new());
+                            }
+                            mesh.uvs[0].push(vt[t]);
+                        }
+                        if let Some(nm) = key.2 {
+                            mesh.normals.push(vn[nm]);
+                        }
+                        mesh.positions.len() as u32 - 1
+                    });
+                    polygon.push(index);
+                }
+                for i in 1..polygon.len().saturating_sub(1) {
+                    mesh.indices.extend([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // a mesh that only sometimes had UVs or normals can't use them
+    for mesh in lods.iter_mut().flat_map(|l| &mut l.meshes) {
+        if mesh.uvs.first().is_some_and(|uv| uv.len() != mesh.positions.len()) {
+            mesh.uvs.clear();
+        }
+        if mesh.normals.len() != mesh.positions.len() {
+            mesh.normals.clear();
+        }
+    }
+    lods.retain(|l| !l.meshes.is_empty());
+    Ok(lods)
+}
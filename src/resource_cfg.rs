@@ -0,0 +1,405 @@
+//! `Resource.cfg`, which tells the game which packages in the Mods folder to load, and which one
+//! wins when several have the same resource.
+//!
+//! ```text
+//! Priority 500
+//! PackedFile Packages/*.package
+//! PackedFile Packages/*/*.package
+//! Priority 501
+//! PackedFile Overrides/.../*.package
+//! DirectoryFiles Files/... autoupdate
+//! ```
+//!
+//! `Priority` applies to the lines after it. In paths, `*` and `?` match within one file or directory
+//! name, and `...` matches any number of directories. Packages load in the order of the lines that
+//! match them, and in name order for each line; a package matched by several lines loads once, with
+//! the first line's priority.
+//!
+//! A resource in a package with a higher priority wins over the same resource in one with a lower
+//! priority. For the same priority, the package that loads last wins. [`Resolver`] answers which
+//! package that is for any key.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache::ScanCache;
+use crate::dbpf::filetypes::tgi::TGI;
+use crate::error::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Directive {
+    /// Packages to load.
+    PackedFile,
+    /// A directory of loose resource files. `autoupdate` makes the game pick up changes while it runs.
+    DirectoryFiles { autoupdate: bool },
+}
+
+/// A `PackedFile` or `DirectoryFiles` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub directive: Directive,
+    /// Relative to the folder `Resource.cfg` is in.
+    pub pattern: String,
+    pub priority: i32,
+    /// Line number, counting from 1.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCfg {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCfgError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseCfgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Resource.cfg line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseCfgError {}
+
+/// A package the game loads, see [`ResourceCfg::packages`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadedPackage {
+    pub path: PathBuf,
+    pub priority: i32,
+}
+
+impl ResourceCfg {
+    /// Parse the text of a `Resource.cfg`. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, ParseCfgError> {
+        let mut cfg = ResourceCfg::default();
+        // the game's default, for lines before the first Priority
+        let mut priority = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let err = |message: String| ParseCfgError {
+                line: line_number,
+                message,
+            };
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+            if directive.starts_with('#') {
+                continue;
+            }
+            let argument = words.next();
+            let directive = match directive.to_ascii_lowercase().as_str() {
+                "priority" => {
+                    let argument = argument.ok_or_else(|| err("Priority needs a number".to_string()))?;
+                    priority = argument
+                        .parse()
+                        .map_err(|_| err(format!("priority '{}' is not a number", argument)))?;
+                    continue;
+                }
+                "packedfile" => Directive::PackedFile,
+                "directoryfiles" => Directive::DirectoryFiles {
+                    autoupdate: words.any(|w| w.eq_ignore_ascii_case("autoupdate")),
+                },
+                _ => return Err(err(format!("unknown directive '{}'", directive))),
+            };
+            let pattern = argument.ok_or_else(|| err("missing a path".to_string()))?;
+            cfg.entries.push(Entry {
+                directive,
+                pattern: pattern.to_owned(),
+                priority,
+                line: line_number,
+            });
+        }
+        Ok(cfg)
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The packages the game would load from `root` (the folder `Resource.cfg` is in), in load order.
+    pub fn packages(&self, root: &Path) -> Vec<LoadedPackage> {
+        let mut seen = HashSet::new();
+        let mut packages = Vec::new();
+        for entry in self.entries.iter().filter(|e| e.directive == Directive::PackedFile) {
+            let mut paths = Vec::new();
+            let parts: Vec<&str> = entry
+                .pattern
+                .split(['/', '\\'])
+                .filter(|p| !p.is_empty() && *p != ".")
+                .collect();
+            expand(root.to_path_buf(), &parts, &mut paths);
+            for path in paths {
+                if seen.insert(path.clone()) {
+                    packages.push(LoadedPackage {
+                        path,
+                        priority: entry.priority,
+                    });
+                }
+            }
+        }
+        packages
+    }
+
+    /// The directories of loose files the game would load from `root`.
+    pub fn directories(&self, root: &Path) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        for entry in self.entries.iter().filter(|e| e.directive != Directive::PackedFile) {
+            // `Files/...` is the directory and everything in it
+            let pattern = entry.pattern.trim_end_matches("...").trim_end_matches(['/', '\\']);
+            let parts: Vec<&str> = pattern
+                .split(['/', '\\'])
+                .filter(|p| !p.is_empty() && *p != ".")
+                .collect();
+            expand(root.to_path_buf(), &parts, &mut dirs);
+        }
+        dirs.retain(|dir| dir.is_dir());
+        dirs
+    }
+}
+
+/// Add the paths under `dir` that match the pattern `parts`, in name order.
+fn expand(dir: PathBuf, parts: &[&str], out: &mut Vec<PathBuf>) {
+    let Some((&part, rest)) = parts.split_first() else {
+        if dir.exists() {
+            out.push(dir);
+        }
+        return;
+    };
+    if part == "..." {
+        expand(dir.clone(), rest, out);
+        for child in children(&dir).into_iter().filter(|c| c.is_dir()) {
+            expand(child, parts, out);
+        }
+    } else if part.contains(['*', '?']) {
+        for child in children(&dir) {
+            let name = child
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if wildcard_match(part, &name) {
+                expand(child, rest, out);
+            }
+        }
+    } else {
+        expand(dir.join(part), rest, out);
+    }
+}
+
+/// What's in `dir`, sorted by name (ignoring case, like Windows does).
+fn children(dir: &Path) -> Vec<PathBuf> {
+    let mut children: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+    children.sort_by_key(|path| path.file_name().map(|n| n.to_string_lossy().to_lowercase()));
+    children
+}
+
+/// `*` matches any run of characters and `?` any one character. Case is ignored.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where the last `*` was, and how much of the name it has taken
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Which of a set of loaded packages provides each resource.
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    packages: Vec<LoadedPackage>,
+    /// Indices into `packages` of the packages that have each key, the winner first.
+    providers: HashMap<TGI, Vec<usize>>,
+}
+
+impl Resolver {
+    /// `packages` in load order, with the keys of the resources in each.
+    pub fn new<K: IntoIterator<Item = TGI>>(packages: impl IntoIterator<Item = (LoadedPackage, K)>) -> Self {
+        let mut resolver = Resolver {
+            packages: Vec::new(),
+            providers: HashMap::new(),
+        };
+        for (i, (package, keys)) in packages.into_iter().enumerate() {
+            for key in keys {
+                resolver.providers.entry(key).or_default().push(i);
+            }
+            resolver.packages.push(package);
+        }
+        let packages = &resolver.packages;
+        for indices in resolver.providers.values_mut() {
+            indices.dedup();
+            // highest priority first, and the last loaded of those
+            indices.sort_by_key(|&i| std::cmp::Reverse((packages[i].priority, i)));
+        }
+        resolver
+    }
+
+    /// The packages that `Resource.cfg` in `mods` loads, with their contents from `cache` (which is
+    /// brought up to date first). Also returns the packages that couldn't be read.
+    pub fn from_mods_folder(mods: &Path, cache: &mut ScanCache) -> io::Result<(Self, Vec<(PathBuf, Error)>)> {
+        let packages = ResourceCfg::read_file(mods.join("Resource.cfg"))?.packages(mods);
+        let paths: Vec<PathBuf> = packages.iter().map(|p| p.path.clone()).collect();
+        let failures = cache.refresh(&paths);
+        let resolver = Resolver::new(packages.into_iter().map(|package| {
+            let keys: Vec<TGI> = cache.get(&package.path).map(|p| p.tgis().collect()).unwrap_or_default();
+            (package, keys)
+        }));
+        Ok((resolver, failures))
+    }
+
+    /// Every package, in load order.
+    pub fn packages(&self) -> &[LoadedPackage] {
+        &self.packages
+    }
+
+    /// The packages that have `tgi`, the one whose copy the game uses first.
+    pub fn providers(&self, tgi: &TGI) -> impl Iterator<Item = &LoadedPackage> + '_ {
+        self.providers
+            .get(tgi)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.packages[i])
+    }
+
+    /// The package whose copy of `tgi` the game uses.
+    pub fn winner(&self, tgi: &TGI) -> Option<&LoadedPackage> {
+        self.providers(tgi).next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Mods folder with these (empty) files in it, in a fresh temporary directory.
+    fn mods_folder(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("sims3_rs-cfg-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        root
+    }
+
+    fn relative(root: &Path, packages: &[LoadedPackage]) -> Vec<(String, i32)> {
+        packages
+            .iter()
+            .map(|p| (p.path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"), p.priority))
+            .collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*.package", "Hair.package"));
+        assert!(wildcard_match("*.PACKAGE", "hair.package"));
+        assert!(!wildcard_match("*.package", "hair.package.bak"));
+        assert!(wildcard_match("a?c*", "abcdef"));
+        assert!(!wildcard_match("a?c", "ac"));
+        assert!(wildcard_match("*a*b*", "xxaxxbxx"));
+        assert!(!wildcard_match("*a*b", "xxbxxa"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn priority_applies_to_the_lines_after_it() {
+        let cfg = ResourceCfg::parse(
+            "# comment\nPackedFile a.package\nPriority 500\n\nPackedFile b/*.package\npriority -1\n\
+             DirectoryFiles Files/... autoupdate\n",
+        )
+        .unwrap();
+        let summary: Vec<_> = cfg.entries.iter().map(|e| (e.pattern.as_str(), e.priority, e.line)).collect();
+        assert_eq!(summary, [("a.package", 0, 2), ("b/*.package", 500, 5), ("Files/...", -1, 7)]);
+        assert_eq!(cfg.entries[2].directive, Directive::DirectoryFiles { autoupdate: true });
+
+        assert_eq!(ResourceCfg::parse("Priority high").unwrap_err().line, 1);
+        assert_eq!(ResourceCfg::parse("\nLoadAll x").unwrap_err().line, 2);
+        assert_eq!(ResourceCfg::parse("PackedFile").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn packages_in_load_order() {
+        let root = mods_folder(
+            "packages",
+            &[
+                "Packages/b.package",
+                "Packages/A.package",
+                "Packages/notes.txt",
+                "Packages/Hair/c.package",
+                "Packages/Hair/Long/d.package",
+                "Overrides/e.package",
+                "Files/x.dds",
+            ],
+        );
+        let cfg = ResourceCfg::parse(
+            "Priority 500\nPackedFile Packages/*.package\nPackedFile Packages/.../*.package\n\
+             Priority 1000\nPackedFile Overrides/*.package\nPackedFile Packages/b.package\n\
+             DirectoryFiles Files/...\n",
+        )
+        .unwrap();
+        assert_eq!(
+            relative(&root, &cfg.packages(&root)),
+            [
+                ("Packages/A.package".to_string(), 500),
+                ("Packages/b.package".to_string(), 500),
+                // `...` matches no directories first, then each subdirectory in turn
+                ("Packages/Hair/c.package".to_string(), 500),
+                ("Packages/Hair/Long/d.package".to_string(), 500),
+                ("Overrides/e.package".to_string(), 1000),
+            ]
+        );
+        assert_eq!(cfg.directories(&root), [root.join("Files")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn resolver_picks_priority_then_load_order() {
+        let package = |name: &str, priority| LoadedPackage {
+            path: PathBuf::from(name),
+            priority,
+        };
+        let (shared, overridden, only) = (TGI::new(1, 0, 1), TGI::new(1, 0, 2), TGI::new(1, 0, 3));
+        let resolver = Resolver::new([
+            (package("first", 500), vec![shared, overridden, only]),
+            (package("second", 500), vec![shared]),
+            (package("override", 1000), vec![overridden]),
+            (package("low", 0), vec![shared]),
+        ]);
+        let names = |tgi| resolver.providers(&tgi).map(|p| p.path.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names(shared), ["second", "first", "low"]);
+        assert_eq!(names(overridden), ["override", "first"]);
+        assert_eq!(resolver.winner(&only).unwrap().path, Path::new("first"));
+        assert!(resolver.winner(&TGI::new(1, 0, 4)).is_none());
+        assert_eq!(resolver.packages().len(), 4);
+    }
+}
@@ -0,0 +1,521 @@
+//! Sims3Pack files, the container the launcher installs and the game exports sims and lots to.
+//!
+//! A Sims3Pack is a short header, an XML manifest, and then the files the manifest lists
+//! (packages, and sometimes thumbnails) one after the other:
+//!
+//! ```text
+//! u32      7
+//! [u8; 7]  "TS3Pack"
+//! u16      0x0101
+//! u32      length of the manifest
+//! [u8]     the manifest, UTF-8 XML with a <Sims3Package> root
+//! [u8]     the files, at the offsets the manifest gives (counted from the end of the manifest)
+//! ```
+//!
+//! The whole file is read into memory, and the packages in it are read with [`Sims3Pack::open_package`]
+//! like any other [`DBPF`].
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use binrw::{binrw, BinRead, BinWrite};
+
+use crate::dbpf::{DBPFReader, DBPF};
+use crate::error::Error;
+
+/// `u32` magic length, and the magic.
+const MAGIC: &[u8] = b"\x07\x00\x00\x00TS3Pack";
+const HEADER_SIZE: u64 = MAGIC.len() as u64 + 6;
+
+#[binrw]
+#[brw(little, magic = b"\x07\x00\x00\x00TS3Pack")]
+struct Header {
+    version: u16,
+    manifest_len: u32,
+}
+
+/// What a [`PackagedFile`] is, going by its name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Package,
+    Thumbnail,
+    Other,
+}
+
+/// A file inside a Sims3Pack, as the manifest describes it.
+///
+/// Fields the tools don't need to understand are kept as text, and elements they don't know at all
+/// as XML, so they're written back unchanged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackagedFile {
+    pub name: String,
+    pub length: u64,
+    /// Where the file starts, counted from the end of the manifest.
+    pub offset: u64,
+    /// The game's checksum of the file. It isn't known how it's calculated, so files added with
+    /// [`Sims3Pack::add_file`] don't have one.
+    pub crc: Option<String>,
+    pub guid: String,
+    /// Like `household`, `lot` or `casPart`.
+    pub content_type: String,
+    /// Which expansions the file needs, as a hex bit field.
+    pub ep_flags: String,
+    /// The children of `<metatags>`, like `numOfThumbs`.
+    pub metatags: Vec<(String, String)>,
+    /// Other elements, as the XML they were read from.
+    pub other: Vec<String>,
+}
+
+impl PackagedFile {
+    pub fn kind(&self) -> FileKind {
+        let name = self.name.to_ascii_lowercase();
+        if name.ends_with(".package") {
+            FileKind::Package
+        } else if name.ends_with(".png") || name.ends_with(".jpg") {
+            FileKind::Thumbnail
+        } else {
+            FileKind::Other
+        }
+    }
+
+    fn parse(node: roxmltree::Node) -> Result<Self, String> {
+        let mut file = PackagedFile::default();
+        let (mut length, mut offset) = (None, None);
+        for child in node.children().filter(|n| n.is_element()) {
+            let (name, text) = (child.tag_name().name(), child.text().unwrap_or_default().trim().to_owned());
+            let number = || {
+                text.parse::<u64>()
+                    .map_err(|_| format!("<{}> of a <PackagedFile> is '{}'", name, text))
+            };
+            match name {
+                "Name" => file.name = text.clone(),
+                "Length" => length = Some(number()?),
+                "Offset" => offset = Some(number()?),
+                "Crc" => file.crc = Some(text.clone()),
+                "Guid" => file.guid = text.clone(),
+                "ContentType" => file.content_type = text.clone(),
+                "EPFlags" => file.ep_flags = text.clone(),
+                "metatags" => {
+                    file.metatags = child_texts(child)
+                        .map(|(name, text)| (name.to_owned(), text))
+                        .collect()
+                }
+                _ => file.other.push(source(child)),
+            }
+        }
+        let missing = |what| format!("<PackagedFile> {} has no <{}>", file.name, what);
+        file.length = length.ok_or_else(|| missing("Length"))?;
+        file.offset = offset.ok_or_else(|| missing("Offset"))?;
+        Ok(file)
+    }
+}
+
+/// The XML manifest at the start of a Sims3Pack.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// The root's `Type`, which is what the Sims3Pack holds: `household`, `lot`, `casPart`, ...
+    pub content_type: String,
+    pub sub_type: String,
+    pub archive_version: String,
+    pub code_version: String,
+    pub game_version: String,
+    pub display_name: String,
+    pub description: String,
+    /// 128 bit id of this Sims3Pack, in hex.
+    pub package_id: String,
+    pub date: String,
+    pub asset_version: String,
+    pub min_req_version: String,
+    /// Package ids of the expansions, stuff packs and store content this needs.
+    pub dependencies: Vec<String>,
+    /// Language code and name.
+    pub localized_names: Vec<(String, String)>,
+    /// Language code and description.
+    pub localized_descriptions: Vec<(String, String)>,
+    pub files: Vec<PackagedFile>,
+    /// Attributes of the root besides `Type` and `SubType`.
+    pub attributes: Vec<(String, String)>,
+    /// Other elements, as the XML they were read from.
+    pub other: Vec<String>,
+}
+
+impl Manifest {
+    /// A manifest for a new Sims3Pack, with the versions the game writes.
+    pub fn new(content_type: impl Into<String>, display_name: impl Into<String>) -> Self {
+        Manifest {
+            content_type: content_type.into(),
+            sub_type: "0x00000000".into(),
+            archive_version: "1.4".into(),
+            code_version: "0.2.0.69".into(),
+            game_version: "0.0.0.0".into(),
+            display_name: display_name.into(),
+            asset_version: "0".into(),
+            min_req_version: "1.0.632.0".into(),
+            ..Default::default()
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let doc = roxmltree::Document::parse(text.trim_start_matches('\u{feff}')).map_err(|e| e.to_string())?;
+        let root = doc.root_element();
+        if !root.has_tag_name("Sims3Package") {
+            return Err(format!(
+                "root element is <{}>, not <Sims3Package>",
+                root.tag_name().name()
+            ));
+        }
+        let mut manifest = Manifest::default();
+        for attribute in root.attributes() {
+            match attribute.name() {
+                "Type" => manifest.content_type = attribute.value().to_owned(),
+                "SubType" => manifest.sub_type = attribute.value().to_owned(),
+                name => manifest.attributes.push((name.to_owned(), attribute.value().to_owned())),
+            }
+        }
+        for node in root.children().filter(|n| n.is_element()) {
+            let text = node.text().unwrap_or_default().trim().to_owned();
+            match node.tag_name().name() {
+                "ArchiveVersion" => manifest.archive_version = text,
+                "CodeVersion" => manifest.code_version = text,
+                "GameVersion" => manifest.game_version = text,
+                "DisplayName" => manifest.display_name = text,
+                "Description" => manifest.description = text,
+                "PackageId" => manifest.package_id = text,
+                "Date" => manifest.date = text,
+                "AssetVersion" => manifest.asset_version = text,
+                "MinReqVersion" => manifest.min_req_version = text,
+                "DependencyList" => manifest.dependencies = child_texts(node).map(|(_, text)| text).collect(),
+                "LocalizedNames" => manifest.localized_names = localized(node),
+                "LocalizedDescriptions" => manifest.localized_descriptions = localized(node),
+                "PackagedFile" => manifest.files.push(PackagedFile::parse(node)?),
+                _ => manifest.other.push(source(node)),
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        let _ = write!(
+            xml,
+            "<Sims3Package Type=\"{}\" SubType=\"{}\"",
+            escape(&self.content_type),
+            escape(&self.sub_type)
+        );
+        for (name, value) in &self.attributes {
+            let _ = write!(xml, " {}=\"{}\"", name, escape(value));
+        }
+        xml.push_str(">\n");
+        let fields = [
+            ("ArchiveVersion", &self.archive_version),
+            ("CodeVersion", &self.code_version),
+            ("GameVersion", &self.game_version),
+            ("DisplayName", &self.display_name),
+            ("Description", &self.description),
+            ("PackageId", &self.package_id),
+            ("Date", &self.date),
+            ("AssetVersion", &self.asset_version),
+            ("MinReqVersion", &self.min_req_version),
+        ];
+        for (name, value) in fields {
+            element(&mut xml, 1, name, value);
+        }
+        xml.push_str("  <DependencyList>\n");
+        for id in &self.dependencies {
+            element(&mut xml, 2, "PackageId", id);
+        }
+        xml.push_str("  </DependencyList>\n");
+        for (list, item, values) in [
+            ("LocalizedNames", "LocalizedName", &self.localized_names),
+            (
+                "LocalizedDescriptions",
+                "LocalizedDescription",
+                &self.localized_descriptions,
+            ),
+        ] {
+            let _ = writeln!(xml, "  <{}>", list);
+            for (language, value) in values {
+                let _ = writeln!(
+                    xml,
+                    "    <{0} Language=\"{1}\">{2}</{0}>",
+                    item,
+                    escape(language),
+                    escape(value)
+                );
+            }
+            let _ = writeln!(xml, "  </{}>", list);
+        }
+        for other in &self.other {
+            let _ = writeln!(xml, "  {}", other);
+        }
+        for file in &self.files {
+            xml.push_str("  <PackagedFile>\n");
+            element(&mut xml, 2, "Name", &file.name);
+            element(&mut xml, 2, "Length", &file.length.to_string());
+            element(&mut xml, 2, "Offset", &file.offset.to_string());
+            if let Some(crc) = &file.crc {
+                element(&mut xml, 2, "Crc", crc);
+            }
+            element(&mut xml, 2, "Guid", &file.guid);
+            element(&mut xml, 2, "ContentType", &file.content_type);
+            element(&mut xml, 2, "EPFlags", &file.ep_flags);
+            xml.push_str("    <metatags>\n");
+            for (name, value) in &file.metatags {
+                element(&mut xml, 3, name, value);
+            }
+            xml.push_str("    </metatags>\n");
+            for other in &file.other {
+                let _ = writeln!(xml, "    {}", other);
+            }
+            xml.push_str("  </PackagedFile>\n");
+        }
+        xml.push_str("</Sims3Package>\n");
+        xml
+    }
+}
+
+/// The child elements of `node`, with their (trimmed) text.
+fn child_texts<'a>(node: roxmltree::Node<'a, '_>) -> impl Iterator<Item = (&'a str, String)> + 'a {
+    node.children()
+        .filter(|n| n.is_element())
+        .map(|n| (n.tag_name().name(), n.text().unwrap_or_default().trim().to_owned()))
+}
+
+/// The XML of `node`, as it is in the manifest.
+fn source(node: roxmltree::Node) -> String {
+    node.document().input_text()[node.range()].to_owned()
+}
+
+fn localized(node: roxmltree::Node) -> Vec<(String, String)> {
+    node.children()
+        .filter(|n| n.is_element())
+        .map(|n| {
+            let language = n.attribute("Language").unwrap_or_default().to_owned();
+            (language, n.text().unwrap_or_default().trim().to_owned())
+        })
+        .collect()
+}
+
+fn element(xml: &mut String, depth: usize, name: &str, value: &str) {
+    let _ = writeln!(
+        xml,
+        "{:indent$}<{name}>{}</{name}>",
+        "",
+        escape(value),
+        indent = depth * 2,
+        name = name
+    );
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A Sims3Pack, in memory.
+#[derive(Clone, Debug)]
+pub struct Sims3Pack {
+    pub manifest: Manifest,
+    /// Everything after the manifest.
+    data: Vec<u8>,
+}
+
+impl Sims3Pack {
+    /// An empty Sims3Pack, to [`add_file`](Self::add_file) to.
+    pub fn new(manifest: Manifest) -> Self {
+        Sims3Pack {
+            manifest,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> crate::error::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> crate::error::Result<Self> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    /// Whether `data` starts like a Sims3Pack.
+    pub fn is_sims3pack(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    fn from_bytes(mut data: Vec<u8>) -> crate::error::Result<Self> {
+        let header = Header::read(&mut io::Cursor::new(&data))?;
+        let manifest_end = HEADER_SIZE + header.manifest_len as u64;
+        if manifest_end > data.len() as u64 {
+            return Err(Error::BadManifest {
+                offset: HEADER_SIZE,
+                message: format!(
+                    "manifest of {} bytes doesn't fit in the file ({} bytes)",
+                    header.manifest_len,
+                    data.len()
+                ),
+            });
+        }
+        let bad_manifest = |message| Error::BadManifest {
+            offset: HEADER_SIZE,
+            message,
+        };
+        let text = std::str::from_utf8(&data[HEADER_SIZE as usize..manifest_end as usize])
+            .map_err(|e| bad_manifest(e.to_string()))?;
+        let manifest = Manifest::parse(text).map_err(bad_manifest)?;
+        data.drain(..manifest_end as usize);
+        Ok(Sims3Pack { manifest, data })
+    }
+
+    /// The contents of `file`, which should be one of this Sims3Pack's.
+    pub fn file_data(&self, file: &PackagedFile) -> crate::error::Result<&[u8]> {
+        file.offset
+            .checked_add(file.length)
+            .and_then(|end| self.data.get(file.offset as usize..end as usize))
+            .ok_or_else(|| Error::ChunkOutOfBounds {
+                tgi: None,
+                offset: file.offset,
+                size: file.length,
+                file_size: self.data.len() as u64,
+            })
+    }
+
+    /// The packages in this Sims3Pack.
+    pub fn packages(&self) -> impl Iterator<Item = &PackagedFile> {
+        self.manifest.files.iter().filter(|f| f.kind() == FileKind::Package)
+    }
+
+    /// Read one of the packages in this Sims3Pack.
+    pub fn open_package<'a, 'brand>(
+        &'a self,
+        file: &PackagedFile,
+        guard: generativity::Guard<'brand>,
+    ) -> crate::error::Result<(
+        DBPFReader<'brand, io::Cursor<&'a [u8]>>,
+        DBPF<'brand, DBPFReader<'brand, io::Cursor<&'a [u8]>>>,
+    )> {
+        DBPFReader::parse(io::Cursor::new(self.file_data(file)?), guard)
+    }
+
+    /// Add a file at the end, and list it in the manifest, with a hash of the contents as its `Guid`.
+    pub fn add_file(&mut self, name: impl Into<String>, content_type: impl Into<String>, data: &[u8]) {
+        self.manifest.files.push(PackagedFile {
+            name: name.into(),
+            length: data.len() as u64,
+            offset: self.data.len() as u64,
+            crc: None,
+            guid: format!("0x{:016x}", crate::hash::fnv1a_64_bytes(data)),
+            content_type: content_type.into(),
+            ep_flags: "0x00000000".into(),
+            metatags: Vec::new(),
+            other: Vec::new(),
+        });
+        self.data.extend_from_slice(data);
+    }
+
+    pub fn write_to<W: io::Write + io::Seek>(&self, writer: &mut W) -> crate::error::Result<()> {
+        let manifest = self.manifest.to_xml();
+        let manifest_len = manifest.len().try_into().map_err(|_| Error::Overflow {
+            tgi: None,
+            what: "Sims3Pack manifest",
+            size: manifest.len() as u64,
+        })?;
+        Header {
+            version: 0x0101,
+            manifest_len,
+        }
+        .write(writer)?;
+        writer.write_all(manifest.as_bytes())?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbpf::{ChunkHandle, DBPFIndexEntry};
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Sims3Package Type="casPart" SubType="0x00000000" Creator="someone">
+  <ArchiveVersion>1.4</ArchiveVersion>
+  <DisplayName>Hair &amp; Hat</DisplayName>
+  <PackageId>0x0123456789abcdef0123456789abcdef</PackageId>
+  <DependencyList>
+    <PackageId>0x050d8e9a9d64b0a5c6fe1a8c6c59b6c6</PackageId>
+  </DependencyList>
+  <LocalizedNames>
+    <LocalizedName Language="en-US">Hair</LocalizedName>
+  </LocalizedNames>
+  <Thumbnails><Thumbnail Size="small">thumb.png</Thumbnail></Thumbnails>
+  <PackagedFile>
+    <Name>hair.package</Name>
+    <Length>4</Length>
+    <Offset>0</Offset>
+    <Crc>12345</Crc>
+    <Guid>0x0000000000000001</Guid>
+    <ContentType>casPart</ContentType>
+    <EPFlags>0x00000000</EPFlags>
+    <metatags>
+      <numOfThumbs>1</numOfThumbs>
+    </metatags>
+    <Rating Level="2">teen</Rating>
+  </PackagedFile>
+</Sims3Package>
+"#;
+
+    #[test]
+    fn manifest_round_trip() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.display_name, "Hair & Hat");
+        assert_eq!(manifest.attributes, [("Creator".to_string(), "someone".to_string())]);
+        assert_eq!(manifest.other, [r#"<Thumbnails><Thumbnail Size="small">thumb.png</Thumbnail></Thumbnails>"#]);
+        let file = &manifest.files[0];
+        assert_eq!((file.length, file.crc.as_deref()), (4, Some("12345")));
+        assert_eq!(file.metatags, [("numOfThumbs".to_string(), "1".to_string())]);
+        assert_eq!(file.other, [r#"<Rating Level="2">teen</Rating>"#]);
+
+        assert_eq!(Manifest::parse(&manifest.to_xml()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn added_packages_can_be_opened() {
+        let mut package = DBPF::new();
+        package.entries.push(DBPFIndexEntry {
+            resource_type: 0x0333406C,
+            resource_group: 0,
+            instance: 1,
+            unk1: false,
+            unk2: 1,
+            chunk: ChunkHandle::Dirty {
+                decompressed: b"data".to_vec(),
+                should_compress: false,
+            },
+        });
+        let mut bytes = io::Cursor::new(Vec::new());
+        package.write_to(&mut bytes, ()).unwrap();
+
+        let mut pack = Sims3Pack::new(Manifest::new("casPart", "Hair"));
+        pack.add_file("thumb.png", "thumbnail", b"\x89PNG");
+        pack.add_file("hair.package", "casPart", bytes.get_ref());
+        let mut written = io::Cursor::new(Vec::new());
+        pack.write_to(&mut written).unwrap();
+
+        let read = Sims3Pack::read(&mut written.get_ref().as_slice()).unwrap();
+        assert_eq!(read.manifest, pack.manifest);
+        assert_eq!(read.file_data(&read.manifest.files[0]).unwrap(), b"\x89PNG");
+        let file = read.packages().next().unwrap();
+        assert_eq!(file.offset, 4);
+        generativity::make_guard!(guard);
+        let (mut reader, opened) = read.open_package(file, guard).unwrap();
+        assert_eq!(opened.entries.len(), 1);
+        let mut data = Vec::new();
+        opened.entries[0].chunk.get_reader(&mut reader).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"data");
+    }
+}
@@ -6,10 +6,27 @@ pub(crate) struct LengthString {
     #[br(temp)]
     #[bw(try_calc = inner.len().try_into())]
     len: u32,
-    #[br(count = len)]
+    #[br(parse_with = read_bytes, args(len as u64))]
     pub inner: Vec<u8>
 }
 
+/// Read `len` bytes. Unlike `#[br(count)]`, this doesn't allocate them all up front,
+/// so a bad length can't allocate more than the data that's actually there.
+#[binrw::parser(reader)]
+pub(crate) fn read_bytes(len: u64) -> BinResult<Vec<u8>> {
+    use io::Read;
+    let pos = reader.stream_position()?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(binrw::Error::AssertFail {
+            pos,
+            message: format!("{} bytes of data run past the end", len),
+        });
+    }
+    Ok(bytes)
+}
+
 pub(crate) fn write_btreemap<'args, K, V, Args, W: io::Write + io::Seek>(
     collection: &BTreeMap<K, V>,
     writer: &mut W,
@@ -25,4 +42,79 @@ where
         BinWrite::write_options(&item, writer, endian, args.clone())?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+pub(crate) fn to_bytes<T>(value: &T) -> BinResult<Vec<u8>>
+where
+    T: for<'a> BinWrite<Args<'a> = ()>,
+{
+    let mut out = io::Cursor::new(Vec::new());
+    value.write_le(&mut out)?;
+    Ok(out.into_inner())
+}
+
+// .NET's BinaryReader.ReadString: a byte length as a 7-bit varint, then the encoded string.
+fn read_7bit_bytes<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Vec<u8>> {
+    use io::Read;
+    let pos = reader.stream_position()?;
+    let mut len = 0usize;
+    for shift in (0..35).step_by(7) {
+        let mut b = [0u8];
+        reader.read_exact(&mut b)?;
+        len |= ((b[0] & 0x7F) as usize) << shift;
+        if b[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(binrw::Error::AssertFail {
+            pos,
+            message: "string runs past the end of the data".to_string(),
+        });
+    }
+    Ok(bytes)
+}
+
+fn write_7bit_bytes<W: io::Write + io::Seek>(writer: &mut W, bytes: &[u8]) -> BinResult<()> {
+    let mut len = bytes.len();
+    loop {
+        let b = (len & 0x7F) as u8;
+        len >>= 7;
+        if len == 0 {
+            writer.write_all(&[b])?;
+            break;
+        }
+        writer.write_all(&[b | 0x80])?;
+    }
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+/// A 7-bit length prefixed UTF-16BE string.
+#[binrw::parser(reader)]
+pub(crate) fn parse_7bit_utf16be() -> BinResult<String> {
+    let bytes = read_7bit_bytes(reader)?;
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+// binrw hands writers the field as it is
+#[allow(clippy::ptr_arg)]
+#[binrw::writer(writer)]
+pub(crate) fn write_7bit_utf16be(s: &String) -> BinResult<()> {
+    let bytes: Vec<u8> = s.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    write_7bit_bytes(writer, &bytes)
+}
+
+/// A 7-bit length prefixed UTF-8 string.
+#[binrw::parser(reader)]
+pub(crate) fn parse_7bit_utf8() -> BinResult<String> {
+    Ok(String::from_utf8_lossy(&read_7bit_bytes(reader)?).into_owned())
+}
+
+#[binrw::writer(writer)]
+pub(crate) fn write_7bit_utf8(s: &String) -> BinResult<()> {
+    write_7bit_bytes(writer, s.as_bytes())
+}